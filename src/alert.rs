@@ -1,7 +1,8 @@
 use tls_result::{TlsResult, TlsError, TlsErrorKind};
 use tls_item::TlsItem;
 
-// we treat every alert as fatal.
+// every alert is fatal during the handshake.
+// after that, warnings other than close_notify are tolerated.
 tls_enum!(u8, enum AlertLevel {
     warning(1),
     fatal(2)
@@ -34,7 +35,9 @@ tls_enum!(u8, #[derive(Show)] enum AlertDescription {
     internal_error(80),
     user_canceled(90),
     no_renegotiation(100),
-    unsupported_extension(110)
+    unsupported_extension(110),
+    // RFC 7301
+    no_application_protocol(120)
 
     // RFC 6066
     // certificate_unobtainable(111),
@@ -53,6 +56,14 @@ impl AlertDescription {
             TlsErrorKind::DecodeError => AlertDescription::decode_error,
             TlsErrorKind::DecryptError => AlertDescription::decrypt_error,
             TlsErrorKind::InternalError => AlertDescription::internal_error,
+            TlsErrorKind::HandshakeFailure => AlertDescription::handshake_failure,
+            TlsErrorKind::BadCertificate => AlertDescription::bad_certificate,
+            TlsErrorKind::UnsupportedCertificate => AlertDescription::unsupported_certificate,
+            TlsErrorKind::CertificateExpired => AlertDescription::certificate_expired,
+            TlsErrorKind::UnknownCa => AlertDescription::unknown_ca,
+            TlsErrorKind::InsufficientSecurity => AlertDescription::insufficient_security,
+            TlsErrorKind::NoApplicationProtocol => AlertDescription::no_application_protocol,
+            TlsErrorKind::UnsupportedExtension => AlertDescription::unsupported_extension,
 
             // FIXME: we probably can't even send alert?
             TlsErrorKind::IoFailure => AlertDescription::internal_error,
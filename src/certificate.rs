@@ -0,0 +1,738 @@
+// X.509 certificate (RFC 5280)
+// only the fields we need for server authentication are decoded.
+
+use std::str;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::{BadCertificate, DecodeError};
+use der::{self, DerReader};
+use signature::{PublicKey, SignatureAndHashAlgorithm, HashAlgorithm, SignatureAlgorithm};
+
+// 2.5.4.3
+static OID_COMMON_NAME: &'static [u8] = &[0x55, 0x04, 0x03];
+
+// 2.5.29.x
+static OID_KEY_USAGE: &'static [u8] = &[0x55, 0x1d, 0x0f];
+static OID_SUBJECT_ALT_NAME: &'static [u8] = &[0x55, 0x1d, 0x11];
+static OID_BASIC_CONSTRAINTS: &'static [u8] = &[0x55, 0x1d, 0x13];
+static OID_EXT_KEY_USAGE: &'static [u8] = &[0x55, 0x1d, 0x25];
+
+// 1.3.6.1.5.5.7.3.x
+pub static OID_SERVER_AUTH: &'static [u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+pub static OID_CLIENT_AUTH: &'static [u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02];
+// 2.5.29.37.0
+pub static OID_ANY_EXT_KEY_USAGE: &'static [u8] = &[0x55, 0x1d, 0x25, 0x00];
+
+// signature algorithms: 1.2.840.113549.1.1.x (RSA) and 1.2.840.10045.4.x (ECDSA)
+static OID_SHA1_WITH_RSA: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x05];
+static OID_SHA256_WITH_RSA: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+static OID_SHA384_WITH_RSA: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+static OID_SHA512_WITH_RSA: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+static OID_ECDSA_WITH_SHA1: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x01];
+static OID_ECDSA_WITH_SHA256: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+static OID_ECDSA_WITH_SHA384: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+static OID_ECDSA_WITH_SHA512: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+// 1.2.840.113549.1.1.10 and 1.2.840.113549.1.1.8
+static OID_RSASSA_PSS: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0a];
+static OID_MGF1: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x08];
+// hash functions: 2.16.840.1.101.3.4.2.x
+static OID_SHA256: &'static [u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+static OID_SHA384: &'static [u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+static OID_SHA512: &'static [u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+
+// KeyUsage bits. bit n corresponds to `(1 << n)`.
+pub const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 1 << 0;
+pub const KEY_USAGE_NON_REPUDIATION: u16 = 1 << 1;
+pub const KEY_USAGE_KEY_ENCIPHERMENT: u16 = 1 << 2;
+pub const KEY_USAGE_DATA_ENCIPHERMENT: u16 = 1 << 3;
+pub const KEY_USAGE_KEY_AGREEMENT: u16 = 1 << 4;
+pub const KEY_USAGE_KEY_CERT_SIGN: u16 = 1 << 5;
+pub const KEY_USAGE_CRL_SIGN: u16 = 1 << 6;
+pub const KEY_USAGE_ENCIPHER_ONLY: u16 = 1 << 7;
+pub const KEY_USAGE_DECIPHER_ONLY: u16 = 1 << 8;
+
+pub struct AttributeTypeAndValue {
+    pub oid: Vec<u8>,
+    // DirectoryString tag (UTF8String, PrintableString, ...)
+    pub tag: u8,
+    pub value: Vec<u8>,
+}
+
+pub struct Name {
+    // DER encoding. names are compared by this.
+    pub der: Vec<u8>,
+    pub attributes: Vec<AttributeTypeAndValue>,
+}
+
+impl Name {
+    pub fn common_name(&self) -> Option<&[u8]> {
+        // the most specific one is the last one
+        for attr in self.attributes.iter().rev() {
+            if &attr.oid[] == OID_COMMON_NAME {
+                return Some(&attr.value[]);
+            }
+        }
+        None
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.der == other.der
+    }
+}
+
+// seconds since 1970-01-01T00:00:00Z
+pub struct Validity {
+    pub not_before: i64,
+    pub not_after: i64,
+}
+
+pub struct BasicConstraints {
+    pub ca: bool,
+    pub path_len: Option<u32>,
+}
+
+pub enum GeneralName {
+    DnsName(String),
+    // 4 bytes for IPv4, 16 bytes for IPv6
+    IpAddress(Vec<u8>),
+    // tag of a name form we don't interpret
+    Other(u8),
+}
+
+pub struct Extensions {
+    pub basic_constraints: Option<BasicConstraints>,
+    pub key_usage: Option<u16>,
+    pub ext_key_usage: Option<Vec<Vec<u8>>>,
+    pub subject_alt_names: Option<Vec<GeneralName>>,
+    // OIDs of critical extensions we don't understand
+    pub unknown_critical: Vec<Vec<u8>>,
+}
+
+pub struct Certificate {
+    // DER encoding of tbsCertificate. the signature covers this.
+    pub tbs_certificate: Vec<u8>,
+    pub version: u8,
+    pub serial_number: Vec<u8>,
+    pub issuer: Name,
+    pub validity: Validity,
+    pub subject: Name,
+    pub public_key: PublicKey,
+    pub extensions: Extensions,
+    // None if the algorithm is unknown
+    pub signature_algorithm: Option<SignatureAndHashAlgorithm>,
+    pub signature: Vec<u8>,
+}
+
+impl Certificate {
+    pub fn parse(data: &[u8]) -> TlsResult<Certificate> {
+        match parse_certificate(data) {
+            Ok(cert) => Ok(cert),
+            Err(ref err) if err.kind == DecodeError => {
+                tls_err!(BadCertificate, "failed to parse certificate: {}", err.desc)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+// Certificate ::= SEQUENCE {
+//     tbsCertificate TBSCertificate,
+//     signatureAlgorithm AlgorithmIdentifier,
+//     signatureValue BIT STRING
+// }
+fn parse_certificate(data: &[u8]) -> TlsResult<Certificate> {
+    let mut reader = DerReader::new(data);
+    let mut cert = try!(reader.read_sequence());
+    try!(reader.finish());
+
+    let (tag, tbs_contents, tbs_der) = try!(cert.read_raw());
+    if tag != der::TAG_SEQUENCE {
+        return tls_err!(DecodeError, "tbsCertificate is not a SEQUENCE");
+    }
+    let (_, _, outer_algorithm) = try!(cert.read_raw());
+    let signature = try!(cert.read_bit_string());
+    try!(cert.finish());
+
+    // TBSCertificate ::= SEQUENCE {
+    //     version [0] EXPLICIT Version DEFAULT v1,
+    //     serialNumber, signature, issuer, validity, subject, subjectPublicKeyInfo,
+    //     issuerUniqueID [1] IMPLICIT OPTIONAL,
+    //     subjectUniqueID [2] IMPLICIT OPTIONAL,
+    //     extensions [3] EXPLICIT Extensions OPTIONAL
+    // }
+    let mut tbs = DerReader::new(tbs_contents);
+
+    let version = match try!(tbs.read_optional(der::context_explicit(0))) {
+        None => 1,
+        Some(version) => {
+            let mut version = DerReader::new(version);
+            let v = try!(version.read_u32());
+            try!(version.finish());
+            if v > 2 {
+                return tls_err!(DecodeError, "unknown certificate version: {}", v);
+            }
+            (v + 1) as u8
+        }
+    };
+
+    // serial numbers can be up to 20 octets, and some CAs issue negative ones.
+    // we only need them for display, so keep the raw contents.
+    let serial_number = try!(tbs.read_tagged(der::TAG_INTEGER)).to_vec();
+
+    let (_, _, inner_algorithm) = try!(tbs.read_raw());
+    if inner_algorithm != outer_algorithm {
+        return tls_err!(DecodeError, "signature algorithms in certificate mismatch");
+    }
+    let signature_algorithm = try!(parse_signature_algorithm(outer_algorithm));
+
+    let issuer = try!(parse_name(&mut tbs));
+    let validity = try!(parse_validity(&mut tbs));
+    let subject = try!(parse_name(&mut tbs));
+
+    let (_, _, spki) = try!(tbs.read_raw());
+    let public_key = try!(PublicKey::from_spki(spki));
+
+    try!(tbs.read_optional(der::context_implicit(1)));
+    try!(tbs.read_optional(der::context_implicit(2)));
+
+    let extensions = match try!(tbs.read_optional(der::context_explicit(3))) {
+        None => Extensions {
+            basic_constraints: None,
+            key_usage: None,
+            ext_key_usage: None,
+            subject_alt_names: None,
+            unknown_critical: Vec::new(),
+        },
+        Some(extensions) => {
+            if version != 3 {
+                return tls_err!(DecodeError, "extensions found in v{} certificate", version);
+            }
+            try!(parse_extensions(extensions))
+        }
+    };
+    try!(tbs.finish());
+
+    Ok(Certificate {
+        tbs_certificate: tbs_der.to_vec(),
+        version: version,
+        serial_number: serial_number,
+        issuer: issuer,
+        validity: validity,
+        subject: subject,
+        public_key: public_key,
+        extensions: extensions,
+        signature_algorithm: signature_algorithm,
+        signature: signature.to_vec(),
+    })
+}
+
+fn parse_signature_algorithm(data: &[u8]) -> TlsResult<Option<SignatureAndHashAlgorithm>> {
+    let mut reader = DerReader::new(data);
+    let mut algorithm = try!(reader.read_sequence());
+    let oid = try!(algorithm.read_oid());
+
+    let rsa_algorithms = [
+        (OID_SHA1_WITH_RSA, HashAlgorithm::sha1),
+        (OID_SHA256_WITH_RSA, HashAlgorithm::sha256),
+        (OID_SHA384_WITH_RSA, HashAlgorithm::sha384),
+        (OID_SHA512_WITH_RSA, HashAlgorithm::sha512),
+    ];
+    for &(alg_oid, hash) in rsa_algorithms.iter() {
+        if oid == alg_oid {
+            // parameters MUST be NULL, but some encoders omit it
+            if !algorithm.is_empty() {
+                try!(algorithm.read_null());
+            }
+            try!(algorithm.finish());
+            return Ok(Some(SignatureAndHashAlgorithm {
+                hash: hash,
+                signature: SignatureAlgorithm::rsa,
+            }));
+        }
+    }
+
+    let ecdsa_algorithms = [
+        (OID_ECDSA_WITH_SHA1, HashAlgorithm::sha1),
+        (OID_ECDSA_WITH_SHA256, HashAlgorithm::sha256),
+        (OID_ECDSA_WITH_SHA384, HashAlgorithm::sha384),
+        (OID_ECDSA_WITH_SHA512, HashAlgorithm::sha512),
+    ];
+    for &(alg_oid, hash) in ecdsa_algorithms.iter() {
+        if oid == alg_oid {
+            // parameters MUST be absent
+            try!(algorithm.finish());
+            return Ok(Some(SignatureAndHashAlgorithm {
+                hash: hash,
+                signature: SignatureAlgorithm::ecdsa,
+            }));
+        }
+    }
+
+    if oid == OID_RSASSA_PSS {
+        let params = try!(algorithm.read_sequence());
+        try!(algorithm.finish());
+        return parse_pss_params(params);
+    }
+
+    Ok(None)
+}
+
+// RSASSA-PSS-params ::= SEQUENCE {
+//     hashAlgorithm [0] HashAlgorithm DEFAULT sha1,
+//     maskGenAlgorithm [1] MaskGenAlgorithm DEFAULT mgf1SHA1,
+//     saltLength [2] INTEGER DEFAULT 20,
+//     trailerField [3] TrailerField DEFAULT trailerFieldBC
+// }
+// we only support SHA-2 with MGF1 of the same hash and a salt as long as the digest,
+// i.e. what TLS calls rsa_pss_rsae_*. the SHA-1 defaults are not supported.
+fn parse_pss_params(mut params: DerReader) -> TlsResult<Option<SignatureAndHashAlgorithm>> {
+    let hash = try!(params.read_optional(der::context_explicit(0)));
+    let mgf = try!(params.read_optional(der::context_explicit(1)));
+    let salt_len = try!(params.read_optional(der::context_explicit(2)));
+    let trailer = try!(params.read_optional(der::context_explicit(3)));
+    try!(params.finish());
+
+    let (hash, mgf, salt_len) = match (hash, mgf, salt_len) {
+        (Some(hash), Some(mgf), Some(salt_len)) => (hash, mgf, salt_len),
+        _ => return Ok(None),
+    };
+
+    let mut hash = DerReader::new(hash);
+    let hash_oid = try!(read_hash_algorithm(&mut hash));
+    try!(hash.finish());
+
+    // MaskGenAlgorithm ::= SEQUENCE { id-mgf1, HashAlgorithm }
+    let mut mgf = DerReader::new(mgf);
+    let mut mgf_algorithm = try!(mgf.read_sequence());
+    try!(mgf.finish());
+    let mgf_oid = try!(mgf_algorithm.read_oid());
+    let mgf_hash_oid = try!(read_hash_algorithm(&mut mgf_algorithm));
+    try!(mgf_algorithm.finish());
+
+    let mut salt_len_reader = DerReader::new(salt_len);
+    let salt_len = try!(salt_len_reader.read_u32());
+    try!(salt_len_reader.finish());
+
+    let trailer = match trailer {
+        Some(trailer) => {
+            let mut reader = DerReader::new(trailer);
+            let trailer = try!(reader.read_u32());
+            try!(reader.finish());
+            trailer
+        }
+        None => 1,
+    };
+
+    let (signature, hash_len) = if hash_oid == OID_SHA256 {
+        (SignatureAlgorithm::rsa_pss_rsae_sha256, 32)
+    } else if hash_oid == OID_SHA384 {
+        (SignatureAlgorithm::rsa_pss_rsae_sha384, 48)
+    } else if hash_oid == OID_SHA512 {
+        (SignatureAlgorithm::rsa_pss_rsae_sha512, 64)
+    } else {
+        return Ok(None);
+    };
+    if mgf_oid != OID_MGF1 || mgf_hash_oid != hash_oid || salt_len != hash_len || trailer != 1 {
+        return Ok(None);
+    }
+
+    Ok(Some(SignatureAndHashAlgorithm {
+        hash: HashAlgorithm::intrinsic,
+        signature: signature,
+    }))
+}
+
+// AlgorithmIdentifier of a hash function. return its OID.
+fn read_hash_algorithm<'a>(reader: &mut DerReader<'a>) -> TlsResult<&'a [u8]> {
+    let mut algorithm = try!(reader.read_sequence());
+    let oid = try!(algorithm.read_oid());
+    // parameters are NULL or absent
+    if !algorithm.is_empty() {
+        try!(algorithm.read_null());
+    }
+    try!(algorithm.finish());
+    Ok(oid)
+}
+
+// Name ::= SEQUENCE OF RelativeDistinguishedName
+// RelativeDistinguishedName ::= SET OF AttributeTypeAndValue
+// AttributeTypeAndValue ::= SEQUENCE { type OID, value ANY }
+fn parse_name(reader: &mut DerReader) -> TlsResult<Name> {
+    let (tag, contents, whole) = try!(reader.read_raw());
+    if tag != der::TAG_SEQUENCE {
+        return tls_err!(DecodeError, "Name is not a SEQUENCE");
+    }
+
+    let mut attributes = Vec::new();
+    let mut rdns = DerReader::new(contents);
+    while !rdns.is_empty() {
+        let rdn = try!(rdns.read_tagged(der::TAG_SET));
+        let mut rdn = DerReader::new(rdn);
+        while !rdn.is_empty() {
+            let mut attr = try!(rdn.read_sequence());
+            let oid = try!(attr.read_oid());
+            let (tag, value, _) = try!(attr.read_raw());
+            try!(attr.finish());
+
+            attributes.push(AttributeTypeAndValue {
+                oid: oid.to_vec(),
+                tag: tag,
+                value: value.to_vec(),
+            });
+        }
+    }
+
+    Ok(Name {
+        der: whole.to_vec(),
+        attributes: attributes,
+    })
+}
+
+// Validity ::= SEQUENCE { notBefore Time, notAfter Time }
+fn parse_validity(reader: &mut DerReader) -> TlsResult<Validity> {
+    let mut validity = try!(reader.read_sequence());
+    let not_before = try!(parse_time(&mut validity));
+    let not_after = try!(parse_time(&mut validity));
+    try!(validity.finish());
+
+    Ok(Validity {
+        not_before: not_before,
+        not_after: not_after,
+    })
+}
+
+// Time ::= CHOICE { utcTime UTCTime, generalTime GeneralizedTime }
+// RFC 5280 requires "YYMMDDHHMMSSZ" and "YYYYMMDDHHMMSSZ" respectively.
+fn parse_time(reader: &mut DerReader) -> TlsResult<i64> {
+    let (tag, value, _) = try!(reader.read_raw());
+
+    let (year, rest) = match tag {
+        der::TAG_UTC_TIME if value.len() == 13 => {
+            let yy = try!(parse_digits(&value[..2]));
+            // 1950 <= year < 2050
+            let year = if yy >= 50 { 1900 + yy } else { 2000 + yy };
+            (year, &value[2..])
+        }
+        der::TAG_GENERALIZED_TIME if value.len() == 15 => {
+            let year = try!(parse_digits(&value[..4]));
+            (year, &value[4..])
+        }
+        _ => return tls_err!(DecodeError, "unexpected Time format"),
+    };
+
+    if rest[10] != b'Z' {
+        return tls_err!(DecodeError, "Time is not in UTC");
+    }
+    let month = try!(parse_digits(&rest[0..2]));
+    let day = try!(parse_digits(&rest[2..4]));
+    let hour = try!(parse_digits(&rest[4..6]));
+    let minute = try!(parse_digits(&rest[6..8]));
+    let second = try!(parse_digits(&rest[8..10]));
+
+    if month < 1 || month > 12 || day < 1 || day > 31 ||
+       hour > 23 || minute > 59 || second > 59 {
+        return tls_err!(DecodeError, "invalid Time");
+    }
+
+    let days = days_from_civil(year, month, day);
+    Ok(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+fn parse_digits(digits: &[u8]) -> TlsResult<i64> {
+    let mut n = 0i64;
+    for &d in digits.iter() {
+        if d < b'0' || d > b'9' {
+            return tls_err!(DecodeError, "unexpected character in Time");
+        }
+        n = n * 10 + ((d - b'0') as i64);
+    }
+    Ok(n)
+}
+
+// number of days since 1970-01-01 in proleptic Gregorian calendar
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Extensions ::= SEQUENCE SIZE (1..MAX) OF Extension
+// Extension ::= SEQUENCE {
+//     extnID OID,
+//     critical BOOLEAN DEFAULT FALSE,
+//     extnValue OCTET STRING
+// }
+fn parse_extensions(data: &[u8]) -> TlsResult<Extensions> {
+    let mut ret = Extensions {
+        basic_constraints: None,
+        key_usage: None,
+        ext_key_usage: None,
+        subject_alt_names: None,
+        unknown_critical: Vec::new(),
+    };
+    let mut seen: Vec<Vec<u8>> = Vec::new();
+
+    let mut reader = DerReader::new(data);
+    let mut extensions = try!(reader.read_sequence());
+    try!(reader.finish());
+
+    while !extensions.is_empty() {
+        let mut extension = try!(extensions.read_sequence());
+        let oid = try!(extension.read_oid());
+        let critical = match extension.peek_tag() {
+            Some(der::TAG_BOOLEAN) => try!(extension.read_boolean()),
+            _ => false,
+        };
+        let value = try!(extension.read_tagged(der::TAG_OCTET_STRING));
+        try!(extension.finish());
+
+        // "A certificate MUST NOT include more than one instance of a particular extension."
+        if seen.iter().any(|seen_oid| &seen_oid[] == oid) {
+            return tls_err!(DecodeError, "duplicate certificate extension");
+        }
+        seen.push(oid.to_vec());
+
+        if oid == OID_BASIC_CONSTRAINTS {
+            ret.basic_constraints = Some(try!(parse_basic_constraints(value)));
+        } else if oid == OID_KEY_USAGE {
+            ret.key_usage = Some(try!(parse_key_usage(value)));
+        } else if oid == OID_EXT_KEY_USAGE {
+            ret.ext_key_usage = Some(try!(parse_ext_key_usage(value)));
+        } else if oid == OID_SUBJECT_ALT_NAME {
+            ret.subject_alt_names = Some(try!(parse_general_names(value)));
+        } else if critical {
+            ret.unknown_critical.push(oid.to_vec());
+        }
+    }
+
+    Ok(ret)
+}
+
+// BasicConstraints ::= SEQUENCE {
+//     cA BOOLEAN DEFAULT FALSE,
+//     pathLenConstraint INTEGER (0..MAX) OPTIONAL
+// }
+fn parse_basic_constraints(data: &[u8]) -> TlsResult<BasicConstraints> {
+    let mut reader = DerReader::new(data);
+    let mut seq = try!(reader.read_sequence());
+    try!(reader.finish());
+
+    let ca = match seq.peek_tag() {
+        Some(der::TAG_BOOLEAN) => try!(seq.read_boolean()),
+        _ => false,
+    };
+    let path_len = match seq.peek_tag() {
+        Some(der::TAG_INTEGER) => Some(try!(seq.read_u32())),
+        _ => None,
+    };
+    try!(seq.finish());
+
+    Ok(BasicConstraints {
+        ca: ca,
+        path_len: path_len,
+    })
+}
+
+// KeyUsage ::= BIT STRING
+fn parse_key_usage(data: &[u8]) -> TlsResult<u16> {
+    let mut reader = DerReader::new(data);
+    let (_unused_bits, bits) = try!(reader.read_named_bits());
+    try!(reader.finish());
+
+    if bits.len() > 2 {
+        return tls_err!(DecodeError, "KeyUsage too long");
+    }
+
+    // the first bit (digitalSignature) is the MSB of the first octet
+    let mut usage = 0u16;
+    for (i, &b) in bits.iter().enumerate() {
+        for j in (0us..8) {
+            if (b >> (7 - j)) & 1 == 1 {
+                usage |= 1 << (i * 8 + j);
+            }
+        }
+    }
+    Ok(usage)
+}
+
+// ExtKeyUsageSyntax ::= SEQUENCE SIZE (1..MAX) OF KeyPurposeId
+fn parse_ext_key_usage(data: &[u8]) -> TlsResult<Vec<Vec<u8>>> {
+    let mut reader = DerReader::new(data);
+    let mut seq = try!(reader.read_sequence());
+    try!(reader.finish());
+
+    let mut purposes = Vec::new();
+    while !seq.is_empty() {
+        purposes.push(try!(seq.read_oid()).to_vec());
+    }
+    if purposes.len() == 0 {
+        return tls_err!(DecodeError, "empty ExtKeyUsage");
+    }
+    Ok(purposes)
+}
+
+// GeneralNames ::= SEQUENCE SIZE (1..MAX) OF GeneralName
+fn parse_general_names(data: &[u8]) -> TlsResult<Vec<GeneralName>> {
+    let mut reader = DerReader::new(data);
+    let mut seq = try!(reader.read_sequence());
+    try!(reader.finish());
+
+    let mut names = Vec::new();
+    while !seq.is_empty() {
+        let (tag, value, _) = try!(seq.read_raw());
+        let name = if tag == der::context_implicit(2) {
+            // dNSName [2] IA5String
+            if !value.iter().all(|&c| c > 0 && c < 0x80) {
+                return tls_err!(DecodeError, "non-ASCII character in dNSName");
+            }
+            let name = match str::from_utf8(value) {
+                Ok(name) => name.to_string(),
+                Err(..) => return tls_err!(DecodeError, "invalid dNSName"),
+            };
+            GeneralName::DnsName(name)
+        } else if tag == der::context_implicit(7) {
+            // iPAddress [7] OCTET STRING
+            if value.len() != 4 && value.len() != 16 {
+                return tls_err!(DecodeError, "invalid iPAddress length: {}", value.len());
+            }
+            GeneralName::IpAddress(value.to_vec())
+        } else {
+            GeneralName::Other(tag)
+        };
+        names.push(name);
+    }
+    if names.len() == 0 {
+        return tls_err!(DecodeError, "empty GeneralNames");
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod test {
+    use signature::{PublicKey, HashAlgorithm, SignatureAlgorithm};
+    use super::{Certificate, GeneralName, OID_SERVER_AUTH, OID_CLIENT_AUTH,
+                KEY_USAGE_DIGITAL_SIGNATURE, KEY_USAGE_KEY_CERT_SIGN, KEY_USAGE_CRL_SIGN};
+    use super::parse_signature_algorithm;
+
+    static ROOT: &'static [u8] = include_bytes!("test_data/root.der");
+    static LEAF_EC: &'static [u8] = include_bytes!("test_data/leaf_ec.der");
+
+    #[test]
+    fn test_parse_leaf() {
+        let cert = Certificate::parse(LEAF_EC).unwrap();
+
+        assert_eq!(cert.version, 3);
+        assert_eq!(cert.serial_number, vec!(0x12, 0x34, 0x56, 0x78, 0x90));
+        assert_eq!(cert.subject.common_name(), Some(&b"suruga.example"[]));
+        assert_eq!(cert.issuer.common_name(), Some(&b"suruga intermediate"[]));
+        assert!(cert.issuer != cert.subject);
+
+        // 2015-01-01T00:00:00Z .. 2045-01-01T00:00:00Z
+        assert_eq!(cert.validity.not_before, 1420070400);
+        assert_eq!(cert.validity.not_after, 2366841600);
+
+        match cert.public_key {
+            PublicKey::EcP256(..) => {}
+            _ => panic!("unexpected public key"),
+        }
+
+        let alg = cert.signature_algorithm.unwrap();
+        assert_eq!(alg.hash, HashAlgorithm::sha256);
+        assert_eq!(alg.signature, SignatureAlgorithm::ecdsa);
+
+        let ext = &cert.extensions;
+        let bc = ext.basic_constraints.as_ref().unwrap();
+        assert!(!bc.ca);
+        assert_eq!(bc.path_len, None);
+        assert_eq!(ext.key_usage, Some(KEY_USAGE_DIGITAL_SIGNATURE));
+
+        let eku = ext.ext_key_usage.as_ref().unwrap();
+        assert_eq!(eku.len(), 2);
+        assert_eq!(&eku[0][], OID_SERVER_AUTH);
+        assert_eq!(&eku[1][], OID_CLIENT_AUTH);
+
+        let names = ext.subject_alt_names.as_ref().unwrap();
+        assert_eq!(names.len(), 3);
+        match names[0] {
+            GeneralName::DnsName(ref name) => assert_eq!(&name[], "suruga.example"),
+            _ => panic!(),
+        }
+        match names[1] {
+            GeneralName::DnsName(ref name) => assert_eq!(&name[], "*.suruga.example"),
+            _ => panic!(),
+        }
+        match names[2] {
+            GeneralName::IpAddress(ref ip) => assert_eq!(&ip[], &[127u8, 0, 0, 1][]),
+            _ => panic!(),
+        }
+        assert_eq!(ext.unknown_critical.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_root() {
+        let cert = Certificate::parse(ROOT).unwrap();
+
+        assert_eq!(cert.serial_number, vec!(1));
+        assert!(cert.issuer == cert.subject);
+
+        match cert.public_key {
+            PublicKey::Rsa(ref key) => assert_eq!(key.len(), 256),
+            _ => panic!("unexpected public key"),
+        }
+
+        let alg = cert.signature_algorithm.unwrap();
+        assert_eq!(alg.hash, HashAlgorithm::sha256);
+        assert_eq!(alg.signature, SignatureAlgorithm::rsa);
+
+        let bc = cert.extensions.basic_constraints.as_ref().unwrap();
+        assert!(bc.ca);
+        assert_eq!(bc.path_len, None);
+        assert_eq!(cert.extensions.key_usage,
+                   Some(KEY_USAGE_KEY_CERT_SIGN | KEY_USAGE_CRL_SIGN));
+        assert!(cert.extensions.subject_alt_names.is_none());
+    }
+
+    #[test]
+    fn test_parse_broken() {
+        assert!(Certificate::parse(&LEAF_EC[..LEAF_EC.len() - 1]).is_err());
+
+        let mut cert = LEAF_EC.to_vec();
+        cert.push(0);
+        assert!(Certificate::parse(&cert[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_pss_algorithm() {
+        // RSASSA-PSS with SHA-256, MGF1 with SHA-256 and 32-byte salt
+        let mut pss = b"\x30\x41\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0a\
+                        \x30\x34\
+                        \xa0\x0f\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\x00\
+                        \xa1\x1c\x30\x1a\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x08\
+                        \x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\x00\
+                        \xa2\x03\x02\x01\x20".to_vec();
+        let alg = parse_signature_algorithm(&pss[]).unwrap().unwrap();
+        assert_eq!(alg.hash, HashAlgorithm::intrinsic);
+        assert_eq!(alg.signature, SignatureAlgorithm::rsa_pss_rsae_sha256);
+
+        // 20-byte salt
+        let last = pss.len() - 1;
+        pss[last] = 0x14;
+        assert!(parse_signature_algorithm(&pss[]).unwrap().is_none());
+
+        // MGF1 with SHA-384
+        pss[last] = 0x20;
+        pss[59] = 0x02;
+        assert!(parse_signature_algorithm(&pss[]).unwrap().is_none());
+
+        // all defaults (SHA-1)
+        let pss = b"\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0a\x30\x00";
+        assert!(parse_signature_algorithm(pss).unwrap().is_none());
+    }
+}
@@ -0,0 +1,126 @@
+// Implements AES-GCM cipher suites (RFC 5288) with 128-bit and 256-bit keys.
+// nonce = salt (4 bytes from the key block) || explicit nonce (8 bytes).
+// the explicit nonce is sent in front of each record. we use the sequence number,
+// which never repeats for a key.
+
+use crypto::gcm::{self, Gcm};
+use util::crypto_compare;
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::BadRecordMac;
+use super::{Encryptor, Decryptor, Aead};
+
+const FIXED_IV_LEN: usize = 4;
+const EXPLICIT_NONCE_LEN: usize = 8;
+const MAC_LEN: usize = gcm::TAG_LEN;
+
+fn nonce(salt: &[u8], explicit: &[u8]) -> Vec<u8> {
+    let mut nonce = salt.to_vec();
+    nonce.push_all(explicit);
+    debug_assert_eq!(nonce.len(), gcm::IV_LEN);
+    nonce
+}
+
+struct AesGcmEncryptor {
+    gcm: Gcm,
+    // SECRET
+    salt: Vec<u8>,
+}
+
+impl Encryptor for AesGcmEncryptor {
+    fn encrypt(&mut self, seq_num: &[u8], data: &[u8], ad: &[u8]) -> Vec<u8> {
+        let nonce = nonce(&self.salt[], seq_num);
+
+        let encrypted = self.gcm.ctr(&nonce[], data);
+        let mac = self.gcm.tag(&nonce[], ad, &encrypted[]);
+
+        let mut ret = seq_num.to_vec();
+        ret.push_all(&encrypted[]);
+        ret.push_all(&mac[]);
+        ret
+    }
+}
+
+struct AesGcmDecryptor {
+    gcm: Gcm,
+    // SECRET
+    salt: Vec<u8>,
+}
+
+impl Decryptor for AesGcmDecryptor {
+    fn decrypt(&mut self, _seq_num: &[u8], data: &[u8], ad: &[u8]) -> TlsResult<Vec<u8>> {
+        let enc_len = data.len();
+        if enc_len < EXPLICIT_NONCE_LEN + MAC_LEN {
+            return tls_err!(BadRecordMac, "message too short");
+        }
+
+        let nonce = nonce(&self.salt[], &data[..EXPLICIT_NONCE_LEN]);
+        let encrypted = &data[EXPLICIT_NONCE_LEN..(enc_len - MAC_LEN)];
+        let mac_expected = &data[(enc_len - MAC_LEN)..];
+
+        let mac_computed = self.gcm.tag(&nonce[], ad, encrypted);
+
+        // SECRET
+        // even if `mac_computed != mac_expected`, decrypt the data to prevent timing attack.
+        let plain = self.gcm.ctr(&nonce[], encrypted);
+
+        if !crypto_compare(&mac_computed[], mac_expected) {
+            tls_err!(BadRecordMac, "wrong mac")
+        } else {
+            Ok(plain)
+        }
+    }
+
+    #[inline(always)]
+    fn mac_len(&self) -> usize {
+        MAC_LEN
+    }
+
+    #[inline(always)]
+    fn explicit_nonce_len(&self) -> usize {
+        EXPLICIT_NONCE_LEN
+    }
+}
+
+macro_rules! aes_gcm_aead {
+    ($name:ident, $key_len:expr) => (
+        pub struct $name;
+
+        impl Aead for $name {
+            #[inline(always)]
+            fn key_size(&self) -> usize {
+                $key_len
+            }
+
+            #[inline(always)]
+            fn fixed_iv_len(&self) -> usize {
+                FIXED_IV_LEN
+            }
+
+            #[inline(always)]
+            fn mac_len(&self) -> usize {
+                MAC_LEN
+            }
+
+            #[inline(always)]
+            fn new_encryptor(&self, key: Vec<u8>, fixed_iv: Vec<u8>) -> Box<Encryptor + 'static> {
+                let encryptor = AesGcmEncryptor {
+                    gcm: Gcm::new(&key[]),
+                    salt: fixed_iv,
+                };
+                Box::new(encryptor) as Box<Encryptor>
+            }
+
+            #[inline(always)]
+            fn new_decryptor(&self, key: Vec<u8>, fixed_iv: Vec<u8>) -> Box<Decryptor + 'static> {
+                let decryptor = AesGcmDecryptor {
+                    gcm: Gcm::new(&key[]),
+                    salt: fixed_iv,
+                };
+                Box::new(decryptor) as Box<Decryptor>
+            }
+        }
+    )
+}
+
+aes_gcm_aead!(Aes128Gcm, 128 / 8);
+aes_gcm_aead!(Aes256Gcm, 256 / 8);
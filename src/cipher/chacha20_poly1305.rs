@@ -1,9 +1,11 @@
 // Implements AEAD_CHACHA20_POLY1305
-// unfortunately, there is no concrete standard yet. some drafts exist:
+// `ChaCha20Poly1305` is the pre-standard construction. some drafts exist:
 // http://tools.ietf.org/html/draft-agl-tls-chacha20poly1305-01
 // http://tools.ietf.org/html/draft-agl-tls-chacha20poly1305-04
 // they differ in detail, so here we follow google/boringssl implementation.
 // openssl 1.0.2-aead branch seems to implement draft 01.
+//
+// `ChaCha20Poly1305Ietf` is the standard one (RFC 7539, RFC 7905).
 
 use crypto::chacha20::ChaCha20;
 use crypto::poly1305;
@@ -15,6 +17,7 @@ use super::{Encryptor, Decryptor, Aead};
 const KEY_LEN: usize = 256 / 8;
 const EXPLICIT_IV_LEN: usize = 0;
 const MAC_LEN: usize = 16;
+const IETF_FIXED_IV_LEN: usize = 12;
 
 fn compute_mac(poly_key: &[u8], encrypted: &[u8], ad: &[u8]) -> [u8; MAC_LEN] {
     let mut msg = Vec::new();
@@ -29,6 +32,30 @@ fn compute_mac(poly_key: &[u8], encrypted: &[u8], ad: &[u8]) -> [u8; MAC_LEN] {
     push_all_with_len(&mut msg, ad);
     push_all_with_len(&mut msg, encrypted);
 
+    poly1305_authenticate(poly_key, &msg[])
+}
+
+// RFC 7539 2.8: ad and data are padded to 16 bytes, then both lengths follow.
+fn compute_mac_ietf(poly_key: &[u8], encrypted: &[u8], ad: &[u8]) -> [u8; MAC_LEN] {
+    let mut msg = Vec::new();
+
+    fn push_all_padded(vec: &mut Vec<u8>, data: &[u8]) {
+        vec.push_all(data);
+        let pad = (16 - data.len() % 16) % 16;
+        for _ in (0us..pad) {
+            vec.push(0);
+        }
+    }
+
+    push_all_padded(&mut msg, ad);
+    push_all_padded(&mut msg, encrypted);
+    msg.push_all(&u64_le_array(ad.len() as u64)[]);
+    msg.push_all(&u64_le_array(encrypted.len() as u64)[]);
+
+    poly1305_authenticate(poly_key, &msg[])
+}
+
+fn poly1305_authenticate(poly_key: &[u8], msg: &[u8]) -> [u8; MAC_LEN] {
     let mut r = [0u8; MAC_LEN];
     for i in (0us..MAC_LEN) {
         r[i] = poly_key[i];
@@ -38,20 +65,48 @@ fn compute_mac(poly_key: &[u8], encrypted: &[u8], ad: &[u8]) -> [u8; MAC_LEN] {
         k[i] = poly_key[MAC_LEN + i];
     }
 
-    poly1305::authenticate(&msg[], &r, &k)
+    poly1305::authenticate(msg, &r, &k)
+}
+
+// `fixed_iv` is None for the draft construction, where the sequence number is the nonce.
+// otherwise the nonce is `fixed_iv` XOR the left-padded sequence number (RFC 7905 2).
+// return the cipher and the poly1305 key, which is taken from the first block.
+fn new_chacha20(key: &[u8], fixed_iv: &Option<Vec<u8>>, seq_num: &[u8]) -> (ChaCha20, [u8; 64]) {
+    let mut chacha20 = match *fixed_iv {
+        None => ChaCha20::new(key, seq_num),
+        Some(ref fixed_iv) => {
+            let mut nonce = fixed_iv.clone();
+            for i in (0us..seq_num.len()) {
+                nonce[IETF_FIXED_IV_LEN - seq_num.len() + i] ^= seq_num[i];
+            }
+            ChaCha20::new_ietf(key, &nonce[], 0)
+        }
+    };
+    let poly1305_key = chacha20.next();
+    (chacha20, poly1305_key)
+}
+
+fn authenticate(fixed_iv: &Option<Vec<u8>>,
+                poly_key: &[u8],
+                encrypted: &[u8],
+                ad: &[u8]) -> [u8; MAC_LEN] {
+    match *fixed_iv {
+        None => compute_mac(poly_key, encrypted, ad),
+        Some(..) => compute_mac_ietf(poly_key, encrypted, ad),
+    }
 }
 
 struct ChaCha20Poly1305Encryptor {
     key: Vec<u8>,
+    fixed_iv: Option<Vec<u8>>,
 }
 
 impl Encryptor for ChaCha20Poly1305Encryptor {
     fn encrypt(&mut self, nonce: &[u8], data: &[u8], ad: &[u8]) -> Vec<u8> {
-        let mut chacha20 = ChaCha20::new(&self.key[], nonce);
-        let poly1305_key = chacha20.next();
+        let (mut chacha20, poly1305_key) = new_chacha20(&self.key[], &self.fixed_iv, nonce);
 
         let mut encrypted = chacha20.encrypt(data);
-        let mac = compute_mac(&poly1305_key[], &encrypted[], ad);
+        let mac = authenticate(&self.fixed_iv, &poly1305_key[], &encrypted[], ad);
         encrypted.push_all(&mac[]);
 
         encrypted
@@ -60,6 +115,7 @@ impl Encryptor for ChaCha20Poly1305Encryptor {
 
 struct ChaCha20Poly1305Decryptor {
     key: Vec<u8>,
+    fixed_iv: Option<Vec<u8>>,
 }
 
 impl Decryptor for ChaCha20Poly1305Decryptor {
@@ -72,10 +128,9 @@ impl Decryptor for ChaCha20Poly1305Decryptor {
         let encrypted = &data[..(enc_len - MAC_LEN)];
         let mac_expected = &data[(enc_len - MAC_LEN)..];
 
-        let mut chacha20 = ChaCha20::new(&self.key[], nonce);
-        let poly1305_key = chacha20.next();
+        let (mut chacha20, poly1305_key) = new_chacha20(&self.key[], &self.fixed_iv, nonce);
 
-        let mac_computed = compute_mac(&poly1305_key[], &encrypted[], ad);
+        let mac_computed = authenticate(&self.fixed_iv, &poly1305_key[], &encrypted[], ad);
 
         // SECRET
         // even if `mac_computed != mac_expected`, decrypt the data to prevent timing attack.
@@ -97,6 +152,11 @@ impl Decryptor for ChaCha20Poly1305Decryptor {
     fn mac_len(&self) -> usize {
         MAC_LEN
     }
+
+    #[inline(always)]
+    fn explicit_nonce_len(&self) -> usize {
+        EXPLICIT_IV_LEN
+    }
 }
 
 pub struct ChaCha20Poly1305;
@@ -118,18 +178,94 @@ impl Aead for ChaCha20Poly1305 {
     }
 
     #[inline(always)]
-    fn new_encryptor(&self, key: Vec<u8>) -> Box<Encryptor + 'static> {
+    fn new_encryptor(&self, key: Vec<u8>, _fixed_iv: Vec<u8>) -> Box<Encryptor + 'static> {
+        let encryptor = ChaCha20Poly1305Encryptor {
+            key: key,
+            fixed_iv: None,
+        };
+        Box::new(encryptor) as Box<Encryptor>
+    }
+
+    #[inline(always)]
+    fn new_decryptor(&self, key: Vec<u8>, _fixed_iv: Vec<u8>) -> Box<Decryptor + 'static> {
+        let decryptor = ChaCha20Poly1305Decryptor {
+            key: key,
+            fixed_iv: None,
+        };
+        Box::new(decryptor) as Box<Decryptor>
+    }
+}
+
+pub struct ChaCha20Poly1305Ietf;
+
+impl Aead for ChaCha20Poly1305Ietf {
+    #[inline(always)]
+    fn key_size(&self) -> usize {
+        KEY_LEN
+    }
+
+    #[inline(always)]
+    fn fixed_iv_len(&self) -> usize {
+        IETF_FIXED_IV_LEN
+    }
+
+    #[inline(always)]
+    fn mac_len(&self) -> usize {
+        MAC_LEN
+    }
+
+    #[inline(always)]
+    fn new_encryptor(&self, key: Vec<u8>, fixed_iv: Vec<u8>) -> Box<Encryptor + 'static> {
         let encryptor = ChaCha20Poly1305Encryptor {
             key: key,
+            fixed_iv: Some(fixed_iv),
         };
         Box::new(encryptor) as Box<Encryptor>
     }
 
     #[inline(always)]
-    fn new_decryptor(&self, key: Vec<u8>) -> Box<Decryptor + 'static> {
+    fn new_decryptor(&self, key: Vec<u8>, fixed_iv: Vec<u8>) -> Box<Decryptor + 'static> {
         let decryptor = ChaCha20Poly1305Decryptor {
             key: key,
+            fixed_iv: Some(fixed_iv),
         };
         Box::new(decryptor) as Box<Decryptor>
     }
 }
+
+#[cfg(test)]
+mod test {
+    use cipher::Aead;
+    use super::ChaCha20Poly1305Ietf;
+
+    #[test]
+    fn test_chacha20_poly1305_ietf() {
+        // RFC 7539 2.8.2. a zero sequence number leaves the nonce as is.
+        let key: Vec<u8> = (0x80u8..0xa0).collect();
+        let nonce = b"\x07\x00\x00\x00\x40\x41\x42\x43\x44\x45\x46\x47";
+        let seq_num = [0u8; 8];
+        let ad = b"\x50\x51\x52\x53\xc0\xc1\xc2\xc3\xc4\xc5\xc6\xc7";
+        let plain = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                      tip for the future, sunscreen would be it.";
+        let expected = b"\xd3\x1a\x8d\x34\x64\x8e\x60\xdb\x7b\x86\xaf\xbc\x53\xef\x7e\xc2\
+                         \xa4\xad\xed\x51\x29\x6e\x08\xfe\xa9\xe2\xb5\xa7\x36\xee\x62\xd6\
+                         \x3d\xbe\xa4\x5e\x8c\xa9\x67\x12\x82\xfa\xfb\x69\xda\x92\x72\x8b\
+                         \x1a\x71\xde\x0a\x9e\x06\x0b\x29\x05\xd6\xa5\xb6\x7e\xcd\x3b\x36\
+                         \x92\xdd\xbd\x7f\x2d\x77\x8b\x8c\x98\x03\xae\xe3\x28\x09\x1b\x58\
+                         \xfa\xb3\x24\xe4\xfa\xd6\x75\x94\x55\x85\x80\x8b\x48\x31\xd7\xbc\
+                         \x3f\xf4\xde\xf0\x8e\x4b\x7a\x9d\xe5\x76\xd2\x65\x86\xce\xc6\x4b\
+                         \x61\x16\x1a\xe1\x0b\x59\x4f\x09\xe2\x6a\x7e\x90\x2e\xcb\xd0\x60\
+                         \x06\x91";
+
+        let mut encryptor = ChaCha20Poly1305Ietf.new_encryptor(key.clone(), nonce.to_vec());
+        let encrypted = encryptor.encrypt(&seq_num, plain, ad);
+        assert_eq!(&encrypted[], &expected[]);
+
+        let mut decryptor = ChaCha20Poly1305Ietf.new_decryptor(key, nonce.to_vec());
+        assert_eq!(&decryptor.decrypt(&seq_num, &encrypted[], ad).unwrap()[], &plain[]);
+
+        let mut broken = encrypted.clone();
+        broken[0] ^= 1;
+        assert!(decryptor.decrypt(&seq_num, &broken[], ad).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+// finite-field Diffie-Hellman key exchange (RFC 5246 8.1.2, RFC 7919)
+
+use std::rand::{Rng, OsRng};
+use std::io::BufReader;
+use std::cmp::Ordering;
+use std::iter::repeat;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::{IllegalParameter, InternalError, DecodeError,
+                               InsufficientSecurity};
+use tls_item::TlsItem;
+use crypto::bignum::{self, Modulus};
+use handshake::NamedCurve;
+use signature::{DigitallySigned, PublicKey, PrivateKey, SignatureAndHashAlgorithm};
+use super::{KeyExchange, chosen_group};
+use super::psk::{ClientPskCallback, ServerPskCallback};
+
+tls_vec!(DhParam = u8(1, (1 << 16) - 1));
+
+tls_struct!(struct ServerDhParams {
+    dh_p: DhParam,
+    dh_g: DhParam,
+    dh_ys: DhParam
+});
+
+tls_struct!(struct DheServerKeyExchange {
+    params: ServerDhParams,
+    signed_params: DigitallySigned
+});
+
+// byte length of the smallest (2048-bit) and the largest (8192-bit) group we accept
+const MIN_GROUP_LEN: usize = 256;
+const MAX_GROUP_LEN: usize = 1024;
+
+// every RFC 7919 group uses g = 2
+static GENERATOR: &'static [u8] = &[2];
+
+static FFDHE2048_P: &'static [u8] = &[
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xad, 0xf8, 0x54, 0x58,
+    0xa2, 0xbb, 0x4a, 0x9a, 0xaf, 0xdc, 0x56, 0x20, 0x27, 0x3d, 0x3c, 0xf1,
+    0xd8, 0xb9, 0xc5, 0x83, 0xce, 0x2d, 0x36, 0x95, 0xa9, 0xe1, 0x36, 0x41,
+    0x14, 0x64, 0x33, 0xfb, 0xcc, 0x93, 0x9d, 0xce, 0x24, 0x9b, 0x3e, 0xf9,
+    0x7d, 0x2f, 0xe3, 0x63, 0x63, 0x0c, 0x75, 0xd8, 0xf6, 0x81, 0xb2, 0x02,
+    0xae, 0xc4, 0x61, 0x7a, 0xd3, 0xdf, 0x1e, 0xd5, 0xd5, 0xfd, 0x65, 0x61,
+    0x24, 0x33, 0xf5, 0x1f, 0x5f, 0x06, 0x6e, 0xd0, 0x85, 0x63, 0x65, 0x55,
+    0x3d, 0xed, 0x1a, 0xf3, 0xb5, 0x57, 0x13, 0x5e, 0x7f, 0x57, 0xc9, 0x35,
+    0x98, 0x4f, 0x0c, 0x70, 0xe0, 0xe6, 0x8b, 0x77, 0xe2, 0xa6, 0x89, 0xda,
+    0xf3, 0xef, 0xe8, 0x72, 0x1d, 0xf1, 0x58, 0xa1, 0x36, 0xad, 0xe7, 0x35,
+    0x30, 0xac, 0xca, 0x4f, 0x48, 0x3a, 0x79, 0x7a, 0xbc, 0x0a, 0xb1, 0x82,
+    0xb3, 0x24, 0xfb, 0x61, 0xd1, 0x08, 0xa9, 0x4b, 0xb2, 0xc8, 0xe3, 0xfb,
+    0xb9, 0x6a, 0xda, 0xb7, 0x60, 0xd7, 0xf4, 0x68, 0x1d, 0x4f, 0x42, 0xa3,
+    0xde, 0x39, 0x4d, 0xf4, 0xae, 0x56, 0xed, 0xe7, 0x63, 0x72, 0xbb, 0x19,
+    0x0b, 0x07, 0xa7, 0xc8, 0xee, 0x0a, 0x6d, 0x70, 0x9e, 0x02, 0xfc, 0xe1,
+    0xcd, 0xf7, 0xe2, 0xec, 0xc0, 0x34, 0x04, 0xcd, 0x28, 0x34, 0x2f, 0x61,
+    0x91, 0x72, 0xfe, 0x9c, 0xe9, 0x85, 0x83, 0xff, 0x8e, 0x4f, 0x12, 0x32,
+    0xee, 0xf2, 0x81, 0x83, 0xc3, 0xfe, 0x3b, 0x1b, 0x4c, 0x6f, 0xad, 0x73,
+    0x3b, 0xb5, 0xfc, 0xbc, 0x2e, 0xc2, 0x20, 0x05, 0xc5, 0x8e, 0xf1, 0x83,
+    0x7d, 0x16, 0x83, 0xb2, 0xc6, 0xf3, 0x4a, 0x26, 0xc1, 0xb2, 0xef, 0xfa,
+    0x88, 0x6b, 0x42, 0x38, 0x61, 0x28, 0x5c, 0x97, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff,
+];
+
+static FFDHE3072_P: &'static [u8] = &[
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xad, 0xf8, 0x54, 0x58,
+    0xa2, 0xbb, 0x4a, 0x9a, 0xaf, 0xdc, 0x56, 0x20, 0x27, 0x3d, 0x3c, 0xf1,
+    0xd8, 0xb9, 0xc5, 0x83, 0xce, 0x2d, 0x36, 0x95, 0xa9, 0xe1, 0x36, 0x41,
+    0x14, 0x64, 0x33, 0xfb, 0xcc, 0x93, 0x9d, 0xce, 0x24, 0x9b, 0x3e, 0xf9,
+    0x7d, 0x2f, 0xe3, 0x63, 0x63, 0x0c, 0x75, 0xd8, 0xf6, 0x81, 0xb2, 0x02,
+    0xae, 0xc4, 0x61, 0x7a, 0xd3, 0xdf, 0x1e, 0xd5, 0xd5, 0xfd, 0x65, 0x61,
+    0x24, 0x33, 0xf5, 0x1f, 0x5f, 0x06, 0x6e, 0xd0, 0x85, 0x63, 0x65, 0x55,
+    0x3d, 0xed, 0x1a, 0xf3, 0xb5, 0x57, 0x13, 0x5e, 0x7f, 0x57, 0xc9, 0x35,
+    0x98, 0x4f, 0x0c, 0x70, 0xe0, 0xe6, 0x8b, 0x77, 0xe2, 0xa6, 0x89, 0xda,
+    0xf3, 0xef, 0xe8, 0x72, 0x1d, 0xf1, 0x58, 0xa1, 0x36, 0xad, 0xe7, 0x35,
+    0x30, 0xac, 0xca, 0x4f, 0x48, 0x3a, 0x79, 0x7a, 0xbc, 0x0a, 0xb1, 0x82,
+    0xb3, 0x24, 0xfb, 0x61, 0xd1, 0x08, 0xa9, 0x4b, 0xb2, 0xc8, 0xe3, 0xfb,
+    0xb9, 0x6a, 0xda, 0xb7, 0x60, 0xd7, 0xf4, 0x68, 0x1d, 0x4f, 0x42, 0xa3,
+    0xde, 0x39, 0x4d, 0xf4, 0xae, 0x56, 0xed, 0xe7, 0x63, 0x72, 0xbb, 0x19,
+    0x0b, 0x07, 0xa7, 0xc8, 0xee, 0x0a, 0x6d, 0x70, 0x9e, 0x02, 0xfc, 0xe1,
+    0xcd, 0xf7, 0xe2, 0xec, 0xc0, 0x34, 0x04, 0xcd, 0x28, 0x34, 0x2f, 0x61,
+    0x91, 0x72, 0xfe, 0x9c, 0xe9, 0x85, 0x83, 0xff, 0x8e, 0x4f, 0x12, 0x32,
+    0xee, 0xf2, 0x81, 0x83, 0xc3, 0xfe, 0x3b, 0x1b, 0x4c, 0x6f, 0xad, 0x73,
+    0x3b, 0xb5, 0xfc, 0xbc, 0x2e, 0xc2, 0x20, 0x05, 0xc5, 0x8e, 0xf1, 0x83,
+    0x7d, 0x16, 0x83, 0xb2, 0xc6, 0xf3, 0x4a, 0x26, 0xc1, 0xb2, 0xef, 0xfa,
+    0x88, 0x6b, 0x42, 0x38, 0x61, 0x1f, 0xcf, 0xdc, 0xde, 0x35, 0x5b, 0x3b,
+    0x65, 0x19, 0x03, 0x5b, 0xbc, 0x34, 0xf4, 0xde, 0xf9, 0x9c, 0x02, 0x38,
+    0x61, 0xb4, 0x6f, 0xc9, 0xd6, 0xe6, 0xc9, 0x07, 0x7a, 0xd9, 0x1d, 0x26,
+    0x91, 0xf7, 0xf7, 0xee, 0x59, 0x8c, 0xb0, 0xfa, 0xc1, 0x86, 0xd9, 0x1c,
+    0xae, 0xfe, 0x13, 0x09, 0x85, 0x13, 0x92, 0x70, 0xb4, 0x13, 0x0c, 0x93,
+    0xbc, 0x43, 0x79, 0x44, 0xf4, 0xfd, 0x44, 0x52, 0xe2, 0xd7, 0x4d, 0xd3,
+    0x64, 0xf2, 0xe2, 0x1e, 0x71, 0xf5, 0x4b, 0xff, 0x5c, 0xae, 0x82, 0xab,
+    0x9c, 0x9d, 0xf6, 0x9e, 0xe8, 0x6d, 0x2b, 0xc5, 0x22, 0x36, 0x3a, 0x0d,
+    0xab, 0xc5, 0x21, 0x97, 0x9b, 0x0d, 0xea, 0xda, 0x1d, 0xbf, 0x9a, 0x42,
+    0xd5, 0xc4, 0x48, 0x4e, 0x0a, 0xbc, 0xd0, 0x6b, 0xfa, 0x53, 0xdd, 0xef,
+    0x3c, 0x1b, 0x20, 0xee, 0x3f, 0xd5, 0x9d, 0x7c, 0x25, 0xe4, 0x1d, 0x2b,
+    0x66, 0xc6, 0x2e, 0x37, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+// groups we support, in order of preference.
+pub fn supported_groups() -> Vec<NamedCurve> {
+    vec!(NamedCurve::ffdhe2048, NamedCurve::ffdhe3072)
+}
+
+// RFC 7919 4: a client sending any of these expects the server to use one of them.
+fn is_ffdhe(group: NamedCurve) -> bool {
+    match group {
+        NamedCurve::ffdhe2048 | NamedCurve::ffdhe3072 | NamedCurve::ffdhe4096 |
+        NamedCurve::ffdhe6144 | NamedCurve::ffdhe8192 => true,
+        _ => false,
+    }
+}
+
+// return (p, g)
+fn group_params(group: NamedCurve) -> Option<(&'static [u8], &'static [u8])> {
+    match group {
+        NamedCurve::ffdhe2048 => Some((FFDHE2048_P, GENERATOR)),
+        NamedCurve::ffdhe3072 => Some((FFDHE3072_P, GENERATOR)),
+        _ => None,
+    }
+}
+
+pub struct DiffieHellman;
+
+impl KeyExchange for DiffieHellman {
+    // our preference wins. a client that sends no ffdhe group accepts any group.
+    fn choose_group(&self,
+                    ours: &[NamedCurve],
+                    theirs: Option<&[NamedCurve]>) -> Option<NamedCurve> {
+        let supported = supported_groups();
+        let theirs = theirs.and_then(|theirs| {
+            if theirs.iter().any(|group| is_ffdhe(*group)) {
+                Some(theirs)
+            } else {
+                None
+            }
+        });
+        let group = ours.iter().find(|group| {
+            supported.iter().any(|g| *g == **group) && match theirs {
+                Some(theirs) => theirs.iter().any(|g| *g == **group),
+                None => true,
+            }
+        });
+        group.map(|group| *group)
+    }
+
+    // the server may use a group other than what we offered, so `curves` is not checked.
+    // instead the group itself is validated.
+    fn compute_keys(&self,
+                    data: &[u8],
+                    cli_random: &[u8],
+                    serv_random: &[u8],
+                    server_key: Option<&PublicKey>,
+                    _curves: &[NamedCurve],
+                    signature_algorithms: &[SignatureAndHashAlgorithm],
+                    _psk: Option<&ClientPskCallback>,
+                    rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let mut reader = BufReader::new(data);
+        let dh_params: DheServerKeyExchange = try!(TlsItem::tls_read(&mut reader));
+        let server_key = match server_key {
+            Some(server_key) => server_key,
+            None => return tls_err!(InternalError, "no server key"),
+        };
+
+        // the signature covers client_random + server_random + ServerDHParams.
+        {
+            let params_len = dh_params.params.tls_size() as usize;
+            let mut signed_data = cli_random.to_vec();
+            signed_data.push_all(serv_random);
+            signed_data.push_all(&data[..params_len]);
+            try!(dh_params.signed_params.verify(server_key,
+                                                &signed_data[],
+                                                signature_algorithms));
+        }
+
+        let p = &*dh_params.params.dh_p;
+        let g = &*dh_params.params.dh_g;
+        try!(check_group(p, g));
+
+        let (x, gx) = try!(generate_key(p, g, rng));
+        let pre_master_secret = try!(compute_shared_secret(p, &x[], &*dh_params.params.dh_ys));
+
+        // ClientDiffieHellmanPublic
+        let public = try!(DhParam::new(gx));
+        let mut data = Vec::new();
+        try!(public.tls_write(&mut data));
+
+        Ok((data, pre_master_secret))
+    }
+
+    fn server_key_exchange(&self,
+                           cli_random: &[u8],
+                           serv_random: &[u8],
+                           server_key: Option<(&PrivateKey, &SignatureAndHashAlgorithm)>,
+                           group: Option<NamedCurve>,
+                           _psk_identity_hint: &[u8],
+                           rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let (p, g) = match group_params(try!(chosen_group(group))) {
+            Some(params) => params,
+            None => return tls_err!(InternalError, "unsupported group"),
+        };
+        let (x, gx) = try!(generate_key(p, g, rng));
+
+        let params = ServerDhParams {
+            dh_p: try!(DhParam::new(p.to_vec())),
+            dh_g: try!(DhParam::new(g.to_vec())),
+            dh_ys: try!(DhParam::new(gx)),
+        };
+
+        let signed_params = {
+            let mut signed_data = cli_random.to_vec();
+            signed_data.push_all(serv_random);
+            try!(params.tls_write(&mut signed_data));
+            let (server_key, algorithm) = match server_key {
+                Some(server_key) => server_key,
+                None => return tls_err!(InternalError, "no server key"),
+            };
+            try!(server_key.sign(algorithm, &signed_data[], rng))
+        };
+
+        let kex = DheServerKeyExchange {
+            params: params,
+            signed_params: signed_params,
+        };
+        let mut data = Vec::new();
+        try!(kex.tls_write(&mut data));
+
+        Ok((data, x))
+    }
+
+    fn compute_server_keys(&self,
+                           data: &[u8],
+                           group: Option<NamedCurve>,
+                           private_key: &[u8],
+                           _psk: Option<&ServerPskCallback>) -> TlsResult<Vec<u8>> {
+        let p = match group_params(try!(chosen_group(group))) {
+            Some((p, _)) => p,
+            None => return tls_err!(InternalError, "unsupported group"),
+        };
+
+        // ClientDiffieHellmanPublic
+        let mut reader = BufReader::new(data);
+        let public: DhParam = try!(TlsItem::tls_read(&mut reader));
+        if public.tls_size() != data.len() as u64 {
+            return tls_err!(DecodeError, "trailing data in ClientKeyExchange");
+        }
+
+        compute_shared_secret(p, private_key, &*public)
+    }
+}
+
+// a named group is trusted as is. otherwise we can't afford a primality test,
+// so only the size and the range of g are checked.
+fn check_group(p: &[u8], g: &[u8]) -> TlsResult<()> {
+    let named = supported_groups().into_iter().any(|group| {
+        let (group_p, group_g) = group_params(group).unwrap();
+        bignum::compare(p, group_p) == Ordering::Equal &&
+            bignum::compare(g, group_g) == Ordering::Equal
+    });
+    if named {
+        return Ok(());
+    }
+
+    let len = bignum::strip_zeros(p).len();
+    if len < MIN_GROUP_LEN {
+        return tls_err!(InsufficientSecurity, "DH group is too small: {} bytes", len);
+    }
+    if len > MAX_GROUP_LEN {
+        return tls_err!(IllegalParameter, "DH group is too large: {} bytes", len);
+    }
+    if Modulus::new(p).is_none() {
+        return tls_err!(IllegalParameter, "DH modulus is even");
+    }
+    if !in_range(p, g) {
+        return tls_err!(IllegalParameter, "DH generator out of range");
+    }
+    Ok(())
+}
+
+// 1 < y < p - 1
+// precondition: p is odd
+fn in_range(p: &[u8], y: &[u8]) -> bool {
+    let mut p_minus_1 = bignum::strip_zeros(p).to_vec();
+    let last = p_minus_1.len() - 1;
+    p_minus_1[last] -= 1;
+    bignum::compare(y, &[1]) == Ordering::Greater &&
+        bignum::compare(y, &p_minus_1[]) == Ordering::Less
+}
+
+// SECRET
+// return (private key, public key on the wire)
+// precondition: `p` and `g` passed `check_group`
+fn generate_key(p: &[u8], g: &[u8], rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+    let modulus = match Modulus::new(p) {
+        Some(modulus) => modulus,
+        None => return tls_err!(InternalError, "bad DH modulus"),
+    };
+
+    // x < p since it is shorter than p
+    let mut x: Vec<u8> = repeat(0u8).take(modulus.len() - 1).collect();
+    rng.fill_bytes(&mut x[]);
+
+    // Ys is zero-padded to the length of p (RFC 7919 5.)
+    match modulus.mod_exp(g, &x[]) {
+        Some(gx) => Ok((x, gx)),
+        None => tls_err!(InternalError, "bad DH generator"),
+    }
+}
+
+// SECRET
+// `public` is the peer's public key. return pre_master_secret.
+fn compute_shared_secret(p: &[u8], private_key: &[u8], public: &[u8]) -> TlsResult<Vec<u8>> {
+    if !in_range(p, public) {
+        return tls_err!(IllegalParameter, "peer sent strange public key");
+    }
+    let modulus = match Modulus::new(p) {
+        Some(modulus) => modulus,
+        None => return tls_err!(InternalError, "bad DH modulus"),
+    };
+    let z = match modulus.mod_exp(public, private_key) {
+        Some(z) => z,
+        None => return tls_err!(InternalError, "bad DH public key"),
+    };
+
+    // leading zero bytes of Z are stripped (RFC 5246 8.1.2)
+    let z = bignum::strip_zeros(&z[]).to_vec();
+    // a public key of small order in a custom group
+    if bignum::compare(&z[], &[1]) != Ordering::Greater {
+        return tls_err!(IllegalParameter, "degenerate shared secret");
+    }
+    Ok(z)
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use tls_result::TlsErrorKind;
+    use crypto::bignum::{self, Modulus};
+    use handshake::NamedCurve;
+    use cipher::KeyExchange;
+    use super::{DiffieHellman, group_params, supported_groups, check_group,
+                compute_shared_secret};
+
+    #[test]
+    fn test_groups() {
+        // Fermat test: 2^(p - 1) == 1 mod p
+        for &group in supported_groups().iter() {
+            let (p, g) = group_params(group).unwrap();
+            let modulus = Modulus::new(p).unwrap();
+            let mut e = p.to_vec();
+            let last = e.len() - 1;
+            e[last] -= 1;
+            let one = modulus.mod_exp(g, &e[]).unwrap();
+            assert_eq!(bignum::compare(&one[], &[1]), Ordering::Equal);
+            check_group(p, g).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_group() {
+        // 2^127 - 1
+        let mut p = vec!(0x7f);
+        p.push_all(&[0xff; 15]);
+        assert_eq!(check_group(&p[], &[2]).err().unwrap().kind,
+                   TlsErrorKind::InsufficientSecurity);
+
+        let (p, _) = group_params(NamedCurve::ffdhe2048).unwrap();
+        let mut even = p.to_vec();
+        let last = even.len() - 1;
+        even[last] = 0xfe;
+        assert!(check_group(&even[], &[2]).is_err());
+
+        // a custom group needs 1 < g < p - 1
+        let mut custom = p.to_vec();
+        custom[0] = 0x7f;
+        check_group(&custom[], &[5]).unwrap();
+        assert!(check_group(&custom[], &[1]).is_err());
+        let mut g = custom.clone();
+        g[last] -= 1;
+        assert!(check_group(&custom[], &g[]).is_err());
+    }
+
+    #[test]
+    fn test_public_key_range() {
+        let (p, _) = group_params(NamedCurve::ffdhe2048).unwrap();
+        let x = [0x55; 32];
+        assert!(compute_shared_secret(p, &x, &[1]).is_err());
+        assert!(compute_shared_secret(p, &x, &[0, 0, 1]).is_err());
+        assert!(compute_shared_secret(p, &x, p).is_err());
+        let mut p_minus_1 = p.to_vec();
+        let last = p_minus_1.len() - 1;
+        p_minus_1[last] -= 1;
+        assert!(compute_shared_secret(p, &x, &p_minus_1[]).is_err());
+
+        // 2^x for x = 1 is 2, without leading zeros
+        assert_eq!(compute_shared_secret(p, &[1], &[2]).unwrap(), vec!(2));
+    }
+
+    #[test]
+    fn test_choose_group() {
+        let kex = DiffieHellman;
+        let ours = [NamedCurve::secp256r1, NamedCurve::ffdhe3072, NamedCurve::ffdhe2048];
+        assert!(kex.choose_group(&ours, None) == Some(NamedCurve::ffdhe3072));
+        let theirs = [NamedCurve::x25519];
+        assert!(kex.choose_group(&ours, Some(&theirs[])) == Some(NamedCurve::ffdhe3072));
+        let theirs = [NamedCurve::x25519, NamedCurve::ffdhe2048];
+        assert!(kex.choose_group(&ours, Some(&theirs[])) == Some(NamedCurve::ffdhe2048));
+        let theirs = [NamedCurve::ffdhe4096];
+        assert!(kex.choose_group(&ours, Some(&theirs[])).is_none());
+        assert!(kex.choose_group(&[NamedCurve::secp256r1], None).is_none());
+    }
+}
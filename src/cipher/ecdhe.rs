@@ -2,12 +2,13 @@ use std::rand::{Rng, OsRng};
 use std::io::BufReader;
 
 use tls_result::TlsResult;
-use tls_result::TlsErrorKind::IllegalParameter;
+use tls_result::TlsErrorKind::{IllegalParameter, InternalError, DecodeError};
 use tls_item::TlsItem;
-use crypto::p256;
+use crypto::{p256, p384, curve25519};
 use handshake::NamedCurve;
-use signature::DigitallySigned;
-use super::KeyExchange;
+use signature::{DigitallySigned, PublicKey, PrivateKey, SignatureAndHashAlgorithm};
+use super::{KeyExchange, chosen_group};
+use super::psk::{ClientPskCallback, ServerPskCallback};
 
 tls_vec!(EcData = u8(1, (1 << 8) - 1));
 tls_struct!(struct EcCurve {
@@ -31,43 +32,83 @@ tls_struct!(struct EcdheServerKeyExchange {
     signed_params: DigitallySigned
 });
 
+// curves we support, in order of preference.
+pub fn supported_curves() -> Vec<NamedCurve> {
+    vec!(NamedCurve::x25519, NamedCurve::secp256r1, NamedCurve::secp384r1)
+}
+
+// our preference wins.
+// if elliptic_curves is absent, the client supports any curve (RFC 4492 4.)
+pub fn choose_curve(ours: &[NamedCurve], theirs: Option<&[NamedCurve]>) -> Option<NamedCurve> {
+    let supported = supported_curves();
+    let curve = ours.iter().find(|curve| {
+        supported.iter().any(|c| *c == **curve) && match theirs {
+            Some(theirs) => theirs.iter().any(|c| *c == **curve),
+            None => true,
+        }
+    });
+    curve.map(|curve| *curve)
+}
+
+// the server must choose one of the curves we offered.
+// `curves` may contain ffdhe groups as well.
+pub fn check_curve(curve: NamedCurve, curves: &[NamedCurve]) -> TlsResult<()> {
+    let supported = supported_curves();
+    if !curves.iter().any(|c| *c == curve) || !supported.iter().any(|c| *c == curve) {
+        return tls_err!(IllegalParameter, "server chose a curve we did not offer");
+    }
+    Ok(())
+}
+
 pub struct EllipticDiffieHellman;
 
 impl KeyExchange for EllipticDiffieHellman {
-    fn compute_keys(&self, data: &[u8], rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+    fn choose_group(&self,
+                    ours: &[NamedCurve],
+                    theirs: Option<&[NamedCurve]>) -> Option<NamedCurve> {
+        choose_curve(ours, theirs)
+    }
+
+    fn compute_keys(&self,
+                    data: &[u8],
+                    cli_random: &[u8],
+                    serv_random: &[u8],
+                    server_key: Option<&PublicKey>,
+                    curves: &[NamedCurve],
+                    signature_algorithms: &[SignatureAndHashAlgorithm],
+                    _psk: Option<&ClientPskCallback>,
+                    rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
         let mut reader = BufReader::new(data);
         let ecdh_params: EcdheServerKeyExchange = try!(TlsItem::tls_read(&mut reader));
-
-        let gy = &*ecdh_params.params.public;
-        let gy = p256::NPoint256::from_uncompressed_bytes(gy);
-        let gy = match gy {
-            None => {
-                return tls_err!(IllegalParameter, "server sent strange public key");
-            }
-            Some(gy) => gy,
+        let server_key = match server_key {
+            Some(server_key) => server_key,
+            None => return tls_err!(InternalError, "no server key"),
         };
-        let gy = gy.to_point();
-
-        fn get_random_x(rng: &mut OsRng) -> p256::int256::Int256 {
-            loop {
-                let mut x = p256::int256::ZERO;
-                for i in 0us..8 {
-                    x.v[i] = rng.next_u32();
-                }
-                let xx = x.reduce_once(0);
-                let x_is_okay = xx.compare(&x);
-                if x_is_okay == 0 {
-                    return x;
-                }
-            }
+
+        // the signature covers client_random + server_random + ServerECDHParams.
+        // use the bytes on the wire rather than re-encoding the parsed params.
+        {
+            let params_len = ecdh_params.params.tls_size() as usize;
+            let mut signed_data = cli_random.to_vec();
+            signed_data.push_all(serv_random);
+            signed_data.push_all(&data[..params_len]);
+            try!(ecdh_params.signed_params.verify(server_key,
+                                                  &signed_data[],
+                                                  signature_algorithms));
         }
 
-        let x = get_random_x(rng);
-        let gx = p256::G.mult_scalar(&x).normalize().to_uncompressed_bytes();
-        let gxy = gy.mult_scalar(&x).normalize();
-        let pre_master_secret = gxy.x.to_bytes();
+        let curve = match ecdh_params.params.curve_params {
+            EcParameters::named_curve(curve) => curve,
+        };
+        try!(check_curve(curve, curves));
+
+        let (x, gx) = try!(generate_key(curve, rng));
+        let pre_master_secret = try!(compute_shared_secret(curve,
+                                                           &x[],
+                                                           &*ecdh_params.params.public));
 
-        // we don't support client cert. send public key explicitly.
+        // our client certificates never carry a fixed ECDH key, so the public key is always
+        // sent explicitly (RFC 4492 5.7).
         let public = try!(EcData::new(gx));
 
         let mut data = Vec::new();
@@ -76,4 +117,164 @@ impl KeyExchange for EllipticDiffieHellman {
 
         Ok((public, pre_master_secret))
     }
+
+    fn server_key_exchange(&self,
+                           cli_random: &[u8],
+                           serv_random: &[u8],
+                           server_key: Option<(&PrivateKey, &SignatureAndHashAlgorithm)>,
+                           group: Option<NamedCurve>,
+                           _psk_identity_hint: &[u8],
+                           rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let curve = try!(chosen_group(group));
+        let (x, gx) = try!(generate_key(curve, rng));
+
+        let params = ServerEcdhParams {
+            curve_params: EcParameters::named_curve(curve),
+            public: try!(EcData::new(gx)),
+        };
+
+        let signed_params = {
+            let mut signed_data = cli_random.to_vec();
+            signed_data.push_all(serv_random);
+            try!(params.tls_write(&mut signed_data));
+            let (server_key, algorithm) = match server_key {
+                Some(server_key) => server_key,
+                None => return tls_err!(InternalError, "no server key"),
+            };
+            try!(server_key.sign(algorithm, &signed_data[], rng))
+        };
+
+        let kex = EcdheServerKeyExchange {
+            params: params,
+            signed_params: signed_params,
+        };
+        let mut data = Vec::new();
+        try!(kex.tls_write(&mut data));
+
+        Ok((data, x))
+    }
+
+    fn compute_server_keys(&self,
+                           data: &[u8],
+                           group: Option<NamedCurve>,
+                           private_key: &[u8],
+                           _psk: Option<&ServerPskCallback>) -> TlsResult<Vec<u8>> {
+        let curve = try!(chosen_group(group));
+
+        // ClientECDiffieHellmanPublic
+        let mut reader = BufReader::new(data);
+        let public: EcData = try!(TlsItem::tls_read(&mut reader));
+        if public.tls_size() != data.len() as u64 {
+            return tls_err!(DecodeError, "trailing data in ClientKeyExchange");
+        }
+
+        compute_shared_secret(curve, private_key, &*public)
+    }
+}
+
+// SECRET
+// return (private key, public key on the wire)
+pub fn generate_key(curve: NamedCurve, rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+    match curve {
+        NamedCurve::secp256r1 => {
+            let x = get_random_x(rng);
+            let gx = p256::G.mult_scalar(&x).normalize().to_uncompressed_bytes();
+            Ok((x.to_bytes(), gx))
+        }
+        NamedCurve::secp384r1 => {
+            let x = get_random_x384(rng);
+            let gx = p384::G.mult_scalar(&x).normalize().to_uncompressed_bytes();
+            Ok((x.to_bytes(), gx))
+        }
+        NamedCurve::x25519 => {
+            let mut x = [0u8; 32];
+            rng.fill_bytes(&mut x);
+            let gx = curve25519::x25519(&x, &curve25519::BASE);
+            Ok((x.to_vec(), gx.to_vec()))
+        }
+        _ => tls_err!(InternalError, "unsupported curve"),
+    }
+}
+
+// SECRET
+// `public` is the peer's public key. return pre_master_secret.
+pub fn compute_shared_secret(curve: NamedCurve,
+                             private_key: &[u8],
+                             public: &[u8]) -> TlsResult<Vec<u8>> {
+    match curve {
+        NamedCurve::secp256r1 => {
+            let gy = match p256::NPoint256::from_uncompressed_bytes(public) {
+                Some(gy) => gy,
+                None => return tls_err!(IllegalParameter, "peer sent strange public key"),
+            };
+            let gy = gy.to_point();
+
+            let x = match p256::int256::Int256::from_bytes(private_key) {
+                Some(x) => x,
+                None => return tls_err!(InternalError, "bad ECDHE private key"),
+            };
+
+            let gxy = gy.mult_scalar(&x).normalize();
+            Ok(gxy.x.to_bytes())
+        }
+        NamedCurve::secp384r1 => {
+            let gy = match p384::NPoint384::from_uncompressed_bytes(public) {
+                Some(gy) => gy,
+                None => return tls_err!(IllegalParameter, "peer sent strange public key"),
+            };
+            let gy = gy.to_point();
+
+            let x = match p384::int384::Int384::from_bytes(private_key) {
+                Some(x) => x,
+                None => return tls_err!(InternalError, "bad ECDHE private key"),
+            };
+
+            let gxy = gy.mult_scalar(&x).normalize();
+            Ok(gxy.x.to_bytes())
+        }
+        NamedCurve::x25519 => {
+            if public.len() != 32 {
+                return tls_err!(IllegalParameter, "peer sent strange public key");
+            }
+            let gxy = curve25519::x25519(private_key, public);
+
+            // a small-order public key gives the all-zero secret (RFC 7748 6.1)
+            let mut acc = 0u8;
+            for b in gxy.iter() {
+                acc |= *b;
+            }
+            if acc == 0 {
+                return tls_err!(IllegalParameter, "all-zero shared secret");
+            }
+            Ok(gxy.to_vec())
+        }
+        _ => tls_err!(InternalError, "unsupported curve"),
+    }
+}
+
+// SECRET
+fn get_random_x(rng: &mut OsRng) -> p256::int256::Int256 {
+    loop {
+        let mut x = p256::int256::ZERO;
+        for i in 0us..8 {
+            x.v[i] = rng.next_u32();
+        }
+        let xx = x.reduce_once(0);
+        let x_is_okay = xx.compare(&x);
+        if x_is_okay == 0 {
+            return x;
+        }
+    }
+}
+
+// SECRET
+fn get_random_x384(rng: &mut OsRng) -> p384::int384::Int384 {
+    loop {
+        let mut bytes = [0u8; 48];
+        rng.fill_bytes(&mut bytes);
+        match p384::int384::Int384::from_bytes(&bytes) {
+            Some(x) => return x,
+            None => {}
+        }
+    }
 }
@@ -1,24 +1,35 @@
 use std::rand::OsRng;
 
 use tls_result::TlsResult;
-use tls_result::TlsErrorKind::UnexpectedMessage;
+use tls_result::TlsErrorKind::{UnexpectedMessage, InternalError};
 use tls_item::TlsItem;
-use self::chacha20_poly1305::ChaCha20Poly1305;
+use signature::{PublicKey, PrivateKey, SignatureAlgorithm, HashAlgorithm,
+                SignatureAndHashAlgorithm};
+use handshake::NamedCurve;
+use self::chacha20_poly1305::{ChaCha20Poly1305, ChaCha20Poly1305Ietf};
+use self::aes_gcm::{Aes128Gcm, Aes256Gcm};
 use self::ecdhe::EllipticDiffieHellman;
+use self::dhe::DiffieHellman;
+use self::psk::{Psk, EcdhePsk, ClientPskCallback, ServerPskCallback};
 
 pub mod prf;
 pub mod ecdhe;
+pub mod dhe;
+pub mod psk;
 pub mod chacha20_poly1305;
+pub mod aes_gcm;
 
 pub trait Aead {
     fn key_size(&self) -> usize;
     fn fixed_iv_len(&self) -> usize;
     fn mac_len(&self) -> usize;
-    fn new_encryptor(&self, key: Vec<u8>) -> Box<Encryptor + 'static>;
-    fn new_decryptor(&self, key: Vec<u8>) -> Box<Decryptor + 'static>;
+    // `fixed_iv` is the implicit part of the nonce, `fixed_iv_len()` bytes long.
+    fn new_encryptor(&self, key: Vec<u8>, fixed_iv: Vec<u8>) -> Box<Encryptor + 'static>;
+    fn new_decryptor(&self, key: Vec<u8>, fixed_iv: Vec<u8>) -> Box<Decryptor + 'static>;
 }
 
 pub trait Encryptor {
+    // `nonce` is the sequence number. the output includes the explicit nonce if any.
     fn encrypt(&mut self, nonce: &[u8], plain: &[u8], ad: &[u8]) -> Vec<u8>;
 }
 
@@ -28,16 +39,80 @@ pub trait Decryptor {
     fn decrypt(&mut self, nonce: &[u8], encrypted: &[u8], ad: &[u8]) -> TlsResult<Vec<u8>>;
     // FIXME: copied from Aead since record::RecordReader wants this
     fn mac_len(&self) -> usize;
+    // length of the explicit nonce prepended to `encrypted`.
+    fn explicit_nonce_len(&self) -> usize;
 }
 
 pub trait KeyExchange {
+    // whether the key exchange needs a group from the elliptic_curves extension.
+    // plain PSK does not.
+    fn uses_group(&self) -> bool { true }
+
+    // server side. choose a group from `ours`, which is in order of preference.
+    // `theirs` is the client's elliptic_curves extension if any.
+    // return None if the cipher suite cannot be used.
+    fn choose_group(&self,
+                    ours: &[NamedCurve],
+                    theirs: Option<&[NamedCurve]>) -> Option<NamedCurve>;
+
+    // `data` is ServerKeyExchange body, empty if the server sent none.
+    // its signature is verified by `server_key`, which is None for PSK suites.
+    // the server must choose one of `curves`, which we offered in ClientHello,
+    // and sign with one of `signature_algorithms`.
+    // `psk` gives the identity and the key for PSK suites.
     // return (client_key_exchange_data, pre_master_secret)
-    fn compute_keys(&self, data: &[u8], rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)>;
+    fn compute_keys(&self,
+                    data: &[u8],
+                    cli_random: &[u8],
+                    serv_random: &[u8],
+                    server_key: Option<&PublicKey>,
+                    curves: &[NamedCurve],
+                    signature_algorithms: &[SignatureAndHashAlgorithm],
+                    psk: Option<&ClientPskCallback>,
+                    rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)>;
+
+    // server side. the parameters are signed by `server_key` with the given algorithm.
+    // `server_key` is None for PSK suites.
+    // `group` is the result of `choose_group`, None if `uses_group()` is false.
+    // return (server_key_exchange_data, private_key)
+    // ServerKeyExchange is not sent if `server_key_exchange_data` is empty.
+    // `private_key` is SECRET and is passed to `compute_server_keys` later.
+    fn server_key_exchange(&self,
+                           cli_random: &[u8],
+                           serv_random: &[u8],
+                           server_key: Option<(&PrivateKey, &SignatureAndHashAlgorithm)>,
+                           group: Option<NamedCurve>,
+                           psk_identity_hint: &[u8],
+                           rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)>;
+
+    // `data` is ClientKeyExchange body. `psk` looks up the key of a PSK identity.
+    // return pre_master_secret
+    fn compute_server_keys(&self,
+                           data: &[u8],
+                           group: Option<NamedCurve>,
+                           private_key: &[u8],
+                           psk: Option<&ServerPskCallback>) -> TlsResult<Vec<u8>>;
+}
+
+// the group chosen by `KeyExchange::choose_group`.
+fn chosen_group(group: Option<NamedCurve>) -> TlsResult<NamedCurve> {
+    match group {
+        Some(group) => Ok(group),
+        None => tls_err!(InternalError, "no group chosen"),
+    }
+}
+
+// groups for ECDHE and DHE, in order of preference.
+// RFC 7919 puts both kinds into the elliptic_curves extension, renamed to supported_groups.
+pub fn supported_groups() -> Vec<NamedCurve> {
+    let mut groups = ecdhe::supported_curves();
+    groups.push_all(&dhe::supported_groups()[]);
+    groups
 }
 
 macro_rules! cipher_suite {
     ($(
-        $id:ident = $kex:ident, $cipher:ident, $mac:ident, $v1:expr, $v2:expr;
+        $id:ident = $kex:ident, $sign:ident, $cipher:ident, $prf_hash:ident, $v1:expr, $v2:expr;
     )+) => (
         #[allow(non_camel_case_types)]
         #[derive(Copy, PartialEq, Show)]
@@ -45,15 +120,23 @@ macro_rules! cipher_suite {
             $(
                 $id,
             )+
+            // RFC 5746: not a real cipher suite but a signal for secure renegotiation.
+            TLS_EMPTY_RENEGOTIATION_INFO_SCSV,
             UnknownCipherSuite,
         }
 
         impl CipherSuite {
+            // every cipher suite we implement, in order of preference.
+            pub fn supported() -> Vec<CipherSuite> {
+                vec!($(CipherSuite::$id),+)
+            }
+
             pub fn new_aead(&self) -> Box<Aead> {
                 match *self {
                     $(
                         CipherSuite::$id => box $cipher as Box<Aead>,
                     )+
+                    CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV |
                     CipherSuite::UnknownCipherSuite => unreachable!(),
                 }
             }
@@ -63,6 +146,30 @@ macro_rules! cipher_suite {
                     $(
                         CipherSuite::$id => box $kex as Box<KeyExchange>,
                     )+
+                    CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV |
+                    CipherSuite::UnknownCipherSuite => unreachable!(),
+                }
+            }
+
+            // the server certificate key must be able to make this signature.
+            // `anonymous` means that the suite uses PSK instead of certificates.
+            pub fn signature_algorithm(&self) -> SignatureAlgorithm {
+                match *self {
+                    $(
+                        CipherSuite::$id => SignatureAlgorithm::$sign,
+                    )+
+                    CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV |
+                    CipherSuite::UnknownCipherSuite => unreachable!(),
+                }
+            }
+
+            // hash used by PRF and Finished (RFC 5246 5).
+            pub fn prf_hash(&self) -> HashAlgorithm {
+                match *self {
+                    $(
+                        CipherSuite::$id => HashAlgorithm::$prf_hash,
+                    )+
+                    CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV |
                     CipherSuite::UnknownCipherSuite => unreachable!(),
                 }
             }
@@ -80,6 +187,11 @@ macro_rules! cipher_suite {
                         return Ok(());
                     }
                 )+
+                if *self == CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV {
+                    try!(writer.write_u8(0x00));
+                    try!(writer.write_u8(0xff));
+                    return Ok(());
+                }
 
                 return tls_err!(UnexpectedMessage, "unexpected CipherSuite: {:?}", self);
             }
@@ -92,6 +204,9 @@ macro_rules! cipher_suite {
                         return Ok(CipherSuite::$id);
                     }
                 )+
+                if id1 == 0x00 && id2 == 0xff {
+                    return Ok(CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV);
+                }
                 // client may send cipher suites we don't know
                 return Ok(CipherSuite::UnknownCipherSuite);
             }
@@ -103,11 +218,41 @@ macro_rules! cipher_suite {
     )
 }
 
-// TODO RSA/ECDSA signs
 cipher_suite!(
+    // RFC 7905
+    // the draft suites below took the names first, hence `_IETF`.
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256_IETF =
+    EllipticDiffieHellman, ecdsa, ChaCha20Poly1305Ietf, sha256, 0xcc, 0xa9;
+    TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256_IETF =
+    EllipticDiffieHellman, rsa, ChaCha20Poly1305Ietf, sha256, 0xcc, 0xa8;
+
     // http://tools.ietf.org/html/draft-agl-tls-chacha20poly1305-04
     TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256 =
-    EllipticDiffieHellman, ChaCha20Poly1305, MAC_SHA256, 0xcc, 0x13;
-    // TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256 =
-    // EllipticDiffieHellman ChaCha20Poly1305 MAC_SHA256 0xcc 0x14;
+    EllipticDiffieHellman, rsa, ChaCha20Poly1305, sha256, 0xcc, 0x13;
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256 =
+    EllipticDiffieHellman, ecdsa, ChaCha20Poly1305, sha256, 0xcc, 0x14;
+
+    // RFC 5289
+    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256 =
+    EllipticDiffieHellman, ecdsa, Aes128Gcm, sha256, 0xc0, 0x2b;
+    TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 =
+    EllipticDiffieHellman, rsa, Aes128Gcm, sha256, 0xc0, 0x2f;
+    TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384 =
+    EllipticDiffieHellman, ecdsa, Aes256Gcm, sha384, 0xc0, 0x2c;
+    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384 =
+    EllipticDiffieHellman, rsa, Aes256Gcm, sha384, 0xc0, 0x30;
+
+    // for servers without ECDHE. RFC 7905, RFC 5288
+    TLS_DHE_RSA_WITH_CHACHA20_POLY1305_SHA256 =
+    DiffieHellman, rsa, ChaCha20Poly1305Ietf, sha256, 0xcc, 0xaa;
+    TLS_DHE_RSA_WITH_AES_128_GCM_SHA256 =
+    DiffieHellman, rsa, Aes128Gcm, sha256, 0x00, 0x9e;
+    TLS_DHE_RSA_WITH_AES_256_GCM_SHA384 =
+    DiffieHellman, rsa, Aes256Gcm, sha384, 0x00, 0x9f;
+
+    // only offered if a PSK is configured. RFC 7905, RFC 5487
+    TLS_ECDHE_PSK_WITH_CHACHA20_POLY1305_SHA256 =
+    EcdhePsk, anonymous, ChaCha20Poly1305Ietf, sha256, 0xcc, 0xac;
+    TLS_PSK_WITH_AES_128_GCM_SHA256 =
+    Psk, anonymous, Aes128Gcm, sha256, 0x00, 0xa8;
 );
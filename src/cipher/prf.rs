@@ -1,58 +1,126 @@
 // In AEAD setting, PRF is only used for key calculation.
-// SHA-256 only for now.
+// the hash is chosen by the cipher suite (RFC 5246 5). SHA-256 unless noted otherwise.
 
 use std::mem;
-use crypto::sha2::sha256;
+use std::iter::repeat;
+use crypto::sha2::{Hash, Sha256, Sha384, Sha512};
+use signature::HashAlgorithm;
 
-// key is SECRET, but the length is publicly known.
-pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
-    const B: usize = 64;
+fn digest<H: Hash>(msg: &[u8]) -> Vec<u8> {
+    let mut h: H = Hash::new();
+    h.update(msg);
+    h.finalize()
+}
+
+pub fn hash(hash: HashAlgorithm, msg: &[u8]) -> Vec<u8> {
+    match hash {
+        HashAlgorithm::sha256 => digest::<Sha256>(msg),
+        HashAlgorithm::sha384 => digest::<Sha384>(msg),
+        HashAlgorithm::sha512 => digest::<Sha512>(msg),
+        _ => panic!("unsupported PRF hash: {:?}", hash),
+    }
+}
+
+// RFC 2104. `clone` a keyed context to MAC several messages with the same key.
+// SECRET
+#[derive(Clone)]
+pub struct Hmac<H> {
+    inner: H,
+    outer: H,
+}
+
+impl<H: Hash> Hmac<H> {
+    // key is SECRET, but the length is publicly known.
+    pub fn new(key: &[u8]) -> Hmac<H> {
+        let mut inner: H = Hash::new();
+        let mut outer: H = Hash::new();
+        let b = inner.block_len();
+
+        // keys longer than the block size are hashed first.
+        let key = if key.len() > b {
+            digest::<H>(key)
+        } else {
+            key.to_vec()
+        };
+
+        let mut i_pad: Vec<u8> = repeat(0x36u8).take(b).collect();
+        let mut o_pad: Vec<u8> = repeat(0x5cu8).take(b).collect();
+        for i in (0us..key.len()) {
+            i_pad[i] ^= key[i];
+            o_pad[i] ^= key[i];
+        }
+
+        inner.update(&i_pad[]);
+        outer.update(&o_pad[]);
+
+        Hmac {
+            inner: inner,
+            outer: outer,
+        }
+    }
 
-    if key.len() > B {
-        // FIXME
-        unimplemented!();
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
     }
 
-    let mut i_msg = [0x36u8; B].to_vec();
-    let mut o_msg = [0x5cu8; B].to_vec();
-    for i in (0us..key.len()) {
-        i_msg[i] ^= key[i];
-        o_msg[i] ^= key[i];
+    pub fn finalize(self) -> Vec<u8> {
+        let Hmac { inner, mut outer } = self;
+        outer.update(&inner.finalize()[]);
+        outer.finalize()
     }
+}
 
-    i_msg.push_all(msg);
-    let h_i = sha256(&i_msg[]);
-    o_msg.push_all(&h_i[]);
-    let h_o = sha256(&o_msg[]);
+fn hmac_with<H: Hash>(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    let mut hmac: Hmac<H> = Hmac::new(key);
+    hmac.update(msg);
+    hmac.finalize()
+}
 
-    h_o
+// key is SECRET, but the length is publicly known.
+pub fn hmac(hash_alg: HashAlgorithm, key: &[u8], msg: &[u8]) -> Vec<u8> {
+    match hash_alg {
+        HashAlgorithm::sha256 => hmac_with::<Sha256>(key, msg),
+        HashAlgorithm::sha384 => hmac_with::<Sha384>(key, msg),
+        HashAlgorithm::sha512 => hmac_with::<Sha512>(key, msg),
+        _ => panic!("unsupported PRF hash: {:?}", hash_alg),
+    }
 }
 
-pub struct Prf {
-    secret: Vec<u8>, // SECRET
+// P_hash of RFC 5246 5.
+pub struct Prf<H> {
+    hmac: Hmac<H>, // keyed with the SECRET
     seed: Vec<u8>,
-    a: [u8; 32],
+    a: Vec<u8>,
     buf: Vec<u8>,
 }
 
-impl Prf {
-    pub fn new(secret: Vec<u8>, seed: Vec<u8>) -> Prf {
-        let a1 = hmac_sha256(&secret[], &seed[]);
+impl<H: Hash> Prf<H> {
+    pub fn new(secret: &[u8], seed: Vec<u8>) -> Prf<H> {
+        let hmac: Hmac<H> = Hmac::new(secret);
+        let a1 = {
+            let mut h = hmac.clone();
+            h.update(&seed[]);
+            h.finalize()
+        };
 
         Prf {
-            secret: secret,
+            hmac: hmac,
             seed: seed,
             a: a1,
             buf: Vec::new(),
         }
     }
 
-    // get 32-byte pseudorandom number.
-    fn next_block(&mut self) -> [u8; 32] {
-        let mut input = self.a.to_vec();
-        input.push_all(&self.seed[]);
-        let next = hmac_sha256(&self.secret[], &input[]);
-        self.a = hmac_sha256(&self.secret[], &self.a[]);
+    // get pseudorandom number of the hash output size.
+    fn next_block(&mut self) -> Vec<u8> {
+        let mut h = self.hmac.clone();
+        h.update(&self.a[]);
+        h.update(&self.seed[]);
+        let next = h.finalize();
+
+        let mut h = self.hmac.clone();
+        h.update(&self.a[]);
+        self.a = h.finalize();
 
         next
     }
@@ -77,7 +145,7 @@ impl Prf {
         while ret.len() < size {
             let next_block = self.next_block();
             let slice_len = size - ret.len();
-            if slice_len > 32 {
+            if slice_len > next_block.len() {
                 ret.push_all(&next_block[]);
             } else {
                 ret.push_all(&next_block[..slice_len]);
@@ -90,12 +158,100 @@ impl Prf {
     }
 }
 
+fn prf_with<H: Hash>(secret: &[u8], label_seed: Vec<u8>, len: usize) -> Vec<u8> {
+    let mut prf: Prf<H> = Prf::new(secret, label_seed);
+    prf.get_bytes(len)
+}
+
+// `len` bytes of PRF(secret, label, seed). `label_seed` is label || seed.
+// SECRET
+fn prf(hash: HashAlgorithm, secret: &[u8], label_seed: Vec<u8>, len: usize) -> Vec<u8> {
+    match hash {
+        HashAlgorithm::sha256 => prf_with::<Sha256>(secret, label_seed, len),
+        HashAlgorithm::sha384 => prf_with::<Sha384>(secret, label_seed, len),
+        HashAlgorithm::sha512 => prf_with::<Sha512>(secret, label_seed, len),
+        _ => panic!("unsupported PRF hash: {:?}", hash),
+    }
+}
+
+// RFC 5246 8.1
+// SECRET
+pub fn master_secret(hash: HashAlgorithm,
+                     pre_master_secret: Vec<u8>,
+                     cli_random: &[u8],
+                     serv_random: &[u8]) -> Vec<u8> {
+    let mut label_seed = b"master secret".to_vec();
+    label_seed.push_all(cli_random);
+    label_seed.push_all(serv_random);
+
+    prf(hash, &pre_master_secret[], label_seed, 48)
+}
+
+// RFC 7627 4
+// `session_hash` is the hash of the handshake messages up to and including ClientKeyExchange.
+// SECRET
+pub fn extended_master_secret(hash: HashAlgorithm,
+                              pre_master_secret: Vec<u8>,
+                              session_hash: &[u8]) -> Vec<u8> {
+    let mut label_seed = b"extended master secret".to_vec();
+    label_seed.push_all(session_hash);
+
+    prf(hash, &pre_master_secret[], label_seed, 48)
+}
+
+// RFC 5246 6.3
+// mac keys are not used in AEAD configuration.
+// SECRET
+pub struct KeyBlock {
+    pub client_write_key: Vec<u8>,
+    pub server_write_key: Vec<u8>,
+    pub client_write_iv: Vec<u8>,
+    pub server_write_iv: Vec<u8>,
+}
+
+// `iv_len` is the implicit nonce length (RFC 5288 3). chacha20-poly1305 uses no iv.
+// SECRET
+pub fn key_block(hash: HashAlgorithm,
+                 master_secret: &[u8],
+                 cli_random: &[u8],
+                 serv_random: &[u8],
+                 key_size: usize,
+                 iv_len: usize) -> KeyBlock {
+    let mut label_seed = b"key expansion".to_vec();
+    label_seed.push_all(serv_random);
+    label_seed.push_all(cli_random);
+
+    let block = prf(hash, master_secret, label_seed, (key_size + iv_len) * 2);
+    let (keys, ivs) = block.split_at(key_size * 2);
+    KeyBlock {
+        client_write_key: keys[..key_size].to_vec(),
+        server_write_key: keys[key_size..].to_vec(),
+        client_write_iv: ivs[..iv_len].to_vec(),
+        server_write_iv: ivs[iv_len..].to_vec(),
+    }
+}
+
+// RFC 5246 7.4.9
+// `label` is "client finished" or "server finished".
+// `handshake_hash` is the hash of the handshake messages so far.
+pub fn verify_data(hash_alg: HashAlgorithm,
+                   master_secret: &[u8],
+                   label: &[u8],
+                   handshake_hash: &[u8],
+                   len: usize) -> Vec<u8> {
+    let mut label_seed = label.to_vec();
+    label_seed.push_all(handshake_hash);
+    prf(hash_alg, master_secret, label_seed, len)
+}
+
 #[cfg(test)]
 mod test {
-    use super::{hmac_sha256, Prf};
+    use signature::HashAlgorithm;
+    use crypto::sha2::{sha256, Hash, Sha256, Sha384};
+    use super::{hmac, Hmac, Prf, extended_master_secret};
 
     #[test]
-    fn test_hmac_sha256() {
+    fn test_hmac() {
         // some test vectors from RFC 4231
         static VALUES: &'static [(&'static [u8], &'static [u8], &'static [u8])] = &[
             (b"\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\
@@ -127,15 +283,112 @@ mod test {
         ];
 
         for &(key, input, expected) in VALUES.iter() {
-            let actual = hmac_sha256(key, input);
+            let actual = hmac(HashAlgorithm::sha256, key, input);
             assert_eq!(&actual[], expected);
         }
+
+        // test case 2 and 6 (key longer than the block size)
+        static JEFE: &'static [u8] = b"Jefe";
+        static JEFE_MSG: &'static [u8] = b"what do ya want for nothing?";
+        static LONG_MSG: &'static [u8] = b"Test Using Larger Than Block-Size Key - Hash Key First";
+        let long_key = [0xaau8; 131];
+
+        static VALUES_SHA2: &'static [(HashAlgorithm, bool, &'static [u8])] = &[
+            (HashAlgorithm::sha384, false,
+             b"\xaf\x45\xd2\xe3\x76\x48\x40\x31\x61\x7f\x78\xd2\xb5\x8a\x6b\x1b\
+               \x9c\x7e\xf4\x64\xf5\xa0\x1b\x47\xe4\x2e\xc3\x73\x63\x22\x44\x5e\
+               \x8e\x22\x40\xca\x5e\x69\xe2\xc7\x8b\x32\x39\xec\xfa\xb2\x16\x49"),
+            (HashAlgorithm::sha512, false,
+             b"\x16\x4b\x7a\x7b\xfc\xf8\x19\xe2\xe3\x95\xfb\xe7\x3b\x56\xe0\xa3\
+               \x87\xbd\x64\x22\x2e\x83\x1f\xd6\x10\x27\x0c\xd7\xea\x25\x05\x54\
+               \x97\x58\xbf\x75\xc0\x5a\x99\x4a\x6d\x03\x4f\x65\xf8\xf0\xe6\xfd\
+               \xca\xea\xb1\xa3\x4d\x4a\x6b\x4b\x63\x6e\x07\x0a\x38\xbc\xe7\x37"),
+            (HashAlgorithm::sha256, true,
+             b"\x60\xe4\x31\x59\x1e\xe0\xb6\x7f\x0d\x8a\x26\xaa\xcb\xf5\xb7\x7f\
+               \x8e\x0b\xc6\x21\x37\x28\xc5\x14\x05\x46\x04\x0f\x0e\xe3\x7f\x54"),
+            (HashAlgorithm::sha384, true,
+             b"\x4e\xce\x08\x44\x85\x81\x3e\x90\x88\xd2\xc6\x3a\x04\x1b\xc5\xb4\
+               \x4f\x9e\xf1\x01\x2a\x2b\x58\x8f\x3c\xd1\x1f\x05\x03\x3a\xc4\xc6\
+               \x0c\x2e\xf6\xab\x40\x30\xfe\x82\x96\x24\x8d\xf1\x63\xf4\x49\x52"),
+            (HashAlgorithm::sha512, true,
+             b"\x80\xb2\x42\x63\xc7\xc1\xa3\xeb\xb7\x14\x93\xc1\xdd\x7b\xe8\xb4\
+               \x9b\x46\xd1\xf4\x1b\x4a\xee\xc1\x12\x1b\x01\x37\x83\xf8\xf3\x52\
+               \x6b\x56\xd0\x37\xe0\x5f\x25\x98\xbd\x0f\xd2\x21\x5d\x6a\x1e\x52\
+               \x95\xe6\x4f\x73\xf6\x3f\x0a\xec\x8b\x91\x5a\x98\x5d\x78\x65\x98"),
+        ];
+
+        for &(hash, long, expected) in VALUES_SHA2.iter() {
+            let actual = if long {
+                hmac(hash, &long_key, LONG_MSG)
+            } else {
+                hmac(hash, JEFE, JEFE_MSG)
+            };
+            assert_eq!(&actual[], expected);
+        }
+    }
+
+    #[test]
+    fn test_hmac_streaming() {
+        // RFC 4231 test case 2, fed in pieces
+        let mut mac: Hmac<Sha256> = Hmac::new(b"Jefe");
+        let mut fork = mac.clone();
+        mac.update(b"what do ya");
+        mac.update(b" want ");
+        mac.update(b"for nothing?");
+        let expected = b"\x5b\xdc\xc1\x46\xbf\x60\x75\x4e\x6a\x04\x24\x26\x08\x95\x75\xc7\
+                         \x5a\x00\x3f\x08\x9d\x27\x39\x83\x9d\xec\x58\xb9\x64\xec\x38\x43";
+        assert_eq!(&mac.finalize()[], &expected[]);
+
+        // the keyed context is reusable
+        fork.update(b"abc");
+        assert_eq!(fork.finalize(), hmac(HashAlgorithm::sha256, b"Jefe", b"abc"));
+    }
+
+    #[test]
+    fn test_prf_sha384() {
+        // widely used TLS 1.2 PRF test vector
+        let secret = b"\xb8\x0b\x73\x3d\x6c\xee\xfc\xdc\x71\x56\x6e\xa4\x8e\x55\x67\xdf";
+        let mut label_seed = b"test label".to_vec();
+        label_seed.push_all(b"\xcd\x66\x5c\xf6\xa8\x44\x7d\xd6\xff\x8b\x27\x55\x5e\xdb\x74\x65");
+
+        let mut prf: Prf<Sha384> = Prf::new(&secret[], label_seed);
+        let expected = b"\x7b\x0c\x18\xe9\xce\xd4\x10\xed\x18\x04\xf2\xcf\xa3\x4a\x33\x6a\
+                         \x1c\x14\xdf\xfb\x49\x00\xbb\x5f\xd7\x94\x21\x07\xe8\x1c\x83\xcd\
+                         \xe9\xca\x0f\xaa\x60\xbe\x9f\xe3\x4f\x82\xb1\x23\x3c\x91\x46\xa0\
+                         \xe5\x34\xcb\x40\x0f\xed\x27\x00\x88\x4f\x9d\xc2\x36\xf8\x0e\xdd\
+                         \x8b\xfa\x96\x11\x44\xc9\xe8\xd7\x92\xec\xa7\x22\xa7\xb3\x2f\xc3\
+                         \xd4\x16\xd4\x73\xeb\xc2\xc5\xfd\x4a\xbf\xda\xd0\x5d\x91\x84\x25\
+                         \x9b\x5b\xf8\xcd\x4d\x90\xfa\x0d\x31\xe2\xde\xc4\x79\xe4\xf1\xa2\
+                         \x60\x66\xf2\xee\xa9\xa6\x92\x36\xa3\xe5\x26\x55\xc9\xe9\xae\xe6\
+                         \x91\xc8\xf3\xa2\x68\x54\x30\x8d\x5e\xaa\x3b\xe8\x5e\x09\x90\x70\
+                         \x3d\x73\xe5\x6f";
+        assert_eq!(&prf.get_bytes(expected.len())[], &expected[]);
+    }
+
+    #[test]
+    fn test_extended_master_secret() {
+        let mut pre_master_secret = vec!(0x03, 0x03);
+        pre_master_secret.push_all(&[0u8; 46]);
+        let session_hash = sha256(b"handshake messages");
+
+        let actual = extended_master_secret(HashAlgorithm::sha256,
+                                            pre_master_secret,
+                                            &session_hash[]);
+        let expected = b"\x52\xc2\xef\x9c\x82\x30\x15\xac\x6d\x3b\xf7\x31\x5c\xd9\x52\xfa\
+                         \x5c\x19\x78\xf7\x3e\x48\xe3\x2e\xb7\xe3\x32\xf1\xc7\x0b\xa4\x1d\
+                         \xce\x63\x44\x22\x6c\xd0\xa3\xa3\x77\xf2\x09\xad\x17\x79\xbf\x9f";
+        assert_eq!(&actual[], &expected[]);
     }
 
     #[test]
     fn test_get_bytes() {
+        check_get_bytes::<Sha256>();
+        check_get_bytes::<Sha384>();
+    }
+
+    fn check_get_bytes<H: Hash>() {
         let ret1 = {
-            let mut prf = Prf::new(Vec::new(), Vec::new());
+            let mut prf: Prf<H> = Prf::new(&[], Vec::new());
             let mut ret = Vec::new();
             for _ in 0us..100 {
                 ret.push_all(&prf.get_bytes(1)[]);
@@ -144,14 +397,14 @@ mod test {
         };
 
         let ret2 = {
-            let mut prf = Prf::new(Vec::new(), Vec::new());
+            let mut prf: Prf<H> = Prf::new(&[], Vec::new());
             prf.get_bytes(100)
         };
 
         assert_eq!(ret1, ret2);
 
         let ret3 = {
-            let mut prf = Prf::new(Vec::new(), Vec::new());
+            let mut prf: Prf<H> = Prf::new(&[], Vec::new());
             let mut b = prf.get_bytes(33);
             b.push_all(&prf.get_bytes(33)[]);
             b.push_all(&prf.get_bytes(100 - 33 * 2)[]);
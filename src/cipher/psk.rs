@@ -0,0 +1,258 @@
+// pre-shared key cipher suites (RFC 4279, RFC 5489)
+// neither side sends a certificate: knowing the key authenticates the peer.
+
+use std::rand::OsRng;
+use std::io::BufReader;
+use std::iter::repeat;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::{InternalError, DecodeError, DecryptError, HandshakeFailure};
+use tls_item::TlsItem;
+use handshake::NamedCurve;
+use signature::{PublicKey, PrivateKey, SignatureAndHashAlgorithm};
+use super::{KeyExchange, chosen_group};
+use super::ecdhe::{self, EcData, EcParameters, ServerEcdhParams};
+
+// client side: given psk_identity_hint (possibly empty), return (psk_identity, psk).
+// None aborts the handshake.
+pub type ClientPskCallback = Box<Fn(&[u8]) -> Option<(Vec<u8>, Vec<u8>)> + Send + Sync>;
+
+// server side: return the psk of psk_identity, or None if the identity is unknown.
+pub type ServerPskCallback = Box<Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+tls_vec!(PskIdentity = u8(0, (1 << 16) - 1));
+
+tls_struct!(struct EcdhePskServerKeyExchange {
+    psk_identity_hint: PskIdentity,
+    params: ServerEcdhParams
+});
+
+tls_struct!(struct EcdhePskClientKeyExchange {
+    psk_identity: PskIdentity,
+    public: EcData
+});
+
+// struct {
+//     opaque other_secret<0..2^16-1>;
+//     opaque psk<0..2^16-1>;
+// };
+// SECRET
+fn pre_master_secret(other_secret: &[u8], psk: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(4 + other_secret.len() + psk.len());
+    ret.push((other_secret.len() >> 8) as u8);
+    ret.push(other_secret.len() as u8);
+    ret.push_all(other_secret);
+    ret.push((psk.len() >> 8) as u8);
+    ret.push(psk.len() as u8);
+    ret.push_all(psk);
+    ret
+}
+
+// return (psk_identity, psk)
+// SECRET
+fn client_psk(psk: Option<&ClientPskCallback>, hint: &[u8]) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+    let callback = match psk {
+        Some(callback) => callback,
+        None => return tls_err!(InternalError, "no PSK callback"),
+    };
+    match (**callback)(hint) {
+        Some((_, ref key)) if key.len() == 0 || key.len() >= 1 << 16 => {
+            tls_err!(InternalError, "invalid PSK")
+        }
+        Some(identity_and_key) => Ok(identity_and_key),
+        None => tls_err!(HandshakeFailure, "no PSK for the server"),
+    }
+}
+
+// SECRET
+fn server_psk(psk: Option<&ServerPskCallback>, identity: &[u8]) -> TlsResult<Vec<u8>> {
+    let callback = match psk {
+        Some(callback) => callback,
+        None => return tls_err!(InternalError, "no PSK callback"),
+    };
+    // RFC 4279 2: decrypt_error hides whether the identity is known.
+    match (**callback)(identity) {
+        Some(ref key) if key.len() == 0 || key.len() >= 1 << 16 => {
+            tls_err!(InternalError, "invalid PSK")
+        }
+        Some(key) => Ok(key),
+        None => tls_err!(DecryptError, "unknown PSK identity"),
+    }
+}
+
+// ServerKeyExchange for plain PSK.
+fn psk_identity_hint(data: &[u8]) -> TlsResult<Vec<u8>> {
+    if data.len() == 0 {
+        return Ok(Vec::new());
+    }
+    let mut reader = BufReader::new(data);
+    let hint: PskIdentity = try!(TlsItem::tls_read(&mut reader));
+    if hint.tls_size() != data.len() as u64 {
+        return tls_err!(DecodeError, "trailing data in ServerKeyExchange");
+    }
+    Ok(hint.unwrap())
+}
+
+// plain PSK: other_secret is zeros as long as the psk.
+pub struct Psk;
+
+impl KeyExchange for Psk {
+    fn uses_group(&self) -> bool { false }
+
+    fn choose_group(&self,
+                    _ours: &[NamedCurve],
+                    _theirs: Option<&[NamedCurve]>) -> Option<NamedCurve> {
+        None
+    }
+
+    fn compute_keys(&self,
+                    data: &[u8],
+                    _cli_random: &[u8],
+                    _serv_random: &[u8],
+                    _server_key: Option<&PublicKey>,
+                    _curves: &[NamedCurve],
+                    _signature_algorithms: &[SignatureAndHashAlgorithm],
+                    psk: Option<&ClientPskCallback>,
+                    _rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let hint = try!(psk_identity_hint(data));
+        let (identity, key) = try!(client_psk(psk, &hint[]));
+
+        let mut data = Vec::new();
+        try!(try!(PskIdentity::new(identity)).tls_write(&mut data));
+
+        let zeros: Vec<u8> = repeat(0u8).take(key.len()).collect();
+        Ok((data, pre_master_secret(&zeros[], &key[])))
+    }
+
+    // the hint is optional, so ServerKeyExchange is sent only with a hint.
+    fn server_key_exchange(&self,
+                           _cli_random: &[u8],
+                           _serv_random: &[u8],
+                           _server_key: Option<(&PrivateKey, &SignatureAndHashAlgorithm)>,
+                           _group: Option<NamedCurve>,
+                           psk_identity_hint: &[u8],
+                           _rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let mut data = Vec::new();
+        if psk_identity_hint.len() > 0 {
+            let hint = try!(PskIdentity::new(psk_identity_hint.to_vec()));
+            try!(hint.tls_write(&mut data));
+        }
+        Ok((data, Vec::new()))
+    }
+
+    fn compute_server_keys(&self,
+                           data: &[u8],
+                           _group: Option<NamedCurve>,
+                           _private_key: &[u8],
+                           psk: Option<&ServerPskCallback>) -> TlsResult<Vec<u8>> {
+        let mut reader = BufReader::new(data);
+        let identity: PskIdentity = try!(TlsItem::tls_read(&mut reader));
+        if identity.tls_size() != data.len() as u64 {
+            return tls_err!(DecodeError, "trailing data in ClientKeyExchange");
+        }
+
+        let key = try!(server_psk(psk, &*identity));
+        let zeros: Vec<u8> = repeat(0u8).take(key.len()).collect();
+        Ok(pre_master_secret(&zeros[], &key[]))
+    }
+}
+
+// ECDHE_PSK: other_secret is the ECDH shared secret. the parameters are not signed.
+pub struct EcdhePsk;
+
+impl KeyExchange for EcdhePsk {
+    fn choose_group(&self,
+                    ours: &[NamedCurve],
+                    theirs: Option<&[NamedCurve]>) -> Option<NamedCurve> {
+        ecdhe::choose_curve(ours, theirs)
+    }
+
+    fn compute_keys(&self,
+                    data: &[u8],
+                    _cli_random: &[u8],
+                    _serv_random: &[u8],
+                    _server_key: Option<&PublicKey>,
+                    curves: &[NamedCurve],
+                    _signature_algorithms: &[SignatureAndHashAlgorithm],
+                    psk: Option<&ClientPskCallback>,
+                    rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let mut reader = BufReader::new(data);
+        let kex: EcdhePskServerKeyExchange = try!(TlsItem::tls_read(&mut reader));
+        if kex.tls_size() != data.len() as u64 {
+            return tls_err!(DecodeError, "trailing data in ServerKeyExchange");
+        }
+
+        let curve = match kex.params.curve_params {
+            EcParameters::named_curve(curve) => curve,
+        };
+        try!(ecdhe::check_curve(curve, curves));
+
+        let (identity, key) = try!(client_psk(psk, &*kex.psk_identity_hint));
+
+        let (x, gx) = try!(ecdhe::generate_key(curve, rng));
+        let other_secret = try!(ecdhe::compute_shared_secret(curve, &x[], &*kex.params.public));
+
+        let client_kex = EcdhePskClientKeyExchange {
+            psk_identity: try!(PskIdentity::new(identity)),
+            public: try!(EcData::new(gx)),
+        };
+        let mut data = Vec::new();
+        try!(client_kex.tls_write(&mut data));
+
+        Ok((data, pre_master_secret(&other_secret[], &key[])))
+    }
+
+    fn server_key_exchange(&self,
+                           _cli_random: &[u8],
+                           _serv_random: &[u8],
+                           _server_key: Option<(&PrivateKey, &SignatureAndHashAlgorithm)>,
+                           group: Option<NamedCurve>,
+                           psk_identity_hint: &[u8],
+                           rng: &mut OsRng) -> TlsResult<(Vec<u8>, Vec<u8>)> {
+        let curve = try!(chosen_group(group));
+        let (x, gx) = try!(ecdhe::generate_key(curve, rng));
+
+        let kex = EcdhePskServerKeyExchange {
+            psk_identity_hint: try!(PskIdentity::new(psk_identity_hint.to_vec())),
+            params: ServerEcdhParams {
+                curve_params: EcParameters::named_curve(curve),
+                public: try!(EcData::new(gx)),
+            },
+        };
+        let mut data = Vec::new();
+        try!(kex.tls_write(&mut data));
+
+        Ok((data, x))
+    }
+
+    fn compute_server_keys(&self,
+                           data: &[u8],
+                           group: Option<NamedCurve>,
+                           private_key: &[u8],
+                           psk: Option<&ServerPskCallback>) -> TlsResult<Vec<u8>> {
+        let curve = try!(chosen_group(group));
+
+        let mut reader = BufReader::new(data);
+        let kex: EcdhePskClientKeyExchange = try!(TlsItem::tls_read(&mut reader));
+        if kex.tls_size() != data.len() as u64 {
+            return tls_err!(DecodeError, "trailing data in ClientKeyExchange");
+        }
+
+        let key = try!(server_psk(psk, &*kex.psk_identity));
+        let other_secret = try!(ecdhe::compute_shared_secret(curve,
+                                                             private_key,
+                                                             &*kex.public));
+        Ok(pre_master_secret(&other_secret[], &key[]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::pre_master_secret;
+
+    #[test]
+    fn test_pre_master_secret() {
+        assert_eq!(pre_master_secret(&[0, 0], b"ab"), b"\x00\x02\x00\x00\x00\x02ab".to_vec());
+        assert_eq!(pre_master_secret(&[1, 2, 3], b"k"), b"\x00\x03\x01\x02\x03\x00\x01k".to_vec());
+    }
+}
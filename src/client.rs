@@ -3,28 +3,156 @@ use std::slice::bytes::copy_memory;
 use std::cmp;
 use std::io::{IoResult, IoError, OtherIoError};
 use std::rand::{Rng, OsRng};
+use std::sync::Arc;
+
+use time;
 
 use tls_result::TlsResult;
-use tls_result::TlsErrorKind::{UnexpectedMessage, InternalError, DecryptError, IllegalParameter};
+use tls_result::TlsErrorKind::{UnexpectedMessage, InternalError, DecryptError, IllegalParameter,
+                               BadCertificate, UnsupportedCertificate, HandshakeFailure,
+                               UnsupportedExtension};
 use util::crypto_compare;
 use cipher::{self, Aead};
-use cipher::prf::Prf;
-use crypto::sha2::sha256;
-use tls_item::{TlsItem, DummyItem};
+use cipher::prf;
+use cipher::psk::ClientPskCallback;
 use handshake::{self, Handshake};
+use certificate::{Certificate, OID_SERVER_AUTH};
+use signature::{self, PrivateKey, SignatureAlgorithm, SignatureAndHashAlgorithm};
+use trust_store::TrustStore;
+use session::{Session, SessionStore};
+use record::Message;
+use server_name::ServerName;
 use tls::{Tls, TLS_VERSION};
 
+// expect specific HandshakeMessage. otherwise return Err
+macro_rules! expect {
+    ($tls:expr, $var:ident) => ({
+        match try!($tls.reader.read_handshake()) {
+            handshake::Handshake::$var(data) => data,
+            _ => return tls_err!(UnexpectedMessage, "unexpected handshake message found"),
+        }
+    })
+}
+
+// certificate chain and key used when the server requests client authentication.
+#[derive(Clone)]
+pub struct ClientCertificate {
+    // DER-encoded certificates, the client certificate first.
+    pub certificates: Vec<Vec<u8>>,
+    // SECRET: key of the client certificate.
+    pub private_key: Arc<PrivateKey>,
+}
+
+// configuration shared by connections.
+#[derive(Clone)]
+pub struct ClientConfig {
+    // server certificates must chain up to one of these.
+    pub trust_store: Arc<TrustStore>,
+    // candidates for CertificateRequest, in order of preference.
+    pub client_certificates: Vec<ClientCertificate>,
+    // if set, sessions are cached per server name and resumed later.
+    pub session_store: Option<Arc<Box<SessionStore + Send + Sync>>>,
+    // whether to accept HelloRequest. we never renegotiate without RFC 5746.
+    pub allow_renegotiation: bool,
+    // RFC 7301: protocols to offer (e.g. `b"h2"`), in order of preference.
+    pub alpn_protocols: Vec<Vec<u8>>,
+    // cipher suites to offer, in order of preference. the server makes the choice.
+    pub cipher_suites: Vec<cipher::CipherSuite>,
+    // curves for ECDHE and groups for DHE to offer, in order of preference.
+    pub elliptic_curves: Vec<handshake::NamedCurve>,
+    // RFC 5246 7.4.1.4.1: signatures to accept from the server, in order of preference.
+    pub signature_algorithms: Vec<SignatureAndHashAlgorithm>,
+    // if set, PSK cipher suites are offered as well.
+    pub psk_callback: Option<Arc<ClientPskCallback>>,
+    // refuse servers without RFC 7627 extended master secret.
+    pub require_extended_master_secret: bool,
+}
+
+impl ClientConfig {
+    pub fn new(trust_store: TrustStore) -> ClientConfig {
+        ClientConfig {
+            trust_store: Arc::new(trust_store),
+            client_certificates: Vec::new(),
+            session_store: None,
+            allow_renegotiation: false,
+            alpn_protocols: Vec::new(),
+            cipher_suites: cipher::CipherSuite::supported(),
+            elliptic_curves: cipher::supported_groups(),
+            signature_algorithms: signature::supported_signature_algorithms(),
+            psk_callback: None,
+            require_extended_master_secret: false,
+        }
+    }
+
+    // `callback` returns (psk_identity, psk) for the server's psk_identity_hint.
+    pub fn set_psk_callback<F>(&mut self, callback: F)
+        where F: Fn(&[u8]) -> Option<(Vec<u8>, Vec<u8>)> + Send + Sync
+    {
+        self.psk_callback = Some(Arc::new(Box::new(callback) as ClientPskCallback));
+    }
+
+    pub fn set_session_store<S: SessionStore + Send + Sync>(&mut self, store: S) {
+        self.session_store = Some(Arc::new(Box::new(store) as Box<SessionStore + Send + Sync>));
+    }
+
+    pub fn add_client_certificate(&mut self,
+                                  certificates: Vec<Vec<u8>>,
+                                  private_key: PrivateKey) {
+        self.client_certificates.push(ClientCertificate {
+            certificates: certificates,
+            private_key: Arc::new(private_key),
+        });
+    }
+}
+
 // handshake is done during construction.
 pub struct TlsClient<R: Reader, W: Writer> {
     tls: Tls<R, W>,
+    config: ClientConfig,
+    // if None, the server certificate is not checked against any name.
+    server_name: Option<ServerName>,
     buf: Vec<u8>,
+    // RFC 5746: the server sent renegotiation_info in the initial handshake.
+    secure_renegotiation: bool,
+    // verify data of the last handshake. empty before the initial handshake is done.
+    client_verify_data: Vec<u8>,
+    server_verify_data: Vec<u8>,
+    // protocol selected by the server
+    alpn_protocol: Option<Vec<u8>>,
 }
 
 impl<R: Reader, W: Writer> TlsClient<R, W> {
-    pub fn new(reader: R, writer: W, rng: OsRng) -> TlsResult<TlsClient<R, W>> {
+    // the certificate chain is verified but the server identity is not.
+    // use `new_with_server_name` unless the identity is checked by other means.
+    pub fn new(reader: R,
+               writer: W,
+               rng: OsRng,
+               config: ClientConfig) -> TlsResult<TlsClient<R, W>> {
+        TlsClient::with_server_name(reader, writer, rng, config, None)
+    }
+
+    pub fn new_with_server_name(reader: R,
+                                writer: W,
+                                rng: OsRng,
+                                config: ClientConfig,
+                                server_name: ServerName) -> TlsResult<TlsClient<R, W>> {
+        TlsClient::with_server_name(reader, writer, rng, config, Some(server_name))
+    }
+
+    fn with_server_name(reader: R,
+                      writer: W,
+                      rng: OsRng,
+                      config: ClientConfig,
+                      server_name: Option<ServerName>) -> TlsResult<TlsClient<R, W>> {
         let mut client = TlsClient {
             tls: Tls::new(reader, writer, rng),
+            config: config,
+            server_name: server_name,
             buf: Vec::new(),
+            secure_renegotiation: false,
+            client_verify_data: Vec::new(),
+            server_verify_data: Vec::new(),
+            alpn_protocol: None,
         };
 
         // handshake failed. send alert if necessary
@@ -37,16 +165,6 @@ impl<R: Reader, W: Writer> TlsClient<R, W> {
 
     // this does not send alert when error occurs
     fn handshake(&mut self) -> TlsResult<()> {
-        // expect specific HandshakeMessage. otherwise return Err
-        macro_rules! expect {
-            ($var:ident) => ({
-                match try!(self.tls.reader.read_handshake()) {
-                    handshake::Handshake::$var(data) => data,
-                    _ => return tls_err!(UnexpectedMessage, "unexpected handshake message found"),
-                }
-            })
-        }
-
         let cli_random = {
             let mut random_bytes = [0u8; 32];
             self.tls.rng.fill_bytes(&mut random_bytes);
@@ -54,21 +172,125 @@ impl<R: Reader, W: Writer> TlsClient<R, W> {
         };
         let random = try!(handshake::Random::new(cli_random.clone()));
 
-        // the only cipher we currently support
-        let cipher_suite = cipher::CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256;
+        // PSK suites are offered only if we have the callback.
+        let has_psk = self.config.psk_callback.is_some();
+        let mut offered_cipher_suites = Vec::new();
+        for &suite in self.config.cipher_suites.iter() {
+            if has_psk || suite.signature_algorithm() != SignatureAlgorithm::anonymous {
+                offered_cipher_suites.push(suite);
+            }
+        }
+        if offered_cipher_suites.len() == 0 {
+            return tls_err!(InternalError, "no cipher suite to offer");
+        }
+
+        if self.config.elliptic_curves.len() == 0 {
+            return tls_err!(InternalError, "no elliptic curve to offer");
+        }
 
-        let curve_list = vec!(handshake::NamedCurve::secp256r1);
+        let curve_list = self.config.elliptic_curves.clone();
         let curve_list = try!(handshake::Extension::new_elliptic_curve_list(curve_list));
 
         let format_list = vec!(handshake::ECPointFormat::uncompressed);
         let format_list = try!(handshake::Extension::new_ec_point_formats(format_list));
 
-        let extensions = vec!(curve_list, format_list);
+        if self.config.signature_algorithms.len() == 0 {
+            return tls_err!(InternalError, "no signature algorithm to offer");
+        }
+        let algorithms = self.config.signature_algorithms.clone();
+        let algorithms = try!(handshake::Extension::new_signature_algorithms(algorithms));
+
+        let mut extensions = Vec::new();
+
+        // RFC 6066: literal IP addresses are not permitted in server_name.
+        let sent_server_name = match self.server_name {
+            Some(ServerName::DnsName(ref name)) => {
+                let name = if name.ends_with(".") {
+                    &name[..(name.len() - 1)]
+                } else {
+                    &name[]
+                };
+                let server_name = try!(handshake::Extension::new_server_name(name.as_bytes()
+                                                                                 .to_vec()));
+                extensions.push(server_name);
+                true
+            }
+            _ => false,
+        };
+
+        extensions.push(curve_list);
+        extensions.push(format_list);
+        extensions.push(algorithms);
+        extensions.push(try!(handshake::Extension::new_extended_master_secret()));
+
+        // sessions are cached only if we know whom we are talking to.
+        let use_session = self.config.session_store.is_some() && self.server_name.is_some();
+
+        // offer the cached session if any.
+        let require_ems = self.config.require_extended_master_secret;
+        let cached_session = match (&self.config.session_store, &self.server_name) {
+            (&Some(ref store), &Some(ref name)) => match store.get(name) {
+                Some(session) => {
+                    let suite = session.cipher_suite;
+                    if offered_cipher_suites.iter().any(|s| *s == suite) &&
+                       (session.extended_master_secret || !require_ems) {
+                        Some(session)
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            },
+            _ => None,
+        };
+
+        // RFC 5077 3.4: with a ticket, we generate a session id to see if the server accepts it.
+        let session_id = match cached_session {
+            Some(Session { ticket: Some(..), .. }) => {
+                let mut session_id = [0u8; 32];
+                self.tls.rng.fill_bytes(&mut session_id);
+                session_id.to_vec()
+            }
+            Some(ref session) => session.session_id.clone(),
+            None => Vec::new(),
+        };
+
+        if use_session {
+            let ticket = match cached_session {
+                Some(Session { ticket: Some(ref ticket), .. }) => ticket.clone(),
+                _ => Vec::new(),
+            };
+            extensions.push(try!(handshake::Extension::new_session_ticket(ticket)));
+        }
 
-        let client_hello = try!(Handshake::new_client_hello(random, cipher_suite, extensions));
+        // RFC 5746: the initial handshake has both the SCSV and an empty renegotiation_info.
+        // renegotiation sends our last verify data instead.
+        let renegotiating = self.client_verify_data.len() > 0;
+        let mut cipher_suites = offered_cipher_suites.clone();
+        if !renegotiating {
+            cipher_suites.push(cipher::CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV);
+        }
+        let renegotiation_info = self.client_verify_data.clone();
+        extensions.push(try!(handshake::Extension::new_renegotiation_info(renegotiation_info)));
+
+        if self.config.alpn_protocols.len() > 0 {
+            let protocols = self.config.alpn_protocols.clone();
+            let alpn = handshake::Extension::new_application_layer_protocol_negotiation(protocols);
+            extensions.push(try!(alpn));
+        }
+
+        let client_hello = try!(Handshake::new_client_hello(random,
+                                                            session_id.clone(),
+                                                            cipher_suites,
+                                                            extensions));
         try!(self.tls.writer.write_handshake(&client_hello));
 
-        let server_hello_data = expect!(server_hello);
+        let server_hello_data = expect!(self.tls, server_hello);
+        // the server will send NewSessionTicket.
+        let mut expect_ticket = false;
+        let mut server_renegotiation_info = false;
+        let mut server_ems = false;
+        let mut alpn_protocol = None;
         {
             let server_major = server_hello_data.server_version.major;
             let server_minor = server_hello_data.server_version.minor;
@@ -79,85 +301,341 @@ impl<R: Reader, W: Writer> TlsClient<R, W> {
                                 server_minor);
             }
 
-            if server_hello_data.cipher_suite != cipher_suite {
+            let suite = server_hello_data.cipher_suite;
+            if !offered_cipher_suites.iter().any(|s| *s == suite) {
                 return tls_err!(IllegalParameter,
-                                "cipher suite mismatch: found {:?}",
-                                server_hello_data.cipher_suite);
+                                "server chose a cipher suite we did not offer: {:?}",
+                                suite);
             }
 
             if server_hello_data.compression_method != handshake::CompressionMethod::null {
                 return tls_err!(IllegalParameter, "compression method mismatch");
             }
 
+            match server_hello_data.extensions {
+                Some(ref extensions) => {
+                    for extension in extensions.iter() {
+                        match *extension {
+                            // the server acknowledges our server_name by an empty one.
+                            handshake::Extension::server_name(ref list) => {
+                                if !sent_server_name || list.len() != 0 {
+                                    return tls_err!(IllegalParameter,
+                                                    "unexpected server_name extension");
+                                }
+                            }
+                            handshake::Extension::session_ticket(ref ticket) => {
+                                if !use_session || ticket.len() != 0 {
+                                    return tls_err!(IllegalParameter,
+                                                    "unexpected session_ticket extension");
+                                }
+                                expect_ticket = true;
+                            }
+                            // empty for the initial handshake, otherwise both verify data.
+                            handshake::Extension::renegotiation_info(ref info) => {
+                                let mut expected = self.client_verify_data.clone();
+                                expected.push_all(&self.server_verify_data[]);
+                                if try!(info.renegotiated_connection()) != &expected[] {
+                                    return tls_err!(HandshakeFailure,
+                                                    "renegotiation_info mismatch");
+                                }
+                                server_renegotiation_info = true;
+                            }
+                            handshake::Extension::extended_master_secret(ref data) => {
+                                try!(data.check());
+                                server_ems = true;
+                            }
+                            handshake::Extension::application_layer_protocol_negotiation(
+                                ref list) => {
+                                let mut names = try!(list.protocol_names());
+                                if names.len() != 1 {
+                                    return tls_err!(IllegalParameter,
+                                                    "server must select exactly one protocol");
+                                }
+                                let name = names.pop().unwrap();
+                                if !self.config.alpn_protocols.iter().any(|p| *p == name) {
+                                    return tls_err!(IllegalParameter,
+                                                    "server selected a protocol we did not offer");
+                                }
+                                alpn_protocol = Some(name);
+                            }
+                            // RFC 5246 7.4.1.4: the server must not send what we did not offer.
+                            handshake::Extension::UnknownExtension(extension_type, _) => {
+                                return tls_err!(UnsupportedExtension,
+                                                "unexpected extension: {}", extension_type);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                None => {}
+            }
+
+            if renegotiating && !server_renegotiation_info {
+                return tls_err!(HandshakeFailure, "server sent no renegotiation_info");
+            }
+            if require_ems && !server_ems {
+                return tls_err!(HandshakeFailure,
+                                "server does not support extended master secret");
+            }
+            self.secure_renegotiation = server_renegotiation_info;
+            self.alpn_protocol = alpn_protocol;
+
             // FIXME: check if server sent unknown extension
             // it is currently done by just not understanding any extensions
             // other than we used.
         }
+        let cipher_suite = server_hello_data.cipher_suite;
+        let serv_random = server_hello_data.random.to_vec();
+        let serv_session_id = server_hello_data.session_id.to_vec();
+
+        // the server accepts resumption by echoing the session id.
+        let resumed_session = match cached_session {
+            Some(session) => {
+                if serv_session_id.len() > 0 && serv_session_id == session_id {
+                    // RFC 5246 7.4.1.3: the resumed session keeps its cipher suite.
+                    if session.cipher_suite != cipher_suite {
+                        return tls_err!(IllegalParameter,
+                                        "cipher suite changed on resumption: {:?}",
+                                        cipher_suite);
+                    }
+                    // RFC 7627 5.3: the resumed session must agree on extended master secret.
+                    if session.extended_master_secret != server_ems {
+                        return tls_err!(HandshakeFailure,
+                                        "extended master secret changed on resumption");
+                    }
+                    Some(session)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
 
-        // we always expect certificate.
-        let certificate_list = expect!(certificate);
-        // TODO: cert validation not implemented yet
+        let aead = cipher_suite.new_aead();
+        let prf_hash = cipher_suite.prf_hash();
 
-        // we always use server key exchange
-        let server_key_ex_data = expect!(server_key_exchange);
-        let kex = cipher_suite.new_kex();
-        let (key_data, pre_master_secret) = try!(kex.compute_keys(&*server_key_ex_data,
-                                                                  &mut self.tls.rng));
+        // a resumed session keeps its issue time.
+        let issued = match resumed_session {
+            Some(ref session) => session.issued,
+            None => time::get_time().sec,
+        };
 
-        expect!(server_hello_done);
+        // SECRET
+        let (master_secret, ticket) = match resumed_session {
+            Some(session) => {
+                // abbreviated handshake: the server sends ChangeCipherSpec and Finished first.
+                let master_secret = session.master_secret;
+
+                // SECRET
+                let key_block = prf::key_block(prf_hash,
+                                               &master_secret[],
+                                               &cli_random[],
+                                               &serv_random[],
+                                               aead.key_size(),
+                                               aead.fixed_iv_len());
+
+                // the server may renew the ticket. otherwise we keep using the current one.
+                let ticket = if expect_ticket {
+                    try!(self.read_new_session_ticket())
+                } else {
+                    session.ticket
+                };
 
-        let client_key_exchange = try!(Handshake::new_client_key_exchange(key_data));
-        try!(self.tls.writer.write_handshake(&client_key_exchange));
+                try!(self.tls.reader.read_change_cipher_spec());
+                self.tls.reader.set_decryptor(aead.new_decryptor(key_block.server_write_key,
+                                                                 key_block.server_write_iv));
+
+                let server_verify_data = prf::verify_data(prf_hash,
+                                                          &master_secret[],
+                                                          b"server finished",
+                                                          &self.tls.handshake_hash(prf_hash)[],
+                                                          cipher_suite.verify_data_len());
+                let server_finished = expect!(self.tls, finished);
+                if !crypto_compare(&server_finished[], &server_verify_data[]) {
+                    return tls_err!(DecryptError, "server sent wrong verify data");
+                }
 
-        try!(self.tls.writer.write_change_cipher_spec());
+                try!(self.tls.writer.write_change_cipher_spec());
+                self.tls.writer.set_encryptor(aead.new_encryptor(key_block.client_write_key,
+                                                                 key_block.client_write_iv));
 
-        // SECRET
-        let master_secret = {
-            let mut label_seed = b"master secret".to_vec();
-            label_seed.push_all(&*cli_random);
-            label_seed.push_all(&*server_hello_data.random);
+                let client_verify_data = prf::verify_data(prf_hash,
+                                                          &master_secret[],
+                                                          b"client finished",
+                                                          &self.tls.handshake_hash(prf_hash)[],
+                                                          cipher_suite.verify_data_len());
+                let finished = try!(Handshake::new_finished(client_verify_data.clone()));
+                try!(self.tls.writer.write_handshake(&finished));
+
+                self.client_verify_data = client_verify_data;
+                self.server_verify_data = server_verify_data;
 
-            let mut prf = Prf::new(pre_master_secret, label_seed);
-            prf.get_bytes(48)
+                (master_secret, ticket)
+            }
+            None => try!(self.full_handshake(cipher_suite,
+                                             &cli_random[],
+                                             &serv_random[],
+                                             expect_ticket,
+                                             server_ems)),
         };
 
-        let aead = cipher_suite.new_aead();
+        // remember the session for the next connection.
+        match (&self.config.session_store, &self.server_name) {
+            (&Some(ref store), &Some(ref name)) => {
+                if serv_session_id.len() > 0 || ticket.is_some() {
+                    let session = Session {
+                        session_id: serv_session_id,
+                        cipher_suite: cipher_suite,
+                        master_secret: master_secret,
+                        extended_master_secret: server_ems,
+                        ticket: ticket,
+                        issued: issued,
+                    };
+                    store.put(name, session);
+                } else {
+                    store.remove(name);
+                }
+            }
+            _ => {}
+        }
 
-        // SECRET
-        let read_key = {
-            let mut label_seed = b"key expansion".to_vec();
-            label_seed.push_all(&*server_hello_data.random);
-            label_seed.push_all(&*cli_random);
+        Ok(())
+    }
 
-            let mut prf = Prf::new(master_secret.clone(), label_seed);
+    // the rest of the full handshake after ServerHello.
+    // return the master secret and the new session ticket if any.
+    // SECRET
+    fn full_handshake(&mut self,
+                      cipher_suite: cipher::CipherSuite,
+                      cli_random: &[u8],
+                      serv_random: &[u8],
+                      expect_ticket: bool,
+                      extended_master_secret: bool) -> TlsResult<(Vec<u8>, Option<Vec<u8>>)> {
+        // PSK suites have no certificate. the key authenticates the server.
+        let psk_suite = cipher_suite.signature_algorithm() == SignatureAlgorithm::anonymous;
+        let prf_hash = cipher_suite.prf_hash();
+
+        let mut certs = Vec::new();
+        if !psk_suite {
+            let certificate_list = expect!(self.tls, certificate);
+            for cert in certificate_list.iter() {
+                certs.push(try!(Certificate::parse(&cert[])));
+            }
+            let now = time::get_time().sec;
+            try!(self.config.trust_store.verify_chain(&certs[], OID_SERVER_AUTH, now));
+            match self.server_name {
+                Some(ref name) => try!(name.verify(&certs[0])),
+                None => {}
+            }
+        }
 
-            // mac_key is not used in AEAD configuration.
+        // ServerKeyExchange is signed by the leaf certificate's key
+        let server_key = match certs.get(0) {
+            Some(cert) => Some(&cert.public_key),
+            None if psk_suite => None,
+            None => return tls_err!(BadCertificate, "server sent no certificate"),
+        };
+        match server_key {
+            Some(server_key) if server_key.signature_algorithm() !=
+                                cipher_suite.signature_algorithm() => {
+                return tls_err!(UnsupportedCertificate,
+                                "{:?} key cannot be used with {:?}",
+                                server_key.signature_algorithm(),
+                                cipher_suite);
+            }
+            _ => {}
+        }
 
-            let enc_key_length = aead.key_size();
+        // ServerKeyExchange may be omitted only by plain PSK (RFC 4279 2.)
+        let (server_key_ex_data, next_msg) = match try!(self.tls.reader.read_handshake()) {
+            Handshake::server_key_exchange(data) => {
+                (Some(data), try!(self.tls.reader.read_handshake()))
+            }
+            msg if psk_suite => (None, msg),
+            _ => return tls_err!(UnexpectedMessage, "unexpected handshake message found"),
+        };
+        let kex = cipher_suite.new_kex();
+        let (key_data, pre_master_secret) = {
+            let data: &[u8] = match server_key_ex_data {
+                Some(ref data) => &**data,
+                None => &[],
+            };
+            try!(kex.compute_keys(data,
+                                  cli_random,
+                                  serv_random,
+                                  server_key,
+                                  &self.config.elliptic_curves[],
+                                  &self.config.signature_algorithms[],
+                                  self.config.psk_callback.as_ref().map(|psk| &**psk),
+                                  &mut self.tls.rng))
+        };
 
-            let write_key = prf.get_bytes(enc_key_length);
-            let encryptor = aead.new_encryptor(write_key);
-            self.tls.writer.set_encryptor(encryptor);
+        // the server may request a client certificate.
+        let certificate_request = match next_msg {
+            Handshake::certificate_request(request) => {
+                expect!(self.tls, server_hello_done);
+                Some(request)
+            }
+            Handshake::server_hello_done(..) => None,
+            _ => return tls_err!(UnexpectedMessage, "unexpected handshake message found"),
+        };
 
-            // this will be set after receiving ChangeCipherSpec.
-            let read_key = prf.get_bytes(enc_key_length);
+        // if we have no suitable certificate, an empty list is sent
+        // and the server decides whether to continue.
+        let client_cert = match certificate_request {
+            Some(ref request) => {
+                let client_cert = try!(choose_client_certificate(&self.config, request));
+                let certs = match client_cert {
+                    Some((ref cert, _)) => cert.certificates.clone(),
+                    None => Vec::new(),
+                };
+                let certificate = try!(Handshake::new_certificate(certs));
+                try!(self.tls.writer.write_handshake(&certificate));
+                client_cert
+            }
+            None => None,
+        };
 
-            // chacha20-poly1305 does not use iv.
+        let client_key_exchange = try!(Handshake::new_client_key_exchange(key_data));
+        try!(self.tls.writer.write_handshake(&client_key_exchange));
 
-            read_key
+        // RFC 7627 3: the session hash covers the messages up to ClientKeyExchange.
+        let master_secret = if extended_master_secret {
+            let session_hash = self.tls.handshake_hash(prf_hash);
+            prf::extended_master_secret(prf_hash,
+                                        pre_master_secret,
+                                        &session_hash[])
+        } else {
+            prf::master_secret(prf_hash,
+                               pre_master_secret,
+                               cli_random,
+                               serv_random)
         };
 
-        // FIXME we should get "raw" packet data and hash them incrementally
-        let msgs = {
-            let mut msgs = Vec::new();
-            try!(client_hello.tls_write(&mut msgs));
-            try!(Handshake::server_hello(server_hello_data).tls_write(&mut msgs));
-            try!(Handshake::certificate(certificate_list).tls_write(&mut msgs));
-            try!(Handshake::server_key_exchange(server_key_ex_data).tls_write(&mut msgs));
-            try!(Handshake::server_hello_done(DummyItem).tls_write(&mut msgs));
-            try!(client_key_exchange.tls_write(&mut msgs));
-            msgs
-        };
+        // CertificateVerify signs every handshake message so far.
+        match client_cert {
+            Some((ref cert, ref algorithm)) => {
+                let msgs = self.tls.handshake_messages();
+                let signed = try!(cert.private_key.sign(algorithm, &msgs[], &mut self.tls.rng));
+                let certificate_verify = Handshake::certificate_verify(signed);
+                try!(self.tls.writer.write_handshake(&certificate_verify));
+            }
+            None => {}
+        }
+
+        let aead = cipher_suite.new_aead();
+
+        // SECRET
+        let key_block = prf::key_block(prf_hash,
+                                       &master_secret[],
+                                       cli_random,
+                                       serv_random,
+                                       aead.key_size(),
+                                       aead.fixed_iv_len());
+
+        try!(self.tls.writer.write_change_cipher_spec());
+        self.tls.writer.set_encryptor(aead.new_encryptor(key_block.client_write_key,
+                                                         key_block.client_write_iv));
 
         // this only verifies Handshake messages! what about others?
         // ApplicationData messages are not permitted until now.
@@ -166,56 +644,88 @@ impl<R: Reader, W: Writer> TlsClient<R, W> {
         // can be broken into several records. This leads to alert attack.
         // since we don't accept strange alerts, all "normal" alert messages are
         // treated as error, so now we can assert that we haven't received alerts.
-        let verify_hash = sha256(&msgs[]);
-
-        let client_verify_data = {
-            let finished_label = b"client finished";
+        let client_verify_data = prf::verify_data(prf_hash,
+                                                  &master_secret[],
+                                                  b"client finished",
+                                                  &self.tls.handshake_hash(prf_hash)[],
+                                                  cipher_suite.verify_data_len());
+        let finished = try!(Handshake::new_finished(client_verify_data.clone()));
+        try!(self.tls.writer.write_handshake(&finished));
 
-            let mut label_seed = finished_label.to_vec();
-            label_seed.push_all(&verify_hash[]);
-            let mut prf = Prf::new(master_secret.clone(), label_seed);
-            prf.get_bytes(cipher_suite.verify_data_len())
+        let ticket = if expect_ticket {
+            try!(self.read_new_session_ticket())
+        } else {
+            None
         };
-        let finished = try!(Handshake::new_finished(client_verify_data));
-        try!(self.tls.writer.write_handshake(&finished));
 
         // Although client->server is encrypted, server->client isn't yet.
         // server may send either ChangeCipherSpec or Alert.
         try!(self.tls.reader.read_change_cipher_spec());
 
         // from now server starts encryption.
-        self.tls.reader.set_decryptor(aead.new_decryptor(read_key));
+        self.tls.reader.set_decryptor(aead.new_decryptor(key_block.server_write_key,
+                                                         key_block.server_write_iv));
+
+        let server_verify_data = prf::verify_data(prf_hash,
+                                                  &master_secret[],
+                                                  b"server finished",
+                                                  &self.tls.handshake_hash(prf_hash)[],
+                                                  cipher_suite.verify_data_len());
+        let server_finished = expect!(self.tls, finished);
+        if !crypto_compare(&server_finished[], &server_verify_data[]) {
+            return tls_err!(DecryptError, "server sent wrong verify data");
+        }
 
-        let server_finished = expect!(finished);
-        {
-            let verify_hash = {
-                // ideally we may save "raw" packet data..
-                let mut serv_msgs = Vec::new();
-                // FIXME: this should not throw "io error".. should throw "internal error"
-                try!(serv_msgs.write(&msgs[]));
-                try!(finished.tls_write(&mut serv_msgs));
-
-                let verify_hash = sha256(&serv_msgs[]);
-                verify_hash
-            };
+        self.client_verify_data = client_verify_data;
+        self.server_verify_data = server_verify_data;
 
-            let server_verify_data = {
-                let finished_label = b"server finished";
+        Ok((master_secret, ticket))
+    }
 
-                let mut label_seed = finished_label.to_vec();
-                label_seed.push_all(&verify_hash[]);
-                let mut prf = Prf::new(master_secret, label_seed);
-                prf.get_bytes(cipher_suite.verify_data_len())
-            };
+    // NewSessionTicket comes right before the server's ChangeCipherSpec.
+    // an empty ticket means the server could not issue one.
+    fn read_new_session_ticket(&mut self) -> TlsResult<Option<Vec<u8>>> {
+        let new_ticket = expect!(self.tls, new_session_ticket);
+        let ticket = if new_ticket.ticket.len() > 0 {
+            Some(new_ticket.ticket.to_vec())
+        } else {
+            None
+        };
+        Ok(ticket)
+    }
+
+    // the application protocol selected by the server (RFC 7301), if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_ref().map(|protocol| &protocol[])
+    }
 
-            let verify_ok = crypto_compare(&server_finished[],
-                                           &server_verify_data[]);
-            if !verify_ok {
-                return tls_err!(DecryptError, "server sent wrong verify data");
+    // read ApplicationData, answering HelloRequest in between.
+    fn read_application_data(&mut self) -> TlsResult<Vec<u8>> {
+        loop {
+            let result = match try!(self.tls.reader.read_application_data()) {
+                Message::ApplicationDataMessage(data) => return Ok(data),
+                Message::HandshakeMessage(Handshake::hello_request(..)) => {
+                    self.handle_hello_request()
+                }
+                Message::HandshakeMessage(..) => {
+                    tls_err!(UnexpectedMessage, "unexpected handshake message found")
+                }
+                // warning alerts are ignored.
+                _ => Ok(()),
+            };
+            match result {
+                Ok(()) => {}
+                Err(err) => return Err(self.tls.send_tls_alert(err)),
             }
         }
+    }
 
-        Ok(())
+    // the new handshake runs under the current keys, and its keys replace them.
+    fn handle_hello_request(&mut self) -> TlsResult<()> {
+        if !self.config.allow_renegotiation || !self.secure_renegotiation {
+            return self.tls.refuse_renegotiation();
+        }
+        self.handshake()
     }
 
     pub fn close(&mut self) -> TlsResult<()> {
@@ -223,8 +733,57 @@ impl<R: Reader, W: Writer> TlsClient<R, W> {
     }
 }
 
+// pick the first certificate whose key type and signature algorithm the server accepts.
+// if `certificate_authorities` is not empty, some certificate of the chain must be issued
+// by one of them.
+// return the certificate and the algorithm to sign CertificateVerify with.
+fn choose_client_certificate(config: &ClientConfig,
+                             request: &handshake::CertificateRequest)
+                             -> TlsResult<Option<(ClientCertificate, SignatureAndHashAlgorithm)>> {
+    for cert in config.client_certificates.iter() {
+        let certificate_type = match cert.private_key.signature_algorithm() {
+            SignatureAlgorithm::rsa => handshake::ClientCertificateType::rsa_sign,
+            SignatureAlgorithm::ecdsa => handshake::ClientCertificateType::ecdsa_sign,
+            _ => continue,
+        };
+        if !request.certificate_types.iter().any(|ty| *ty == certificate_type) {
+            continue;
+        }
+
+        let offered = &request.supported_signature_algorithms[];
+        let algorithm = match cert.private_key.choose_signature_algorithm(offered) {
+            Some(algorithm) => algorithm,
+            None => continue,
+        };
+
+        if request.certificate_authorities.len() > 0 {
+            let mut issued = false;
+            for der in cert.certificates.iter() {
+                let issuer = match Certificate::parse(&der[]) {
+                    Ok(parsed) => parsed.issuer,
+                    Err(err) => {
+                        return tls_err!(InternalError, "invalid client certificate: {}", err.desc);
+                    }
+                };
+                if request.certificate_authorities.iter().any(|ca| &**ca == &issuer.der[]) {
+                    issued = true;
+                    break;
+                }
+            }
+            if !issued {
+                continue;
+            }
+        }
+
+        return Ok(Some((cert.clone(), algorithm)));
+    }
+    Ok(None)
+}
+
 impl TlsClient<TcpStream, TcpStream> {
-    pub fn from_tcp(stream: TcpStream) -> TlsResult<TlsClient<TcpStream, TcpStream>> {
+    // see `TlsClient::new`.
+    pub fn from_tcp(stream: TcpStream,
+                    config: ClientConfig) -> TlsResult<TlsClient<TcpStream, TcpStream>> {
         let rng = match OsRng::new() {
             Ok(rng) => rng,
             Err(..) => return tls_err!(InternalError, "failed to create OsRng"),
@@ -232,7 +791,22 @@ impl TlsClient<TcpStream, TcpStream> {
 
         let reader = stream.clone();
         let writer = stream;
-        TlsClient::new(reader, writer, rng)
+        TlsClient::new(reader, writer, rng, config)
+    }
+
+    // `hostname` is a DNS name or an IP address literal.
+    pub fn from_tcp_with_hostname(stream: TcpStream,
+                                  hostname: &str,
+                                  config: ClientConfig)
+                                  -> TlsResult<TlsClient<TcpStream, TcpStream>> {
+        let rng = match OsRng::new() {
+            Ok(rng) => rng,
+            Err(..) => return tls_err!(InternalError, "failed to create OsRng"),
+        };
+
+        let reader = stream.clone();
+        let writer = stream;
+        TlsClient::new_with_server_name(reader, writer, rng, config, ServerName::new(hostname))
     }
 }
 
@@ -263,7 +837,7 @@ impl<R: Reader, W: Writer> Reader for TlsClient<R, W> {
         while pos < len {
             let remaining = len - pos;
             if self.buf.len() == 0 {
-                let data = match self.tls.reader.read_application_data() {
+                let data = match self.read_application_data() {
                     Ok(data) => data,
                     Err(_err) => {
                         break; // FIXME: stop if EOF. otherwise raise error?
@@ -283,3 +857,60 @@ impl<R: Reader, W: Writer> Reader for TlsClient<R, W> {
         Ok(pos)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use handshake::{CertificateRequest, CertificiateTypeVec, ClientCertificateType,
+                    DistinguishedName, DistinguishedNameVec};
+    use signature::{PrivateKey, SignatureAndHashAlgorithm, SignatureAndHashAlgorithmVec,
+                    HashAlgorithm, SignatureAlgorithm};
+    use certificate::Certificate;
+    use trust_store::TrustStore;
+    use super::{ClientConfig, choose_client_certificate};
+
+    static INTER: &'static [u8] = include_bytes!("test_data/inter.der");
+    static LEAF_RSA: &'static [u8] = include_bytes!("test_data/leaf_rsa.der");
+    static LEAF_RSA_KEY: &'static [u8] = include_bytes!("test_data/leaf_rsa_key.der");
+
+    fn request(hashes: &[HashAlgorithm], authorities: Vec<Vec<u8>>) -> CertificateRequest {
+        let algorithms = hashes.iter().map(|&hash| {
+            SignatureAndHashAlgorithm {
+                hash: hash,
+                signature: SignatureAlgorithm::rsa,
+            }
+        }).collect();
+        let authorities = authorities.into_iter().map(|name| {
+            DistinguishedName::new(name).unwrap()
+        }).collect();
+        let types = vec!(ClientCertificateType::rsa_sign);
+        CertificateRequest {
+            certificate_types: CertificiateTypeVec::new(types).unwrap(),
+            supported_signature_algorithms: SignatureAndHashAlgorithmVec::new(algorithms).unwrap(),
+            certificate_authorities: DistinguishedNameVec::new(authorities).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_choose_client_certificate() {
+        let mut config = ClientConfig::new(TrustStore::new());
+        let key = PrivateKey::from_der(LEAF_RSA_KEY).unwrap();
+        config.add_client_certificate(vec!(LEAF_RSA.to_vec(), INTER.to_vec()), key);
+
+        // the hash is taken from the request.
+        let req = request(&[HashAlgorithm::sha384], Vec::new());
+        let (_, algorithm) = choose_client_certificate(&config, &req).unwrap().unwrap();
+        assert!(algorithm.hash == HashAlgorithm::sha384);
+        let req = request(&[HashAlgorithm::md5], Vec::new());
+        assert!(choose_client_certificate(&config, &req).unwrap().is_none());
+
+        // the intermediate or the root issued one of the chain.
+        let leaf = Certificate::parse(LEAF_RSA).unwrap();
+        let inter = Certificate::parse(INTER).unwrap();
+        for issuer in [leaf.issuer.der.clone(), inter.issuer.der.clone()].iter() {
+            let req = request(&[HashAlgorithm::sha256], vec!(issuer.clone()));
+            assert!(choose_client_certificate(&config, &req).unwrap().is_some());
+        }
+        let req = request(&[HashAlgorithm::sha256], vec!(leaf.subject.der.clone()));
+        assert!(choose_client_certificate(&config, &req).unwrap().is_none());
+    }
+}
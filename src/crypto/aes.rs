@@ -0,0 +1,164 @@
+// AES block cipher (FIPS 197), encryption only.
+// GCM and other counter modes never need the inverse cipher.
+//
+// lookup tables leak the index through cache timing, so the S-box is computed
+// from the inverse in GF(2^8) without tables or secret-dependent branches.
+// this is slow but constant-time.
+
+const BLOCK_LEN: usize = 16;
+
+// multiply by x in GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1.
+#[inline(always)]
+fn xtime(a: u8) -> u8 {
+    (a << 1) ^ (0x1b * (a >> 7))
+}
+
+// SECRET
+fn gf_mult(a: u8, b: u8) -> u8 {
+    let mut a = a;
+    let mut r = 0u8;
+    for i in (0us..8) {
+        r ^= a * ((b >> i) & 1);
+        a = xtime(a);
+    }
+    r
+}
+
+// a^254, which is a^-1 for nonzero a and 0 for a == 0.
+// SECRET
+fn gf_inverse(a: u8) -> u8 {
+    // 254 = 0b11111110
+    let mut r = 1u8;
+    for i in (0us..8).rev() {
+        r = gf_mult(r, r);
+        if (254 >> i) & 1 == 1 {
+            r = gf_mult(r, a);
+        }
+    }
+    r
+}
+
+// SECRET
+fn sub_byte(a: u8) -> u8 {
+    let b = gf_inverse(a);
+    b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4) ^ 0x63
+}
+
+pub struct Aes {
+    // SECRET
+    round_keys: Vec<[u8; BLOCK_LEN]>,
+}
+
+impl Aes {
+    // key: SECRET. 16, 24 or 32 bytes.
+    pub fn new(key: &[u8]) -> Aes {
+        let nk = key.len() / 4;
+        assert!(key.len() % 4 == 0 && nk >= 4 && nk <= 8 && nk % 2 == 0);
+        let rounds = nk + 6;
+
+        // key expansion (FIPS 197 5.2)
+        let mut words: Vec<[u8; 4]> = Vec::with_capacity(4 * (rounds + 1));
+        for i in (0us..nk) {
+            words.push([key[4 * i], key[4 * i + 1], key[4 * i + 2], key[4 * i + 3]]);
+        }
+        let mut rcon = 1u8;
+        for i in (nk..(4 * (rounds + 1))) {
+            let mut w = words[i - 1];
+            if i % nk == 0 {
+                w = [sub_byte(w[1]) ^ rcon, sub_byte(w[2]), sub_byte(w[3]), sub_byte(w[0])];
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                w = [sub_byte(w[0]), sub_byte(w[1]), sub_byte(w[2]), sub_byte(w[3])];
+            }
+            let prev = words[i - nk];
+            words.push([prev[0] ^ w[0], prev[1] ^ w[1], prev[2] ^ w[2], prev[3] ^ w[3]]);
+        }
+
+        let mut round_keys = Vec::with_capacity(rounds + 1);
+        for round in (0us..(rounds + 1)) {
+            let mut round_key = [0u8; BLOCK_LEN];
+            for i in (0us..BLOCK_LEN) {
+                round_key[i] = words[4 * round + i / 4][i % 4];
+            }
+            round_keys.push(round_key);
+        }
+
+        Aes {
+            round_keys: round_keys,
+        }
+    }
+
+    // the state is stored column by column, as the input block.
+    pub fn encrypt_block(&self, block: &[u8]) -> [u8; BLOCK_LEN] {
+        assert_eq!(block.len(), BLOCK_LEN);
+
+        let rounds = self.round_keys.len() - 1;
+        let mut state = [0u8; BLOCK_LEN];
+        for i in (0us..BLOCK_LEN) {
+            state[i] = block[i] ^ self.round_keys[0][i];
+        }
+
+        for round in (1us..(rounds + 1)) {
+            // SubBytes and ShiftRows: row r is rotated left by r columns.
+            let mut shifted = [0u8; BLOCK_LEN];
+            for col in (0us..4) {
+                for row in (0us..4) {
+                    shifted[4 * col + row] = sub_byte(state[4 * ((col + row) % 4) + row]);
+                }
+            }
+            state = shifted;
+
+            if round != rounds {
+                for col in (0us..4) {
+                    let a0 = state[4 * col];
+                    let a1 = state[4 * col + 1];
+                    let a2 = state[4 * col + 2];
+                    let a3 = state[4 * col + 3];
+                    let all = a0 ^ a1 ^ a2 ^ a3;
+                    state[4 * col] = a0 ^ all ^ xtime(a0 ^ a1);
+                    state[4 * col + 1] = a1 ^ all ^ xtime(a1 ^ a2);
+                    state[4 * col + 2] = a2 ^ all ^ xtime(a2 ^ a3);
+                    state[4 * col + 3] = a3 ^ all ^ xtime(a3 ^ a0);
+                }
+            }
+
+            for i in (0us..BLOCK_LEN) {
+                state[i] ^= self.round_keys[round][i];
+            }
+        }
+
+        state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Aes, sub_byte};
+
+    #[test]
+    fn test_sub_byte() {
+        assert_eq!(sub_byte(0x00), 0x63);
+        assert_eq!(sub_byte(0x01), 0x7c);
+        assert_eq!(sub_byte(0x53), 0xed);
+        assert_eq!(sub_byte(0xff), 0x16);
+    }
+
+    #[test]
+    fn test_encrypt_block() {
+        // FIPS 197 Appendix C
+        let plain = b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99\xaa\xbb\xcc\xdd\xee\xff";
+        let key: Vec<u8> = (0u8..32).collect();
+
+        let aes128 = Aes::new(&key[..16]);
+        assert_eq!(&aes128.encrypt_block(plain)[],
+                   b"\x69\xc4\xe0\xd8\x6a\x7b\x04\x30\xd8\xcd\xb7\x80\x70\xb4\xc5\x5a");
+
+        let aes192 = Aes::new(&key[..24]);
+        assert_eq!(&aes192.encrypt_block(plain)[],
+                   b"\xdd\xa9\x7c\xa4\x86\x4c\xdf\xe0\x6e\xaf\x70\xa0\xec\x0d\x71\x91");
+
+        let aes256 = Aes::new(&key[]);
+        assert_eq!(&aes256.encrypt_block(plain)[],
+                   b"\x8e\xa2\xb7\xca\x51\x67\x45\xbf\xea\xfc\x49\x90\x4b\x49\x60\x89");
+    }
+}
@@ -0,0 +1,293 @@
+// constant-time modular exponentiation.
+// the modulus is public, but bases and exponents are SECRET:
+// neither branches nor memory access depend on them.
+// numbers are big-endian byte strings outside of this module.
+
+use std::cmp::Ordering;
+use std::iter::repeat;
+
+// window size of the exponentiation in bits
+const WINDOW: usize = 4;
+
+// odd modulus n with Montgomery parameters. R = 2^(32 k) where k is the limb count.
+pub struct Modulus {
+    // little-endian 2^32-radix limbs
+    n: Vec<u32>,
+    // byte length of n without leading zeros
+    len: usize,
+    // -n^-1 mod 2^32
+    n0_inv: u32,
+    // R^2 mod n
+    r2: Vec<u32>,
+}
+
+impl Modulus {
+    // return None if `n` is even or less than 3.
+    pub fn new(n: &[u8]) -> Option<Modulus> {
+        let n = strip_zeros(n);
+        if n.len() == 0 || n[n.len() - 1] & 1 == 0 || compare(n, &[1]) != Ordering::Greater {
+            return None;
+        }
+
+        let k = (n.len() + 3) / 4;
+        let n_limbs = from_bytes(n, k);
+
+        // Newton's method: each step doubles the number of correct low bits.
+        let n0 = n_limbs[0] as u64;
+        let mut inv = 1u64;
+        for _ in (0us..5) {
+            let t = (n0 * inv) & 0xffffffff;
+            let t = ((1u64 << 32) + 2 - t) & 0xffffffff;
+            inv = (inv * t) & 0xffffffff;
+        }
+        let n0_inv = ((1u64 << 32) - inv) as u32;
+
+        let mut modulus = Modulus {
+            n: n_limbs,
+            len: n.len(),
+            n0_inv: n0_inv,
+            r2: Vec::new(),
+        };
+
+        // R^2 mod n by doubling 1 (2 * 32 * k) times
+        let mut r2 = zeros(k);
+        r2[0] = 1;
+        for _ in (0us..(64 * k)) {
+            r2 = modulus.double_mod(&r2[]);
+        }
+        modulus.r2 = r2;
+
+        Some(modulus)
+    }
+
+    // byte length of the modulus.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // bit length of the modulus.
+    pub fn bits(&self) -> usize {
+        let top = self.to_bytes(&self.n[])[0];
+        let mut top_bits = 0us;
+        while top_bits < 8 && (top >> top_bits) != 0 {
+            top_bits += 1;
+        }
+        (self.len - 1) * 8 + top_bits
+    }
+
+    // SECRET
+    // return `base^e mod n`, zero-padded to `self.len()` bytes.
+    // None if `base` is not less than n. only the length of `e` is leaked.
+    pub fn mod_exp(&self, base: &[u8], e: &[u8]) -> Option<Vec<u8>> {
+        if compare(base, &self.to_bytes(&self.n[])[]) != Ordering::Less {
+            return None;
+        }
+        let k = self.n.len();
+
+        let mut one = zeros(k);
+        one[0] = 1;
+
+        // table[i] = base^i in the Montgomery form
+        let mut table = Vec::with_capacity(1 << WINDOW);
+        table.push(self.mult(&one[], &self.r2[]));
+        table.push(self.mult(&from_bytes(strip_zeros(base), k)[], &self.r2[]));
+        for i in (2us..(1 << WINDOW)) {
+            let next = self.mult(&table[i - 1][], &table[1][]);
+            table.push(next);
+        }
+
+        let mut x = table[0].clone();
+        for byte in e.iter() {
+            for &shift in [4us, 0].iter() {
+                for _ in (0us..WINDOW) {
+                    x = self.mult(&x[], &x[]);
+                }
+                let y = select(&table[], ((*byte >> shift) & 0xf) as usize);
+                x = self.mult(&x[], &y[]);
+            }
+        }
+
+        let x = self.mult(&x[], &one[]);
+        Some(self.to_bytes(&x[]))
+    }
+
+    fn to_bytes(&self, a: &[u32]) -> Vec<u8> {
+        let mut b = Vec::with_capacity(self.len);
+        for i in (0us..self.len).rev() {
+            b.push((a[i / 4] >> ((i % 4) * 8)) as u8);
+        }
+        b
+    }
+
+    // return `a - n` if `a (+ 2^(32 k) * carry) >= n`, otherwise `a`.
+    fn reduce_once(&self, a: &[u32], carry: u32) -> Vec<u32> {
+        let k = self.n.len();
+        let mut sub = zeros(k);
+        let mut borrow = 0u64;
+        for i in (0us..k) {
+            let d = (a[i] as u64) - (self.n[i] as u64) - borrow;
+            sub[i] = d as u32;
+            borrow = d >> 63;
+        }
+        // take `sub` if carry == 1 or borrow == 0
+        let mask = 0u32 - (carry | (1 - borrow as u32));
+        let mut ret = zeros(k);
+        for i in (0us..k) {
+            ret[i] = (sub[i] & mask) | (a[i] & !mask);
+        }
+        ret
+    }
+
+    // precondition: a < n
+    fn double_mod(&self, a: &[u32]) -> Vec<u32> {
+        let k = self.n.len();
+        let mut d = zeros(k);
+        let mut carry = 0u32;
+        for i in (0us..k) {
+            d[i] = (a[i] << 1) | carry;
+            carry = a[i] >> 31;
+        }
+        self.reduce_once(&d[], carry)
+    }
+
+    // return a * b / R mod n
+    // precondition: a < n, b < n
+    fn mult(&self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        let k = self.n.len();
+        let mut t = zeros(k + 2);
+
+        for i in (0us..k) {
+            // t += a * b[i]
+            let mut c = 0u64;
+            for j in (0us..k) {
+                let s = (t[j] as u64) + (a[j] as u64) * (b[i] as u64) + c;
+                t[j] = s as u32;
+                c = s >> 32;
+            }
+            let s = (t[k] as u64) + c;
+            t[k] = s as u32;
+            t[k + 1] = (s >> 32) as u32;
+
+            // t = (t + m * n) / 2^32
+            let m = ((t[0] as u64) * (self.n0_inv as u64)) & 0xffffffff;
+            let s = (t[0] as u64) + m * (self.n[0] as u64);
+            let mut c = s >> 32;
+            for j in (1us..k) {
+                let s = (t[j] as u64) + m * (self.n[j] as u64) + c;
+                t[j - 1] = s as u32;
+                c = s >> 32;
+            }
+            let s = (t[k] as u64) + c;
+            t[k - 1] = s as u32;
+            t[k] = t[k + 1] + ((s >> 32) as u32);
+            t[k + 1] = 0;
+        }
+
+        // t < 2n
+        let carry = t[k];
+        self.reduce_once(&t[..k], carry)
+    }
+}
+
+// SECRET: `idx`
+// return table[idx] reading every entry.
+fn select(table: &[Vec<u32>], idx: usize) -> Vec<u32> {
+    let k = table[0].len();
+    let mut ret = zeros(k);
+    for (i, entry) in table.iter().enumerate() {
+        // all ones if i == idx
+        let mask = (((i ^ idx) as u64) - 1) >> 32;
+        let mask = mask as u32;
+        for j in (0us..k) {
+            ret[j] |= entry[j] & mask;
+        }
+    }
+    ret
+}
+
+// big-endian to `k` limbs.
+// precondition: the value fits in `k` limbs.
+fn from_bytes(b: &[u8], k: usize) -> Vec<u32> {
+    let mut v = zeros(k);
+    for (pos, byte) in b.iter().rev().enumerate() {
+        v[pos / 4] |= (*byte as u32) << ((pos % 4) * 8);
+    }
+    v
+}
+
+fn zeros(len: usize) -> Vec<u32> {
+    repeat(0u32).take(len).collect()
+}
+
+// remove leading zero bytes.
+pub fn strip_zeros(b: &[u8]) -> &[u8] {
+    let mut i = 0;
+    while i < b.len() && b[i] == 0 {
+        i += 1;
+    }
+    &b[i..]
+}
+
+// compare big-endian numbers. this is not constant-time: use only for public values.
+pub fn compare(a: &[u8], b: &[u8]) -> Ordering {
+    let a = strip_zeros(a);
+    let b = strip_zeros(b);
+    match a.len().cmp(&b.len()) {
+        Ordering::Equal => a.cmp(b),
+        ord => ord,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+    use std::iter::repeat;
+    use super::{Modulus, compare};
+
+    #[test]
+    fn test_mod_exp() {
+        // 4^13 mod 497 == 445
+        let n = Modulus::new(&[0x01, 0xf1]).unwrap();
+        assert_eq!(n.mod_exp(&[4], &[13]).unwrap(), vec!(0x01, 0xbd));
+        // leading zeros are allowed in both base and exponent
+        assert_eq!(n.mod_exp(&[0, 0, 0, 0, 0, 4], &[0, 13]).unwrap(), vec!(0x01, 0xbd));
+        assert_eq!(n.mod_exp(&[4], &[]).unwrap(), vec!(0x00, 0x01));
+
+        // (2^64 - 1)^3 mod (2^89 - 1)
+        let n = Modulus::new(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                               0xff, 0xff, 0xff, 0xff, 0xff]).unwrap();
+        assert_eq!(n.len(), 12);
+        let expected = vec!(0x00, 0x00, 0x00, 0x02, 0xff, 0xff, 0xfe, 0x80, 0x00, 0x00,
+                            0x3f, 0xff);
+        assert_eq!(n.mod_exp(&[0xff; 8], &[3]).unwrap(), expected);
+
+        // Fermat: 2^(p - 1) == 1 mod p for p = 2^127 - 1
+        let mut p = vec!(0x7f);
+        p.push_all(&[0xff; 15]);
+        let n = Modulus::new(&p[]).unwrap();
+        let mut e = p.clone();
+        e[15] = 0xfe;
+        let mut one: Vec<u8> = repeat(0u8).take(16).collect();
+        one[15] = 1;
+        assert_eq!(n.mod_exp(&[2], &e[]).unwrap(), one);
+
+        // the base must be reduced
+        assert!(n.mod_exp(&p[], &[1]).is_none());
+    }
+
+    #[test]
+    fn test_modulus() {
+        assert!(Modulus::new(&[0x01, 0xf0]).is_none());
+        assert!(Modulus::new(&[0, 1]).is_none());
+        assert!(Modulus::new(&[]).is_none());
+        assert_eq!(Modulus::new(&[0, 0, 3]).unwrap().len(), 1);
+        assert_eq!(Modulus::new(&[0, 0, 3]).unwrap().bits(), 2);
+        assert_eq!(Modulus::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap().bits(), 65);
+        assert_eq!(Modulus::new(&[0xff, 0xff]).unwrap().bits(), 16);
+
+        assert_eq!(compare(&[0, 2], &[1]), Ordering::Greater);
+        assert_eq!(compare(&[1, 0], &[0xff]), Ordering::Greater);
+        assert_eq!(compare(&[], &[0]), Ordering::Equal);
+        assert_eq!(compare(&[3, 1], &[3, 2]), Ordering::Less);
+    }
+}
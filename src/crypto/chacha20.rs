@@ -48,6 +48,19 @@ impl ChaCha20 {
         }
     }
 
+    // RFC 7539 variant: 32-bit block counter followed by 96-bit nonce.
+    // key: SECRET
+    pub fn new_ietf(key: &[u8], nonce: &[u8], counter: u32) -> ChaCha20 {
+        assert_eq!(nonce.len(), 12);
+
+        let mut chacha20 = ChaCha20::new(key, &[0u8; 8]);
+        chacha20.vals[12] = counter;
+        chacha20.vals[13] = to_le_u32!(nonce[0]);
+        chacha20.vals[14] = to_le_u32!(nonce[4]);
+        chacha20.vals[15] = to_le_u32!(nonce[8]);
+        chacha20
+    }
+
     fn round20(&self) -> [u32; 16] {
         // $e must be > 0 and < 32
         macro_rules! rot {
@@ -109,7 +122,8 @@ impl ChaCha20 {
     pub fn next(&mut self) -> [u8; 64] {
         let next = self.round20();
 
-        // in TLS, vals[13] never increases
+        // in TLS, vals[13] never increases.
+        // for RFC 7539, vals[12] is the whole 32-bit counter and vals[13] is a part of nonce.
         {
             self.vals[12] += 1;
             //let mut count = (self.vals[12] as u64) | (self.vals[13] as u64 << 32);
@@ -224,4 +238,24 @@ mod test {
                           \x87\x46\xd4\x52\x4d\x38\x40\x7a\x6d\xeb\x3a\xb7\x8f\xab\x78\xc9";
         check_keystream(&key, &nonce, keystream);
     }
+
+    #[test]
+    fn test_chacha20_ietf() {
+        // RFC 7539 2.4.2
+        let key: Vec<u8> = (0u8..32).collect();
+        let nonce = b"\x00\x00\x00\x00\x00\x00\x00\x4a\x00\x00\x00\x00";
+        let plain = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                      tip for the future, sunscreen would be it.";
+        let encrypted = b"\x6e\x2e\x35\x9a\x25\x68\xf9\x80\x41\xba\x07\x28\xdd\x0d\x69\x81\
+                          \xe9\x7e\x7a\xec\x1d\x43\x60\xc2\x0a\x27\xaf\xcc\xfd\x9f\xae\x0b\
+                          \xf9\x1b\x65\xc5\x52\x47\x33\xab\x8f\x59\x3d\xab\xcd\x62\xb3\x57\
+                          \x16\x39\xd6\x24\xe6\x51\x52\xab\x8f\x53\x0c\x35\x9f\x08\x61\xd8\
+                          \x07\xca\x0d\xbf\x50\x0d\x6a\x61\x56\xa3\x8e\x08\x8a\x22\xb6\x5e\
+                          \x52\xbc\x51\x4d\x16\xcc\xf8\x06\x81\x8c\xe9\x1a\xb7\x79\x37\x36\
+                          \x5a\xf9\x0b\xbf\x74\xa3\x5b\xe6\xb4\x0b\x8e\xed\xf2\x78\x5e\x42\
+                          \x87\x4d";
+
+        let mut chacha = ChaCha20::new_ietf(&key[], nonce, 1);
+        assert_eq!(&chacha.encrypt(plain)[], &encrypted[]);
+    }
 }
@@ -0,0 +1,243 @@
+// X25519 Diffie-Hellman function (RFC 7748)
+// field arithmetic follows TweetNaCl: 16 limbs of 16 bits in i64,
+// without secret-dependent branches or memory access.
+
+const KEY_LEN: usize = 32;
+
+// u = 9
+pub const BASE: [u8; KEY_LEN] = [9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+// element of GF(2^255 - 19). v[i] holds bits 16*i.. of the value.
+// limbs may exceed 16 bits between operations.
+#[derive(Copy)]
+struct Fe {
+    v: [i64; 16],
+}
+
+const ZERO: Fe = Fe { v: [0; 16] };
+const ONE: Fe = Fe { v: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+// (A - 2) / 4 where A = 486662
+const A24: Fe = Fe { v: [0xdb41, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+
+impl Fe {
+    // the most significant bit is ignored (RFC 7748 5.)
+    fn from_bytes(b: &[u8]) -> Fe {
+        assert_eq!(b.len(), KEY_LEN);
+        let mut v = [0i64; 16];
+        for i in (0us..16) {
+            v[i] = (b[2 * i] as i64) | ((b[2 * i + 1] as i64) << 8);
+        }
+        v[15] &= 0x7fff;
+        Fe { v: v }
+    }
+
+    // fully reduced little-endian encoding
+    fn to_bytes(&self) -> [u8; KEY_LEN] {
+        let mut t = *self;
+        t.carry();
+        t.carry();
+        t.carry();
+
+        // subtract p at most twice
+        for _ in (0us..2) {
+            let mut m = [0i64; 16];
+            m[0] = t.v[0] - 0xffed;
+            for i in (1us..15) {
+                m[i] = t.v[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+                m[i - 1] &= 0xffff;
+            }
+            m[15] = t.v[15] - 0x7fff - ((m[14] >> 16) & 1);
+            m[14] &= 0xffff;
+            let borrow = (m[15] >> 16) & 1;
+            let mut m = Fe { v: m };
+            Fe::swap(1 - borrow, &mut t, &mut m);
+        }
+
+        let mut ret = [0u8; KEY_LEN];
+        for i in (0us..16) {
+            ret[2 * i] = t.v[i] as u8;
+            ret[2 * i + 1] = (t.v[i] >> 8) as u8;
+        }
+        ret
+    }
+
+    // propagate carries. 2^256 = 38 mod p.
+    fn carry(&mut self) {
+        for i in (0us..16) {
+            self.v[i] += 1 << 16;
+            let c = self.v[i] >> 16;
+            if i < 15 {
+                self.v[i + 1] += c - 1;
+            } else {
+                self.v[0] += 38 * (c - 1);
+            }
+            self.v[i] -= c << 16;
+        }
+    }
+
+    // SECRET
+    // swap `a` and `b` if `flag == 1`. `flag` must be 0 or 1.
+    fn swap(flag: i64, a: &mut Fe, b: &mut Fe) {
+        let mask = !(flag - 1);
+        for i in (0us..16) {
+            let t = mask & (a.v[i] ^ b.v[i]);
+            a.v[i] ^= t;
+            b.v[i] ^= t;
+        }
+    }
+
+    fn add(&self, b: &Fe) -> Fe {
+        let mut ret = ZERO;
+        for i in (0us..16) {
+            ret.v[i] = self.v[i] + b.v[i];
+        }
+        ret
+    }
+
+    fn sub(&self, b: &Fe) -> Fe {
+        let mut ret = ZERO;
+        for i in (0us..16) {
+            ret.v[i] = self.v[i] - b.v[i];
+        }
+        ret
+    }
+
+    fn mult(&self, b: &Fe) -> Fe {
+        let mut t = [0i64; 31];
+        for i in (0us..16) {
+            for j in (0us..16) {
+                t[i + j] += self.v[i] * b.v[j];
+            }
+        }
+        for i in (0us..15) {
+            t[i] += 38 * t[i + 16];
+        }
+        let mut ret = ZERO;
+        for i in (0us..16) {
+            ret.v[i] = t[i];
+        }
+        ret.carry();
+        ret.carry();
+        ret
+    }
+
+    fn square(&self) -> Fe {
+        self.mult(self)
+    }
+
+    // self^(p - 2). the exponent is public.
+    fn inverse(&self) -> Fe {
+        let mut c = *self;
+        for a in (0us..254).rev() {
+            c = c.square();
+            if a != 2 && a != 4 {
+                c = c.mult(self);
+            }
+        }
+        c
+    }
+}
+
+// SECRET: `scalar`
+// compute the u-coordinate of `scalar * point` by the Montgomery ladder (RFC 7748 5.)
+// the result is all-zero if `point` has small order. callers must check it.
+pub fn x25519(scalar: &[u8], point: &[u8]) -> [u8; KEY_LEN] {
+    assert_eq!(scalar.len(), KEY_LEN);
+
+    // clamp
+    let mut k = [0u8; KEY_LEN];
+    for i in (0us..KEY_LEN) {
+        k[i] = scalar[i];
+    }
+    k[0] &= 248;
+    k[31] = (k[31] & 127) | 64;
+
+    let x1 = Fe::from_bytes(point);
+    let mut x2 = ONE;
+    let mut z2 = ZERO;
+    let mut x3 = x1;
+    let mut z3 = ONE;
+
+    for i in (0us..255).rev() {
+        let bit = ((k[i / 8] >> (i % 8)) & 1) as i64;
+        Fe::swap(bit, &mut x2, &mut x3);
+        Fe::swap(bit, &mut z2, &mut z3);
+
+        let a = x2.add(&z2);
+        let aa = a.square();
+        let b = x2.sub(&z2);
+        let bb = b.square();
+        let e = aa.sub(&bb);
+        let c = x3.add(&z3);
+        let d = x3.sub(&z3);
+        let da = d.mult(&a);
+        let cb = c.mult(&b);
+        x3 = da.add(&cb).square();
+        z3 = x1.mult(&da.sub(&cb).square());
+        x2 = aa.mult(&bb);
+        z2 = e.mult(&aa.add(&A24.mult(&e)));
+
+        Fe::swap(bit, &mut x2, &mut x3);
+        Fe::swap(bit, &mut z2, &mut z3);
+    }
+
+    x2.mult(&z2.inverse()).to_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{x25519, BASE};
+
+    #[test]
+    fn test_x25519() {
+        // RFC 7748 5.2
+        let scalar = b"\xa5\x46\xe3\x6b\xf0\x52\x7c\x9d\x3b\x16\x15\x4b\x82\x46\x5e\xdd\
+                       \x62\x14\x4c\x0a\xc1\xfc\x5a\x18\x50\x6a\x22\x44\xba\x44\x9a\xc4";
+        let point = b"\xe6\xdb\x68\x67\x58\x30\x30\xdb\x35\x94\xc1\xa4\x24\xb1\x5f\x7c\
+                      \x72\x66\x24\xec\x26\xb3\x35\x3b\x10\xa9\x03\xa6\xd0\xab\x1c\x4c";
+        assert_eq!(&x25519(scalar, point)[],
+                   b"\xc3\xda\x55\x37\x9d\xe9\xc6\x90\x8e\x94\xea\x4d\xf2\x8d\x08\x4f\
+                     \x32\xec\xcf\x03\x49\x1c\x71\xf7\x54\xb4\x07\x55\x77\xa2\x85\x52");
+
+        // the high bit of the point is masked
+        let scalar = b"\x4b\x66\xe9\xd4\xd1\xb4\x67\x3c\x5a\xd2\x26\x91\x95\x7d\x6a\xf5\
+                       \xc1\x1b\x64\x21\xe0\xea\x01\xd4\x2c\xa4\x16\x9e\x79\x18\xba\x0d";
+        let point = b"\xe5\x21\x0f\x12\x78\x68\x11\xd3\xf4\xb7\x95\x9d\x05\x38\xae\x2c\
+                      \x31\xdb\xe7\x10\x6f\xc0\x3c\x3e\xfc\x4c\xd5\x49\xc7\x15\xa4\x93";
+        assert_eq!(&x25519(scalar, point)[],
+                   b"\x95\xcb\xde\x94\x76\xe8\x90\x7d\x7a\xad\xe4\x5c\xb4\xb8\x73\xf8\
+                     \x8b\x59\x5a\x68\x79\x9f\xa1\x52\xe6\xf8\xf7\x64\x7a\xac\x79\x57");
+
+        // iterated once
+        let k = x25519(&BASE, &BASE);
+        assert_eq!(&k[],
+                   b"\x42\x2c\x8e\x7a\x62\x27\xd7\xbc\xa1\x35\x0b\x3e\x2b\xb7\x27\x9f\
+                     \x78\x97\xb8\x7b\xb6\x85\x4b\x78\x3c\x60\xe8\x03\x11\xae\x30\x79");
+    }
+
+    #[test]
+    fn test_x25519_dh() {
+        // RFC 7748 6.1
+        let alice = b"\x77\x07\x6d\x0a\x73\x18\xa5\x7d\x3c\x16\xc1\x72\x51\xb2\x66\x45\
+                      \xdf\x4c\x2f\x87\xeb\xc0\x99\x2a\xb1\x77\xfb\xa5\x1d\xb9\x2c\x2a";
+        let bob = b"\x5d\xab\x08\x7e\x62\x4a\x8a\x4b\x79\xe1\x7f\x8b\x83\x80\x0e\xe6\
+                    \x6f\x3b\xb1\x29\x26\x18\xb6\xfd\x1c\x2f\x8b\x27\xff\x88\xe0\xeb";
+        let alice_public = x25519(alice, &BASE);
+        assert_eq!(&alice_public[],
+                   b"\x85\x20\xf0\x09\x89\x30\xa7\x54\x74\x8b\x7d\xdc\xb4\x3e\xf7\x5a\
+                     \x0d\xbf\x3a\x0d\x26\x38\x1a\xf4\xeb\xa4\xa9\x8e\xaa\x9b\x4e\x6a");
+        let bob_public = x25519(bob, &BASE);
+        assert_eq!(&bob_public[],
+                   b"\xde\x9e\xdb\x7d\x7b\x7d\xc1\xb4\xd3\x5b\x61\xc2\xec\xe4\x35\x37\
+                     \x3f\x83\x43\xc8\x5b\x78\x67\x4d\xad\xfc\x7e\x14\x6f\x88\x2b\x4f");
+
+        let shared = b"\x4a\x5d\x9d\x5b\xa4\xce\x2d\xe1\x72\x8e\x3b\xf4\x80\x35\x0f\x25\
+                       \xe0\x7e\x21\xc9\x47\xd1\x9e\x33\x76\xf0\x9b\x3c\x1e\x16\x17\x42";
+        assert_eq!(&x25519(alice, &bob_public)[], &shared[]);
+        assert_eq!(&x25519(bob, &alice_public)[], &shared[]);
+
+        // a small-order point gives zero
+        assert_eq!(&x25519(alice, &[0u8; 32])[], &[0u8; 32][]);
+    }
+}
@@ -0,0 +1,182 @@
+// Galois/Counter Mode with AES (NIST SP 800-38D), 96-bit IV only.
+
+use util::u64_be_array;
+use super::aes::Aes;
+
+const BLOCK_LEN: usize = 16;
+pub const IV_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+// element of GF(2^128) in GCM bit order: v[0] holds the first 8 bytes, big-endian.
+#[derive(Copy)]
+struct Ghash {
+    v: [u64; 2],
+}
+
+impl Ghash {
+    fn from_bytes(b: &[u8]) -> Ghash {
+        debug_assert_eq!(b.len(), BLOCK_LEN);
+        let mut v = [0u64; 2];
+        for i in (0us..BLOCK_LEN) {
+            v[i / 8] = (v[i / 8] << 8) | (b[i] as u64);
+        }
+        Ghash { v: v }
+    }
+
+    fn to_bytes(&self) -> [u8; BLOCK_LEN] {
+        let mut ret = [0u8; BLOCK_LEN];
+        let hi = u64_be_array(self.v[0]);
+        let lo = u64_be_array(self.v[1]);
+        for i in (0us..8) {
+            ret[i] = hi[i];
+            ret[8 + i] = lo[i];
+        }
+        ret
+    }
+
+    // SECRET
+    // shift-and-add multiplication without secret-dependent branches or tables.
+    fn mult(&self, y: &Ghash) -> Ghash {
+        let mut z = [0u64; 2];
+        let mut v = y.v;
+        for i in (0us..128) {
+            let bit = (self.v[i / 64] >> (63 - i % 64)) & 1;
+            z[0] ^= v[0] * bit;
+            z[1] ^= v[1] * bit;
+
+            // multiply v by x: shift right in GCM bit order, reduce by R = 0xe1 || 0^120
+            let lsb = v[1] & 1;
+            v[1] = (v[1] >> 1) | (v[0] << 63);
+            v[0] = (v[0] >> 1) ^ (0xe100000000000000 * lsb);
+        }
+        Ghash { v: z }
+    }
+}
+
+pub struct Gcm {
+    aes: Aes,
+    // SECRET
+    h: Ghash,
+}
+
+impl Gcm {
+    // key: SECRET
+    pub fn new(key: &[u8]) -> Gcm {
+        let aes = Aes::new(key);
+        let h = Ghash::from_bytes(&aes.encrypt_block(&[0u8; BLOCK_LEN])[]);
+        Gcm {
+            aes: aes,
+            h: h,
+        }
+    }
+
+    // J0 || counter: the counter block for a 96-bit IV.
+    fn counter_block(iv: &[u8], counter: u32) -> [u8; BLOCK_LEN] {
+        assert_eq!(iv.len(), IV_LEN);
+        let mut block = [0u8; BLOCK_LEN];
+        for i in (0us..IV_LEN) {
+            block[i] = iv[i];
+        }
+        block[12] = (counter >> 24) as u8;
+        block[13] = (counter >> 16) as u8;
+        block[14] = (counter >> 8) as u8;
+        block[15] = counter as u8;
+        block
+    }
+
+    // encryption and decryption are identical.
+    // the counter starts at 2 since J0 (counter 1) is used for the tag.
+    pub fn ctr(&self, iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(data.len());
+        for (i, chunk) in data.chunks(BLOCK_LEN).enumerate() {
+            let key_stream = self.aes.encrypt_block(&Gcm::counter_block(iv, 2 + i as u32)[]);
+            for j in (0us..chunk.len()) {
+                ret.push(chunk[j] ^ key_stream[j]);
+            }
+        }
+        ret
+    }
+
+    // GHASH of zero-padded `data`, continuing from `y`.
+    fn ghash_update(&self, y: &Ghash, data: &[u8]) -> Ghash {
+        let mut y = *y;
+        for chunk in data.chunks(BLOCK_LEN) {
+            let mut block = [0u8; BLOCK_LEN];
+            for i in (0us..chunk.len()) {
+                block[i] = chunk[i];
+            }
+            let x = Ghash::from_bytes(&block[]);
+            y = Ghash { v: [y.v[0] ^ x.v[0], y.v[1] ^ x.v[1]] }.mult(&self.h);
+        }
+        y
+    }
+
+    pub fn tag(&self, iv: &[u8], ad: &[u8], encrypted: &[u8]) -> [u8; TAG_LEN] {
+        let y = Ghash { v: [0; 2] };
+        let y = self.ghash_update(&y, ad);
+        let y = self.ghash_update(&y, encrypted);
+
+        let lens = [(ad.len() as u64) * 8, (encrypted.len() as u64) * 8];
+        let s = Ghash { v: [y.v[0] ^ lens[0], y.v[1] ^ lens[1]] }.mult(&self.h).to_bytes();
+
+        let mask = self.aes.encrypt_block(&Gcm::counter_block(iv, 1)[]);
+        let mut tag = [0u8; TAG_LEN];
+        for i in (0us..TAG_LEN) {
+            tag[i] = s[i] ^ mask[i];
+        }
+        tag
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Gcm;
+
+    fn check(key: &[u8], iv: &[u8], plain: &[u8], ad: &[u8], encrypted: &[u8], tag: &[u8]) {
+        let gcm = Gcm::new(key);
+        assert_eq!(&gcm.ctr(iv, plain)[], encrypted);
+        assert_eq!(&gcm.ctr(iv, encrypted)[], plain);
+        assert_eq!(&gcm.tag(iv, ad, encrypted)[], tag);
+    }
+
+    #[test]
+    fn test_gcm() {
+        // from "The Galois/Counter Mode of Operation (GCM)", McGrew and Viega
+        let zero = [0u8; 16];
+
+        // test case 1
+        check(&zero, &zero[..12], b"", b"", b"",
+              b"\x58\xe2\xfc\xce\xfa\x7e\x30\x61\x36\x7f\x1d\x57\xa4\xe7\x45\x5a");
+
+        // test case 2
+        check(&zero, &zero[..12], &zero, b"",
+              b"\x03\x88\xda\xce\x60\xb6\xa3\x92\xf3\x28\xc2\xb9\x71\xb2\xfe\x78",
+              b"\xab\x6e\x47\xd4\x2c\xec\x13\xbd\xf5\x3a\x67\xb2\x12\x57\xbd\xdf");
+
+        let key = b"\xfe\xff\xe9\x92\x86\x65\x73\x1c\x6d\x6a\x8f\x94\x67\x30\x83\x08\
+                    \xfe\xff\xe9\x92\x86\x65\x73\x1c\x6d\x6a\x8f\x94\x67\x30\x83\x08";
+        let iv = b"\xca\xfe\xba\xbe\xfa\xce\xdb\xad\xde\xca\xf8\x88";
+        let plain = b"\xd9\x31\x32\x25\xf8\x84\x06\xe5\xa5\x59\x09\xc5\xaf\xf5\x26\x9a\
+                      \x86\xa7\xa9\x53\x15\x34\xf7\xda\x2e\x4c\x30\x3d\x8a\x31\x8a\x72\
+                      \x1c\x3c\x0c\x95\x95\x68\x09\x53\x2f\xcf\x0e\x24\x49\xa6\xb5\x25\
+                      \xb1\x6a\xed\xf5\xaa\x0d\xe6\x57\xba\x63\x7b\x39";
+        let ad = b"\xfe\xed\xfa\xce\xde\xad\xbe\xef\xfe\xed\xfa\xce\xde\xad\xbe\xef\
+                   \xab\xad\xda\xd2";
+
+        // test case 4
+        check(&key[..16], iv, plain, ad,
+              b"\x42\x83\x1e\xc2\x21\x77\x74\x24\x4b\x72\x21\xb7\x84\xd0\xd4\x9c\
+                \xe3\xaa\x21\x2f\x2c\x02\xa4\xe0\x35\xc1\x7e\x23\x29\xac\xa1\x2e\
+                \x21\xd5\x14\xb2\x54\x66\x93\x1c\x7d\x8f\x6a\x5a\xac\x84\xaa\x05\
+                \x1b\xa3\x0b\x39\x6a\x0a\xac\x97\x3d\x58\xe0\x91",
+              b"\x5b\xc9\x4f\xbc\x32\x21\xa5\xdb\x94\xfa\xe9\x5a\xe7\x12\x1a\x47");
+
+        // test case 16 (AES-256)
+        check(key, iv, plain, ad,
+              b"\x52\x2d\xc1\xf0\x99\x56\x7d\x07\xf4\x7f\x37\xa3\x2a\x84\x42\x7d\
+                \x64\x3a\x8c\xdc\xbf\xe5\xc0\xc9\x75\x98\xa2\xbd\x25\x55\xd1\xaa\
+                \x8c\xb0\x8e\x48\x59\x0d\xbb\x3d\xa7\xb0\x8b\x10\x56\x82\x88\x38\
+                \xc5\xf6\x1e\x63\x93\xba\x7a\x0a\xbc\xc9\xf6\x62",
+              b"\x76\xfc\x6e\xce\x0f\x4e\x17\x68\xcd\xdf\x88\x53\xbb\x2d\x55\x1b");
+    }
+}
@@ -2,7 +2,12 @@
 // http://www.nsa.gov/ia/_files/nist-routines.pdf
 // http://point-at-infinity.org/ecc/nisttv
 
+use std::rand::Rng;
+
+use tls_result::TlsResult;
+use der::{self, DerReader};
 use self::int256::{Int256, ZERO, ONE};
+use self::scalar::Scalar;
 
 // Point on Y^2 = X^3 - 3 * X + B mod P256 where B is some obscure big number
 // (x, y, z): (X, Y) = (x/z^2, y/z^3) is point of Y^2 = X^3 - 3 * X + c
@@ -189,8 +194,8 @@ impl Point256 {
 
     pub fn mult_scalar(&self, n: &Int256) -> Point256 {
         let mut ret = INFTY.clone();
-        for i in (0us..7).rev() {
-            for j in (0us..8).rev() {
+        for i in (0us..8).rev() {
+            for j in (0us..32).rev() {
                 let bit = (n.v[i] >> j) & 1;
 
                 let ret2 = ret.double();
@@ -202,6 +207,27 @@ impl Point256 {
 
         ret
     }
+
+    // self * n + b * m by simultaneous multiplication (Shamir's trick).
+    // this branches on the bits of n and m, so they must not be SECRET.
+    pub fn mult_double_scalar(&self, n: &Int256, b: &Point256, m: &Int256) -> Point256 {
+        let sum = self.add(b);
+
+        let mut ret = INFTY.clone();
+        for i in (0us..8).rev() {
+            for j in (0us..32).rev() {
+                ret = ret.double();
+                match ((n.v[i] >> j) & 1, (m.v[i] >> j) & 1) {
+                    (1, 1) => ret = ret.add(&sum),
+                    (1, 0) => ret = ret.add(self),
+                    (0, 1) => ret = ret.add(b),
+                    _ => {}
+                }
+            }
+        }
+
+        ret
+    }
 }
 
 // normalized
@@ -268,6 +294,99 @@ impl NPoint256 {
     }
 }
 
+// ECDSA signature verification (SEC 1, 4.1.4)
+// `hash` is the message digest and `sig` is DER-encoded Ecdsa-Sig-Value.
+// nothing here is SECRET.
+pub fn verify_ecdsa(key: &NPoint256, hash: &[u8], sig: &[u8]) -> bool {
+    let (r, s) = match read_ecdsa_signature(sig) {
+        Ok((r, s)) => (Scalar::from_bytes(r), Scalar::from_bytes(s)),
+        Err(..) => return false,
+    };
+    let (r, s) = match (r, s) {
+        (Some(r), Some(s)) => (r, s),
+        _ => return false,
+    };
+    if r.is_zero() || s.is_zero() {
+        return false;
+    }
+
+    let e = Scalar::from_hash(hash);
+    let w = s.inverse();
+    let u1 = e.mult(&w);
+    let u2 = r.mult(&w);
+
+    let q = Point256 {
+        x: key.x,
+        y: key.y,
+        z: ONE,
+    };
+    let p = G.mult_double_scalar(&u1.to_int256(), &q, &u2.to_int256());
+    if p.z.compare(&ZERO) == 0 {
+        return false;
+    }
+
+    let x = Scalar::from_int256(&p.normalize().x);
+    x.compare(&r) == 0
+}
+
+// ECDSA signature generation (SEC 1, 4.1.3)
+// `key` is SECRET. `hash` is the message digest.
+// return DER-encoded Ecdsa-Sig-Value.
+pub fn sign_ecdsa<R: Rng>(key: &Scalar, hash: &[u8], rng: &mut R) -> Vec<u8> {
+    let e = Scalar::from_hash(hash);
+    loop {
+        // SECRET
+        let k = random_scalar(rng);
+        let p = G.mult_scalar(&k.to_int256()).normalize();
+        let r = Scalar::from_int256(&p.x);
+        if r.is_zero() {
+            continue;
+        }
+
+        // SECRET
+        let s = k.inverse().mult(&e.add(&r.mult(key)));
+        if s.is_zero() {
+            continue;
+        }
+
+        return write_ecdsa_signature(&r, &s);
+    }
+}
+
+// uniformly random scalar in [1, N - 1]
+// SECRET
+fn random_scalar<R: Rng>(rng: &mut R) -> Scalar {
+    loop {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        match Scalar::from_bytes(&bytes) {
+            Some(k) => {
+                if !k.is_zero() {
+                    return k;
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+// Ecdsa-Sig-Value ::= SEQUENCE { r INTEGER, s INTEGER }
+fn write_ecdsa_signature(r: &Scalar, s: &Scalar) -> Vec<u8> {
+    let mut contents = der::encode_unsigned_integer(&r.to_int256().to_bytes()[]);
+    contents.push_all(&der::encode_unsigned_integer(&s.to_int256().to_bytes()[])[]);
+    der::encode(der::TAG_SEQUENCE, &contents[])
+}
+
+pub fn read_ecdsa_signature(sig: &[u8]) -> TlsResult<(&[u8], &[u8])> {
+    let mut reader = DerReader::new(sig);
+    let mut seq = try!(reader.read_sequence());
+    try!(reader.finish());
+    let r = try!(seq.read_unsigned_integer());
+    let s = try!(seq.read_unsigned_integer());
+    try!(seq.finish());
+    Ok((r, s))
+}
+
 pub mod int256 {
     const LIMBS: usize = 8;
 
@@ -772,3 +891,363 @@ pub mod int256 {
         }
     }
 }
+
+// arithmetic modulo N, the order of G
+pub mod scalar {
+    use super::int256::Int256;
+
+    const LIMBS: usize = 8;
+
+    // 2^32-radix, same as Int256
+    // value must be < N
+    #[derive(Copy)]
+    pub struct Scalar {
+        pub v: [u32; LIMBS]
+    }
+
+    // N = 0xffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551
+    pub const N: Scalar = Scalar {
+        v: [0xfc632551, 0xf3b9cac2, 0xa7179e84, 0xbce6faad,
+            0xffffffff, 0xffffffff, 0x00000000, 0xffffffff]
+    };
+    pub const ZERO: Scalar = Scalar { v: [0; LIMBS] };
+    pub const ONE: Scalar = Scalar { v: [1, 0, 0, 0, 0, 0, 0, 0] };
+
+    // 2^512 mod N, for conversion into Montgomery form
+    const R2: Scalar = Scalar {
+        v: [0xbe79eea2, 0x83244c95, 0x49bd6fa6, 0x4699799c,
+            0x2b6bec59, 0x2845b239, 0xf3d95620, 0x66e12d94]
+    };
+    // -N^-1 mod 2^32
+    const N0_INV: u32 = 0xee00bc4f;
+
+    impl Clone for Scalar {
+        fn clone(&self) -> Scalar {
+            Scalar { v: self.v }
+        }
+    }
+
+    impl Scalar {
+        // return 0 if self == b.
+        // otherwise return 1.
+        pub fn compare(&self, b: &Scalar) -> u32 {
+            Int256 { v: self.v }.compare(&Int256 { v: b.v })
+        }
+
+        pub fn is_zero(&self) -> bool {
+            self.compare(&ZERO) == 0
+        }
+
+        // if flag == 0, returns a
+        // if flag == 1, returns b
+        fn choose(flag: u32, a: &Scalar, b: &Scalar) -> Scalar {
+            let mut v = [0; LIMBS];
+            for i in (0us..LIMBS) {
+                v[i] = a.v[i] ^ (flag * (a.v[i] ^ b.v[i]));
+            }
+            Scalar { v: v }
+        }
+
+        // return (self + b mod 2^256, carry)
+        fn add_no_reduce(&self, b: &Scalar) -> (Scalar, u32) {
+            let mut v = ZERO;
+            let mut carry = 0u64;
+            for i in (0us..LIMBS) {
+                let add = (self.v[i] as u64) + (b.v[i] as u64) + carry;
+                v.v[i] = add as u32;
+                carry = add >> 32;
+            }
+            (v, carry as u32)
+        }
+
+        // return (self - b mod 2^256, 1 if self < b else 0)
+        fn sub_no_reduce(&self, b: &Scalar) -> (Scalar, u32) {
+            let mut v = ZERO;
+            let mut carry_sub = 0u64;
+            for i in (0us..LIMBS) {
+                let sub = (self.v[i] as u64) - (b.v[i] as u64) - carry_sub;
+                carry_sub = sub >> 63;
+                v.v[i] = sub as u32;
+            }
+            (v, carry_sub as u32)
+        }
+
+        // precondition: `self + carry * 2^256 < 2 * N`
+        // return `(self + carry * 2^256) mod N`
+        fn reduce_once(&self, carry: u32) -> Scalar {
+            let (v, carry_sub) = self.sub_no_reduce(&N);
+            debug_assert!(!(carry_sub == 0 && carry == 1)); // precondition violated
+            let choose_new = carry ^ carry_sub;
+            Scalar::choose(choose_new, &v, self)
+        }
+
+        // return `self * b / 2^256 mod N` (Montgomery multiplication)
+        fn mont_mult(&self, b: &Scalar) -> Scalar {
+            let mut t = [0u32; LIMBS + 2];
+            for i in (0us..LIMBS) {
+                // t += self * b[i]
+                let mut c = 0u64;
+                for j in (0us..LIMBS) {
+                    let s = (t[j] as u64) + (self.v[j] as u64) * (b.v[i] as u64) + c;
+                    t[j] = s as u32;
+                    c = s >> 32;
+                }
+                let s = (t[LIMBS] as u64) + c;
+                t[LIMBS] = s as u32;
+                t[LIMBS + 1] = (s >> 32) as u32;
+
+                // t = (t + m * N) / 2^32
+                let m = ((t[0] as u64) * (N0_INV as u64)) & 0xffffffff;
+                let s = (t[0] as u64) + m * (N.v[0] as u64);
+                let mut c = s >> 32;
+                for j in (1us..LIMBS) {
+                    let s = (t[j] as u64) + m * (N.v[j] as u64) + c;
+                    t[j - 1] = s as u32;
+                    c = s >> 32;
+                }
+                let s = (t[LIMBS] as u64) + c;
+                t[LIMBS - 1] = s as u32;
+                t[LIMBS] = t[LIMBS + 1] + ((s >> 32) as u32);
+                t[LIMBS + 1] = 0;
+            }
+
+            // t < 2N
+            let mut v = ZERO;
+            for i in (0us..LIMBS) {
+                v.v[i] = t[i];
+            }
+            v.reduce_once(t[LIMBS])
+        }
+
+        pub fn add(&self, b: &Scalar) -> Scalar {
+            let (v, carry) = self.add_no_reduce(b);
+            v.reduce_once(carry)
+        }
+
+        pub fn mult(&self, b: &Scalar) -> Scalar {
+            self.mont_mult(b).mont_mult(&R2)
+        }
+
+        // return self^-1 = self^(N - 2)
+        // N is public, so this does not leak anything about self.
+        pub fn inverse(&self) -> Scalar {
+            let (e, _) = N.sub_no_reduce(&Scalar { v: [2, 0, 0, 0, 0, 0, 0, 0] });
+
+            let a = self.mont_mult(&R2);
+            let mut y = ONE.mont_mult(&R2);
+            for i in (0us..LIMBS).rev() {
+                for j in (0us..32).rev() {
+                    y = y.mont_mult(&y);
+                    if (e.v[i] >> j) & 1 == 1 {
+                        y = y.mont_mult(&a);
+                    }
+                }
+            }
+            y.mont_mult(&ONE)
+        }
+
+        // big-endian, at most 32 bytes.
+        // return None if the value is not less than N.
+        pub fn from_bytes(b: &[u8]) -> Option<Scalar> {
+            if b.len() > 32 {
+                return None;
+            }
+
+            let mut x = ZERO;
+            for (i, &bi) in b.iter().rev().enumerate() {
+                x.v[i / 4] |= (bi as u32) << ((i % 4) * 8);
+            }
+
+            let (_, carry_sub) = x.sub_no_reduce(&N);
+            if carry_sub == 0 {
+                return None;
+            }
+            Some(x)
+        }
+
+        // convert a message digest into a scalar (SEC 1, 4.1.3, step 5):
+        // the leftmost 256 bits are taken, then reduced mod N.
+        pub fn from_hash(hash: &[u8]) -> Scalar {
+            let hash = if hash.len() > 32 {
+                &hash[..32]
+            } else {
+                hash
+            };
+
+            let mut x = ZERO;
+            for (i, &bi) in hash.iter().rev().enumerate() {
+                x.v[i / 4] |= (bi as u32) << ((i % 4) * 8);
+            }
+            // 2^256 < 2 * N
+            x.reduce_once(0)
+        }
+
+        // P256 < 2 * N
+        pub fn from_int256(x: &Int256) -> Scalar {
+            Scalar { v: x.v }.reduce_once(0)
+        }
+
+        pub fn to_int256(&self) -> Int256 {
+            Int256 { v: self.v }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{Scalar, N, ZERO, ONE};
+
+        static VALUES: &'static [Scalar] = &[
+            ONE,
+            Scalar { v: [2, 0, 0, 0, 0, 0, 0, 0] },
+            Scalar { v: [1, 2, 3, 4, 5, 6, 7, 8] },
+            Scalar { v: [0xfc632550, 0xf3b9cac2, 0xa7179e84, 0xbce6faad,
+                         0xffffffff, 0xffffffff, 0x00000000, 0xffffffff] },
+        ];
+
+        #[test]
+        fn test_scalar_add() {
+            for a in VALUES.iter() {
+                assert_eq!(a.add(&ZERO).v, a.v);
+                for b in VALUES.iter() {
+                    assert_eq!(a.add(b).v, b.add(a).v);
+                }
+            }
+
+            // (N - 1) + 1 == 0
+            assert!(VALUES[3].add(&ONE).is_zero());
+            // (N - 1) + (N - 1) == N - 2
+            assert_eq!(VALUES[3].add(&VALUES[3]).v,
+                       [0xfc63254f, 0xf3b9cac2, 0xa7179e84, 0xbce6faad,
+                        0xffffffff, 0xffffffff, 0x00000000, 0xffffffff]);
+        }
+
+        #[test]
+        fn test_scalar_mult() {
+            for a in VALUES.iter() {
+                assert_eq!(a.mult(&ONE).v, a.v);
+                assert!(a.mult(&ZERO).is_zero());
+
+                for b in VALUES.iter() {
+                    assert_eq!(a.mult(b).v, b.mult(a).v);
+                }
+            }
+
+            // (N - 1)^2 == 1
+            assert_eq!(VALUES[3].mult(&VALUES[3]).v, ONE.v);
+        }
+
+        #[test]
+        fn test_scalar_inverse() {
+            for a in VALUES.iter() {
+                let a_inv = a.inverse();
+                assert_eq!(a_inv.mult(a).v, ONE.v);
+                assert_eq!(a_inv.inverse().v, a.v);
+            }
+        }
+
+        #[test]
+        fn test_scalar_from_bytes() {
+            let n = N.to_int256().to_bytes();
+            assert!(Scalar::from_bytes(&n[]).is_none());
+            assert!(Scalar::from_hash(&n[]).is_zero());
+            assert_eq!(Scalar::from_bytes(&[1, 0]).unwrap().v, [256, 0, 0, 0, 0, 0, 0, 0]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rand::OsRng;
+
+    use super::{G, NPoint256, verify_ecdsa, sign_ecdsa};
+    use super::scalar::{Scalar, N};
+    use super::int256::ZERO;
+    use crypto::sha2::sha256;
+
+    #[test]
+    fn test_mult_scalar_order() {
+        let p = G.mult_scalar(&N.to_int256());
+        assert_eq!(p.z.compare(&ZERO), 0);
+    }
+
+    #[test]
+    fn test_mult_double_scalar() {
+        let q = G.mult_scalar(&Scalar { v: [9, 8, 7, 6, 5, 4, 3, 2] }.to_int256());
+        let scalars = [
+            Scalar { v: [0; 8] },
+            Scalar { v: [1, 0, 0, 0, 0, 0, 0, 0] },
+            Scalar { v: [1, 2, 3, 4, 5, 6, 7, 8] },
+            Scalar { v: [0xfc632550, 0xf3b9cac2, 0xa7179e84, 0xbce6faad,
+                         0xffffffff, 0xffffffff, 0x00000000, 0xffffffff] },
+        ];
+
+        for n in scalars.iter() {
+            for m in scalars.iter() {
+                let n = n.to_int256();
+                let m = m.to_int256();
+                let expected = G.mult_scalar(&n).add(&q.mult_scalar(&m));
+                let computed = G.mult_double_scalar(&n, &q, &m);
+
+                if expected.z.compare(&ZERO) == 0 {
+                    assert_eq!(computed.z.compare(&ZERO), 0);
+                } else {
+                    let expected = expected.normalize();
+                    let computed = computed.normalize();
+                    assert_eq!(expected.x.v, computed.x.v);
+                    assert_eq!(expected.y.v, computed.y.v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_ecdsa() {
+        static KEY: &'static [u8] = &[
+            0x04, 0xfe, 0xe7, 0xeb, 0x8f, 0x31, 0x36, 0x63, 0x8e, 0xf1, 0x1c, 0xb3,
+            0xcc, 0xd7, 0x82, 0x52, 0x5d, 0x34, 0x5f, 0xdb, 0x95, 0xe0, 0x6b, 0x85,
+            0xa1, 0x46, 0x18, 0x8b, 0x7f, 0x15, 0x6b, 0x85, 0xab, 0x0f, 0x79, 0x7b,
+            0x03, 0xde, 0x5d, 0x6f, 0x07, 0xe5, 0x41, 0x65, 0xd2, 0x98, 0x28, 0x36,
+            0x1c, 0xb4, 0x31, 0x03, 0x7a, 0x84, 0xa2, 0xf1, 0xd8, 0x92, 0xa3, 0xfd,
+            0xdb, 0xec, 0xbf, 0x59, 0xcf,
+        ];
+        // ECDSA-SHA256 signature of "suruga"
+        static SIG: &'static [u8] = &[
+            0x30, 0x45, 0x02, 0x20, 0x34, 0x09, 0x0d, 0xb6, 0x43, 0xf0, 0xbd, 0xfb,
+            0x37, 0x9a, 0x0f, 0x8b, 0x51, 0x4f, 0xe8, 0x5a, 0x13, 0xae, 0x64, 0x13,
+            0xef, 0xf1, 0xdd, 0xce, 0xc5, 0x76, 0xcd, 0xae, 0x76, 0xaf, 0xc4, 0x1f,
+            0x02, 0x21, 0x00, 0xa1, 0x0b, 0x48, 0xe5, 0xb8, 0xeb, 0xe9, 0xbc, 0xaa,
+            0x65, 0x7e, 0x75, 0x9d, 0xd8, 0x48, 0xd2, 0xdf, 0xda, 0xae, 0x3d, 0xe1,
+            0x40, 0x25, 0x8d, 0x9e, 0x1b, 0x37, 0xb9, 0x92, 0xe5, 0x26, 0x29,
+        ];
+
+        let key = NPoint256::from_uncompressed_bytes(KEY).unwrap();
+        let hash = sha256(b"suruga");
+        assert!(verify_ecdsa(&key, &hash[], SIG));
+
+        let hash2 = sha256(b"surugA");
+        assert!(!verify_ecdsa(&key, &hash2[], SIG));
+
+        let mut sig = SIG.to_vec();
+        sig[10] ^= 1;
+        assert!(!verify_ecdsa(&key, &hash[], &sig[]));
+    }
+
+    #[test]
+    fn test_sign_ecdsa() {
+        let key = Scalar { v: [1, 2, 3, 4, 5, 6, 7, 8] };
+        let public = G.mult_scalar(&key.to_int256()).normalize();
+        let hash = sha256(b"suruga");
+
+        let mut rng = OsRng::new().unwrap();
+        let sig = sign_ecdsa(&key, &hash[], &mut rng);
+        assert!(verify_ecdsa(&public, &hash[], &sig[]));
+
+        // k is random
+        let sig2 = sign_ecdsa(&key, &hash[], &mut rng);
+        assert!(sig != sig2);
+        assert!(verify_ecdsa(&public, &hash[], &sig2[]));
+
+        let hash2 = sha256(b"surugA");
+        assert!(!verify_ecdsa(&public, &hash2[], &sig[]));
+    }
+}
@@ -0,0 +1,964 @@
+// constantly slow implementation of NIST P-384, modeled on `p256`
+// http://www.nsa.gov/ia/_files/nist-routines.pdf
+
+use self::int384::{Int384, ZERO, ONE};
+use self::scalar::Scalar;
+use super::p256::read_ecdsa_signature;
+
+// Point on Y^2 = X^3 - 3 * X + B mod P384
+// (x, y, z): (X, Y) = (x/z^2, y/z^3)
+// identity (INFTY) is (1, 1, 0)
+#[derive(Copy)]
+pub struct Point384 {
+    x: Int384,
+    y: Int384,
+    z: Int384,
+}
+
+pub const G: Point384 = Point384 {
+    x: Int384 {
+        v: [0x72760ab7, 0x3a545e38, 0xbf55296c, 0x5502f25d,
+            0x82542a38, 0x59f741e0, 0x8ba79b98, 0x6e1d3b62,
+            0xf320ad74, 0x8eb1c71e, 0xbe8b0537, 0xaa87ca22]
+    },
+    y: Int384 {
+        v: [0x90ea0e5f, 0x7a431d7c, 0x1d7e819d, 0x0a60b1ce,
+            0xb5f0b8c0, 0xe9da3113, 0x289a147c, 0xf8f41dbd,
+            0x9292dc29, 0x5d9e98bf, 0x96262c6f, 0x3617de4a]
+    },
+    z: ONE,
+};
+
+pub const B: Int384 = Int384 {
+    v: [0xd3ec2aef, 0x2a85c8ed, 0x8a2ed19d, 0xc656398d,
+        0x5013875a, 0x0314088f, 0xfe814112, 0x181d9c6e,
+        0xe3f82d19, 0x988e056b, 0xe23ee7e4, 0xb3312fa7]
+};
+
+const INFTY: Point384 = Point384 {
+    x: ONE,
+    y: ONE,
+    z: ZERO,
+};
+
+impl Clone for Point384 {
+    fn clone(&self) -> Point384 {
+        Point384 {
+            x: self.x.clone(),
+            y: self.y.clone(),
+            z: self.z.clone(),
+        }
+    }
+}
+
+impl Point384 {
+    pub fn normalize(&self) -> NPoint384 {
+        let z2 = self.z.square();
+        let z3 = self.z.mult(&z2);
+        let x = self.x.mult(&z2.inverse());
+        let y = self.y.mult(&z3.inverse());
+
+        NPoint384 {
+            x: x,
+            y: y,
+        }
+    }
+
+    fn choose(flag: u32, a: &Point384, b: &Point384) -> Point384 {
+        let x = Int384::choose(flag, &a.x, &b.x);
+        let y = Int384::choose(flag, &a.y, &b.y);
+        let z = Int384::choose(flag, &a.z, &b.z);
+
+        Point384 {
+            x: x,
+            y: y,
+            z: z,
+        }
+    }
+
+    // compute `self + self`. see `Point256::double`.
+    fn double(&self) -> Point384 {
+        let z2 = self.z.square();
+        let y2 = self.y.square();
+
+        // a = 3 * (x - z^2) * (x + z^2)
+        let a = {
+            let x_sub_z2 = self.x.sub(&z2);
+            let x_add_z2 = self.x.add(&z2);
+            let mult = x_add_z2.mult(&x_sub_z2);
+            mult.add(&mult).add(&mult)
+        };
+
+        // b = x * y^2
+        let b = self.x.mult(&y2);
+        let b2 = b.add(&b);
+        let b4 = b2.add(&b2);
+        let b8 = b4.add(&b4);
+
+        // x_new = a^2 - 8 * x * y^2
+        let x_new = a.square().sub(&b8);
+
+        // y_new = (4 * b - x_new) * a - 8 * y^4
+        let y_new = {
+            let y4 = y2.square();
+            let y4_2 = y4.add(&y4);
+            let y4_4 = y4_2.add(&y4_2);
+            let y4_8 = y4_4.add(&y4_4);
+
+            a.mult(&b4.sub(&x_new)).sub(&y4_8)
+        };
+
+        // z_new = 2 * z * y = (z + y)^2 - (z^2 + y^2)
+        let z_new = self.y.add(&self.z).square().sub(&z2.add(&y2));
+
+        let ret = Point384 {
+            x: x_new,
+            y: y_new,
+            z: z_new,
+        };
+
+        // return normalized INFTY if self is INFTY
+        let self_not_infty = self.z.compare(&ZERO);
+        Point384::choose(self_not_infty, &INFTY, &ret)
+    }
+
+    // see `Point256::add`.
+    fn add(&self, b: &Point384) -> Point384 {
+        let self_is_zero = self.z.compare(&ZERO);
+        let b_is_zero = b.z.compare(&ZERO);
+
+        let z2 = self.z.square();
+        let z3 = self.z.mult(&z2);
+        let bz2 = b.z.square();
+        let bz3 = b.z.mult(&bz2);
+
+        let x = self.x.mult(&bz2);
+        let y = self.y.mult(&bz3);
+        let bx = b.x.mult(&z2);
+        let by = b.y.mult(&z3);
+
+        let xdiff = x.sub(&bx);
+        let xdiff2 = xdiff.square();
+        let xdiff3 = xdiff.mult(&xdiff2);
+
+        let ydiff = y.sub(&by);
+        let ydiff2 = ydiff.square();
+
+        let xsum = x.add(&bx);
+        let ysum = y.add(&by);
+
+        // e = (x + x') * (x - x')^2
+        let e = xsum.mult(&xdiff2);
+
+        // x_new = (y - y')^2 - e
+        let x_new = ydiff2.sub(&e);
+        let x_new_2 = x_new.add(&x_new);
+
+        // y_new = ((y - y') * (e - 2 * x_new) - (y + y') * (x - x')^3) / 2
+        let y_new = {
+            let t4 = ysum.mult(&xdiff3);
+            let t5 = ydiff.mult(&e.sub(&x_new_2));
+            t5.sub(&t4).divide_by_2()
+        };
+
+        // z_new = z * z' * (x - x')
+        let z_new = self.z.mult(&b.z).mult(&xdiff);
+
+        let xdiff_nonzero = xdiff.compare(&ZERO);
+        let ydiff_nonzero = ydiff.compare(&ZERO);
+
+        let ret = Point384 {
+            x: x_new,
+            y: y_new,
+            z: z_new,
+        };
+
+        // if self == b, return self.double() since ret is (0, 0, 0)
+        let double = self.double();
+        let ret = Point384::choose(xdiff_nonzero | ydiff_nonzero, &double, &ret);
+        // if self == -b, return INFTY
+        let ret = Point384::choose(xdiff_nonzero | (1 - ydiff_nonzero), &INFTY, &ret);
+        // if self == INFTY, return b
+        let ret = Point384::choose(self_is_zero, b, &ret);
+        // if b == INFTY, return self
+        Point384::choose(b_is_zero, self, &ret)
+    }
+
+    pub fn mult_scalar(&self, n: &Int384) -> Point384 {
+        let mut ret = INFTY.clone();
+        for i in (0us..12).rev() {
+            for j in (0us..32).rev() {
+                let bit = (n.v[i] >> j) & 1;
+
+                let ret2 = ret.double();
+                let ret3 = ret2.add(self);
+
+                ret = Point384::choose(bit, &ret2, &ret3);
+            }
+        }
+
+        ret
+    }
+
+    // self * n + b * m by simultaneous multiplication (Shamir's trick).
+    // this branches on the bits of n and m, so they must not be SECRET.
+    pub fn mult_double_scalar(&self, n: &Int384, b: &Point384, m: &Int384) -> Point384 {
+        let sum = self.add(b);
+
+        let mut ret = INFTY.clone();
+        for i in (0us..12).rev() {
+            for j in (0us..32).rev() {
+                ret = ret.double();
+                match ((n.v[i] >> j) & 1, (m.v[i] >> j) & 1) {
+                    (1, 1) => ret = ret.add(&sum),
+                    (1, 0) => ret = ret.add(self),
+                    (0, 1) => ret = ret.add(b),
+                    _ => {}
+                }
+            }
+        }
+
+        ret
+    }
+}
+
+// normalized
+pub struct NPoint384 {
+    pub x: Int384,
+    pub y: Int384,
+}
+
+impl NPoint384 {
+    pub fn to_point(self) -> Point384 {
+        Point384 {
+            x: self.x,
+            y: self.y,
+            z: ONE,
+        }
+    }
+
+    pub fn from_uncompressed_bytes(data: &[u8]) -> Option<NPoint384> {
+        if data.len() != 1 + 48 * 2 {
+            return None;
+        }
+        if data[0] != 0x04 {
+            return None;
+        }
+
+        let x = Int384::from_bytes(&data[1..(48 + 1)]);
+        let y = Int384::from_bytes(&data[(1 + 48)..(1 + 48 * 2)]);
+
+        let (x, y) = match (x, y) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return None,
+        };
+
+        // check if y^2 + 3 * x == x^3 + B
+        let y2 = y.square();
+        let lhs = y2.add(&x.double().add(&x));
+
+        let x3 = x.square().mult(&x);
+        let rhs = x3.add(&B);
+
+        if lhs.compare(&rhs) != 0 {
+            return None;
+        }
+
+        Some(NPoint384 {
+            x: x,
+            y: y,
+        })
+    }
+
+    pub fn to_uncompressed_bytes(&self) -> Vec<u8> {
+        // 0x04 || self.x (big endian) || self.y (big endian)
+        let mut b = Vec::with_capacity(1 + (384 / 8) * 2);
+        b.push(0x04); // uncompressed
+        b.push_all(&self.x.to_bytes()[]);
+        b.push_all(&self.y.to_bytes()[]);
+        b
+    }
+}
+
+// ECDSA signature verification (SEC 1, 4.1.4)
+// `hash` is the message digest and `sig` is DER-encoded Ecdsa-Sig-Value.
+// nothing here is SECRET.
+pub fn verify_ecdsa(key: &NPoint384, hash: &[u8], sig: &[u8]) -> bool {
+    let (r, s) = match read_ecdsa_signature(sig) {
+        Ok((r, s)) => (Scalar::from_bytes(r), Scalar::from_bytes(s)),
+        Err(..) => return false,
+    };
+    let (r, s) = match (r, s) {
+        (Some(r), Some(s)) => (r, s),
+        _ => return false,
+    };
+    if r.is_zero() || s.is_zero() {
+        return false;
+    }
+
+    let e = Scalar::from_hash(hash);
+    let w = s.inverse();
+    let u1 = e.mult(&w);
+    let u2 = r.mult(&w);
+
+    let q = Point384 {
+        x: key.x,
+        y: key.y,
+        z: ONE,
+    };
+    let p = G.mult_double_scalar(&u1.to_int384(), &q, &u2.to_int384());
+    if p.z.compare(&ZERO) == 0 {
+        return false;
+    }
+
+    let x = Scalar::from_int384(&p.normalize().x);
+    x.compare(&r) == 0
+}
+
+pub mod int384 {
+    const LIMBS: usize = 12;
+
+    // 2^32-radix: value = v[0] + 2^32 v[1] + ... + 2^352 v[11]
+    // value must be < P384
+    #[derive(Copy)]
+    pub struct Int384 {
+        pub v: [u32; LIMBS]
+    }
+
+    // P384 = 2^384 - 2^128 - 2^96 + 2^32 - 1
+    pub const P384: Int384 = Int384 {
+        v: [0xffffffff, 0x00000000, 0x00000000, 0xffffffff,
+            0xfffffffe, 0xffffffff, 0xffffffff, 0xffffffff,
+            0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff]
+    };
+    pub const ZERO: Int384 = Int384 { v: [0; LIMBS] };
+    pub const ONE: Int384 = Int384 { v: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+
+    impl Clone for Int384 {
+        fn clone(&self) -> Int384 {
+            Int384 { v: self.v }
+        }
+    }
+
+    impl Int384 {
+        // return 0 if self == b.
+        // otherwise return 1.
+        pub fn compare(&self, b: &Int384) -> u32 {
+            let mut diff = 0u32;
+            for i in (0us..LIMBS) {
+                diff |= self.v[i] ^ b.v[i];
+            }
+            diff |= diff >> 16;
+            diff |= diff >> 8;
+            diff |= diff >> 4;
+            diff |= diff >> 2;
+            diff |= diff >> 1;
+            diff & 1
+        }
+
+        // if flag == 0, returns a
+        // if flag == 1, returns b
+        pub fn choose(flag: u32, a: &Int384, b: &Int384) -> Int384 {
+            let mut v = [0; LIMBS];
+            for i in (0us..LIMBS) {
+                v[i] = a.v[i] ^ (flag * (a.v[i] ^ b.v[i]));
+            }
+            Int384 { v: v }
+        }
+
+        // return (self + b mod 2^384, carry)
+        fn add_no_reduce(&self, b: &Int384) -> (Int384, u32) {
+            let mut v = ZERO;
+            let mut carry = 0u64;
+            for i in (0us..LIMBS) {
+                let add = (self.v[i] as u64) + (b.v[i] as u64) + carry;
+                v.v[i] = add as u32;
+                carry = add >> 32;
+            }
+            (v, carry as u32)
+        }
+
+        // return (self - b mod 2^384, 1 if self < b else 0)
+        fn sub_no_reduce(&self, b: &Int384) -> (Int384, u32) {
+            let mut v = ZERO;
+            let mut carry_sub = 0u64;
+            for i in (0us..LIMBS) {
+                let sub = (self.v[i] as u64) - (b.v[i] as u64) - carry_sub;
+                carry_sub = sub >> 63;
+                v.v[i] = sub as u32;
+            }
+            (v, carry_sub as u32)
+        }
+
+        // precondition: `self + carry * 2^384 < 2 * P384`
+        // return `(self + carry * 2^384) mod P384`
+        pub fn reduce_once(&self, carry: u32) -> Int384 {
+            let (v, carry_sub) = self.sub_no_reduce(&P384);
+            debug_assert!(!(carry_sub == 0 && carry == 1)); // precondition violated
+            let choose_new = carry ^ carry_sub;
+            Int384::choose(choose_new, &v, self)
+        }
+
+        pub fn add(&self, b: &Int384) -> Int384 {
+            let (v, carry) = self.add_no_reduce(b);
+            v.reduce_once(carry)
+        }
+
+        pub fn double(&self) -> Int384 {
+            self.add(self)
+        }
+
+        pub fn sub(&self, b: &Int384) -> Int384 {
+            let (v, carry_sub) = self.sub_no_reduce(b);
+            // if self - b < 0, carry_sub == 1 and v == 2^384 + self - b
+            let (v2, _carry_add) = v.add_no_reduce(&P384);
+            debug_assert!(!(_carry_add == 0 && carry_sub == 1));
+            Int384::choose(carry_sub, &v, &v2)
+        }
+
+        // `idx[i]` is the index of the word of `c` placed at `v[i]`, or `None` for zero.
+        fn from_words(c: &[u32], idx: [Option<usize>; LIMBS]) -> Int384 {
+            let mut buf = ZERO;
+            for i in (0us..LIMBS) {
+                match idx[i] {
+                    Some(j) => buf.v[i] = c[j],
+                    None => {}
+                }
+            }
+            buf.reduce_once(0)
+        }
+
+        pub fn mult(&self, b: &Int384) -> Int384 {
+            let mut w = [0u64; LIMBS * 2];
+            for i in (0us..LIMBS) {
+                for j in (0us..LIMBS) {
+                    let ij = i + j;
+                    let v_ij = (self.v[i] as u64) * (b.v[j] as u64);
+                    let v_ij_low = (v_ij as u32) as u64;
+                    let v_ij_high = v_ij >> 32;
+                    let w_ij = w[ij] + v_ij_low;
+                    let w_ij_low = (w_ij as u32) as u64;
+                    let w_ij_high = v_ij_high + (w_ij >> 32);
+                    w[ij] = w_ij_low;
+                    w[ij + 1] += w_ij_high;
+                }
+            }
+
+            let mut c = [0u32; LIMBS * 2];
+            let mut carry = 0u64;
+            for i in 0us..(LIMBS * 2) {
+                let a = w[i] + carry;
+                c[i] = a as u32;
+                carry = a >> 32;
+            }
+            debug_assert_eq!(carry, 0);
+
+            // fast reduction (FIPS 186-4 D.2.4). words are listed from v[0].
+            let t = Int384::from_words(&c, [Some(0), Some(1), Some(2), Some(3),
+                                            Some(4), Some(5), Some(6), Some(7),
+                                            Some(8), Some(9), Some(10), Some(11)]);
+            let s1 = Int384::from_words(&c, [None, None, None, None,
+                                             Some(21), Some(22), Some(23), None,
+                                             None, None, None, None]);
+            let s2 = Int384::from_words(&c, [Some(12), Some(13), Some(14), Some(15),
+                                             Some(16), Some(17), Some(18), Some(19),
+                                             Some(20), Some(21), Some(22), Some(23)]);
+            let s3 = Int384::from_words(&c, [Some(21), Some(22), Some(23), Some(12),
+                                             Some(13), Some(14), Some(15), Some(16),
+                                             Some(17), Some(18), Some(19), Some(20)]);
+            let s4 = Int384::from_words(&c, [None, Some(23), None, Some(20),
+                                             Some(12), Some(13), Some(14), Some(15),
+                                             Some(16), Some(17), Some(18), Some(19)]);
+            let s5 = Int384::from_words(&c, [None, None, None, None,
+                                             Some(20), Some(21), Some(22), Some(23),
+                                             None, None, None, None]);
+            let s6 = Int384::from_words(&c, [Some(20), None, None, Some(21),
+                                             Some(22), Some(23), None, None,
+                                             None, None, None, None]);
+            let d1 = Int384::from_words(&c, [Some(23), Some(12), Some(13), Some(14),
+                                             Some(15), Some(16), Some(17), Some(18),
+                                             Some(19), Some(20), Some(21), Some(22)]);
+            let d2 = Int384::from_words(&c, [None, Some(20), Some(21), Some(22),
+                                             Some(23), None, None, None,
+                                             None, None, None, None]);
+            let d3 = Int384::from_words(&c, [None, None, None, Some(23),
+                                             Some(23), None, None, None,
+                                             None, None, None, None]);
+
+            let r = t.add(&s1.double()).add(&s2).add(&s3).add(&s4).add(&s5).add(&s6);
+            r.sub(&d1.add(&d2).add(&d3))
+        }
+
+        pub fn square(&self) -> Int384 {
+            self.mult(self)
+        }
+
+        // return self^-1 = self^(P384 - 2)
+        // P384 is public, so this does not leak anything about self.
+        pub fn inverse(&self) -> Int384 {
+            let (e, _) = P384.sub_no_reduce(&Int384 { v: [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] });
+
+            let mut y = ONE;
+            for i in (0us..LIMBS).rev() {
+                for j in (0us..32).rev() {
+                    y = y.square();
+                    if (e.v[i] >> j) & 1 == 1 {
+                        y = y.mult(self);
+                    }
+                }
+            }
+            y
+        }
+
+        pub fn divide_by_2(&self) -> Int384 {
+            let is_odd = self.v[0] & 1;
+
+            let mut half_even = ZERO;
+            for i in 0us..(LIMBS - 1) {
+                half_even.v[i] = (self.v[i] >> 1) | ((self.v[i + 1] & 1) << 31);
+            }
+            half_even.v[LIMBS - 1] = self.v[LIMBS - 1] >> 1;
+
+            let mut half_odd = ZERO;
+            let (self_p, carry) = self.add_no_reduce(&P384);
+            for i in 0us..(LIMBS - 1) {
+                half_odd.v[i] = (self_p.v[i] >> 1) | ((self_p.v[i + 1] & 1) << 31);
+            }
+            half_odd.v[LIMBS - 1] = (self_p.v[LIMBS - 1] >> 1) | (carry << 31);
+
+            Int384::choose(is_odd, &half_even, &half_odd)
+        }
+
+        // big-endian.
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut b = [0u8; 384 / 8];
+            for i in (0us..LIMBS) {
+                let vi = self.v[LIMBS - 1 - i];
+                for j in (0us..4) {
+                    b[i * 4 + j] = (vi >> ((3 - j) * 8)) as u8;
+                }
+            }
+
+            b.to_vec()
+        }
+
+        // big-endian.
+        // return None if the value is not less than P384.
+        pub fn from_bytes(b: &[u8]) -> Option<Int384> {
+            if b.len() != 48 {
+                return None;
+            }
+
+            let mut x = ZERO;
+            for i in (0us..LIMBS) {
+                let mut vi = 0u32;
+                for j in (0us..4) {
+                    vi |= (b[i * 4 + j] as u32) << ((3 - j) * 8);
+                }
+                x.v[LIMBS - 1 - i] = vi;
+            }
+
+            let (_, carry_sub) = x.sub_no_reduce(&P384);
+            if carry_sub == 0 {
+                return None;
+            }
+            Some(x)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{Int384, P384, ZERO, ONE};
+
+        static VALUES_384: &'static [Int384] = &[
+            ZERO,
+            ONE,
+            Int384 { v: [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] },
+            Int384 { v: [1; 12] },
+            Int384 { v: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12] },
+            Int384 { v: [0, 0, 0, 0, 0xffffffff, 0xffffffff, 0, 0xffffffff,
+                         0, 0xffffffff, 0, 0xffffffff] },
+            Int384 { v: [0xfffffffe, 0, 0, 0xffffffff, 0xfffffffe, 0xffffffff,
+                         0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff,
+                         0xffffffff, 0xffffffff] },
+        ];
+
+        #[test]
+        fn test_int384_reduce_once() {
+            assert_eq!(P384.reduce_once(0).v, ZERO.v);
+
+            // 2^384 == 2^128 + 2^96 - 2^32 + 1
+            let v = Int384 { v: [1, 0xffffffff, 0xffffffff, 0, 1, 0, 0, 0, 0, 0, 0, 0] };
+            assert_eq!(ZERO.reduce_once(1).v, v.v);
+        }
+
+        #[test]
+        fn test_int384_add_sub() {
+            for a in VALUES_384.iter() {
+                assert_eq!(a.add(&ZERO).v, a.v);
+                assert_eq!(a.sub(a).v, ZERO.v);
+                for b in VALUES_384.iter() {
+                    assert_eq!(a.add(b).v, b.add(a).v);
+                    assert_eq!(a.sub(b).add(b).v, a.v);
+                }
+            }
+        }
+
+        #[test]
+        fn test_int384_mult() {
+            for a in VALUES_384.iter() {
+                assert_eq!(a.mult(&ONE).v, a.v);
+                assert_eq!(a.mult(&ZERO).v, ZERO.v);
+
+                for b in VALUES_384.iter() {
+                    let ab = a.mult(b);
+                    assert_eq!(ab.v, b.mult(a).v);
+                    for c in VALUES_384.iter() {
+                        assert_eq!(ab.mult(c).v, a.mult(&b.mult(c)).v);
+                        assert_eq!(ab.add(&a.mult(c)).v, a.mult(&b.add(c)).v);
+                    }
+                }
+            }
+
+            // (P384 - 1)^2 == 1
+            assert_eq!(VALUES_384[6].mult(&VALUES_384[6]).v, ONE.v);
+        }
+
+        #[test]
+        fn test_int384_inverse() {
+            for a in VALUES_384.iter() {
+                if a.compare(&ZERO) == 0 {
+                    continue;
+                }
+                assert_eq!(a.inverse().mult(a).v, ONE.v);
+            }
+        }
+
+        #[test]
+        fn test_int384_divide_by_2() {
+            for a in VALUES_384.iter() {
+                let a_half = a.divide_by_2();
+                assert_eq!(a_half.add(&a_half).v, a.v);
+            }
+        }
+
+        #[test]
+        fn test_int384_from_bytes() {
+            for a in VALUES_384.iter() {
+                let b = a.to_bytes();
+                assert_eq!(Int384::from_bytes(&b[]).unwrap().v, a.v);
+            }
+            assert!(Int384::from_bytes(&P384.to_bytes()[]).is_none());
+        }
+    }
+}
+
+// arithmetic modulo N, the order of G
+pub mod scalar {
+    use super::int384::Int384;
+
+    const LIMBS: usize = 12;
+
+    // 2^32-radix, same as Int384
+    // value must be < N
+    #[derive(Copy)]
+    pub struct Scalar {
+        pub v: [u32; LIMBS]
+    }
+
+    // N = 0xffffffffffffffffffffffffffffffffffffffffffffffffc7634d81f4372ddf
+    //       581a0db248b0a77aecec196accc52973
+    pub const N: Scalar = Scalar {
+        v: [0xccc52973, 0xecec196a, 0x48b0a77a, 0x581a0db2,
+            0xf4372ddf, 0xc7634d81, 0xffffffff, 0xffffffff,
+            0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff]
+    };
+    pub const ZERO: Scalar = Scalar { v: [0; LIMBS] };
+    pub const ONE: Scalar = Scalar { v: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+
+    // 2^768 mod N, for conversion into Montgomery form
+    const R2: Scalar = Scalar {
+        v: [0x19b409a9, 0x2d319b24, 0xdf1aa419, 0xff3d81e5,
+            0xfcb82947, 0xbc3e483a, 0x4aab1cc5, 0xd40d4917,
+            0x28266895, 0x3fb05b7a, 0x2b39bf21, 0x0c84ee01]
+    };
+    // -N^-1 mod 2^32
+    const N0_INV: u32 = 0xe88fdc45;
+
+    impl Clone for Scalar {
+        fn clone(&self) -> Scalar {
+            Scalar { v: self.v }
+        }
+    }
+
+    impl Scalar {
+        // return 0 if self == b.
+        // otherwise return 1.
+        pub fn compare(&self, b: &Scalar) -> u32 {
+            Int384 { v: self.v }.compare(&Int384 { v: b.v })
+        }
+
+        pub fn is_zero(&self) -> bool {
+            self.compare(&ZERO) == 0
+        }
+
+        // if flag == 0, returns a
+        // if flag == 1, returns b
+        fn choose(flag: u32, a: &Scalar, b: &Scalar) -> Scalar {
+            let mut v = [0; LIMBS];
+            for i in (0us..LIMBS) {
+                v[i] = a.v[i] ^ (flag * (a.v[i] ^ b.v[i]));
+            }
+            Scalar { v: v }
+        }
+
+        // return (self + b mod 2^384, carry)
+        fn add_no_reduce(&self, b: &Scalar) -> (Scalar, u32) {
+            let mut v = ZERO;
+            let mut carry = 0u64;
+            for i in (0us..LIMBS) {
+                let add = (self.v[i] as u64) + (b.v[i] as u64) + carry;
+                v.v[i] = add as u32;
+                carry = add >> 32;
+            }
+            (v, carry as u32)
+        }
+
+        // return (self - b mod 2^384, 1 if self < b else 0)
+        fn sub_no_reduce(&self, b: &Scalar) -> (Scalar, u32) {
+            let mut v = ZERO;
+            let mut carry_sub = 0u64;
+            for i in (0us..LIMBS) {
+                let sub = (self.v[i] as u64) - (b.v[i] as u64) - carry_sub;
+                carry_sub = sub >> 63;
+                v.v[i] = sub as u32;
+            }
+            (v, carry_sub as u32)
+        }
+
+        // precondition: `self + carry * 2^384 < 2 * N`
+        // return `(self + carry * 2^384) mod N`
+        fn reduce_once(&self, carry: u32) -> Scalar {
+            let (v, carry_sub) = self.sub_no_reduce(&N);
+            debug_assert!(!(carry_sub == 0 && carry == 1)); // precondition violated
+            let choose_new = carry ^ carry_sub;
+            Scalar::choose(choose_new, &v, self)
+        }
+
+        // return `self * b / 2^384 mod N` (Montgomery multiplication)
+        fn mont_mult(&self, b: &Scalar) -> Scalar {
+            let mut t = [0u32; LIMBS + 2];
+            for i in (0us..LIMBS) {
+                // t += self * b[i]
+                let mut c = 0u64;
+                for j in (0us..LIMBS) {
+                    let s = (t[j] as u64) + (self.v[j] as u64) * (b.v[i] as u64) + c;
+                    t[j] = s as u32;
+                    c = s >> 32;
+                }
+                let s = (t[LIMBS] as u64) + c;
+                t[LIMBS] = s as u32;
+                t[LIMBS + 1] = (s >> 32) as u32;
+
+                // t = (t + m * N) / 2^32
+                let m = ((t[0] as u64) * (N0_INV as u64)) & 0xffffffff;
+                let s = (t[0] as u64) + m * (N.v[0] as u64);
+                let mut c = s >> 32;
+                for j in (1us..LIMBS) {
+                    let s = (t[j] as u64) + m * (N.v[j] as u64) + c;
+                    t[j - 1] = s as u32;
+                    c = s >> 32;
+                }
+                let s = (t[LIMBS] as u64) + c;
+                t[LIMBS - 1] = s as u32;
+                t[LIMBS] = t[LIMBS + 1] + ((s >> 32) as u32);
+                t[LIMBS + 1] = 0;
+            }
+
+            // t < 2N
+            let mut v = ZERO;
+            for i in (0us..LIMBS) {
+                v.v[i] = t[i];
+            }
+            v.reduce_once(t[LIMBS])
+        }
+
+        pub fn add(&self, b: &Scalar) -> Scalar {
+            let (v, carry) = self.add_no_reduce(b);
+            v.reduce_once(carry)
+        }
+
+        pub fn mult(&self, b: &Scalar) -> Scalar {
+            self.mont_mult(b).mont_mult(&R2)
+        }
+
+        // return self^-1 = self^(N - 2)
+        // N is public, so this does not leak anything about self.
+        pub fn inverse(&self) -> Scalar {
+            let (e, _) = N.sub_no_reduce(&Scalar { v: [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] });
+
+            let a = self.mont_mult(&R2);
+            let mut y = ONE.mont_mult(&R2);
+            for i in (0us..LIMBS).rev() {
+                for j in (0us..32).rev() {
+                    y = y.mont_mult(&y);
+                    if (e.v[i] >> j) & 1 == 1 {
+                        y = y.mont_mult(&a);
+                    }
+                }
+            }
+            y.mont_mult(&ONE)
+        }
+
+        // big-endian, at most 48 bytes.
+        // return None if the value is not less than N.
+        pub fn from_bytes(b: &[u8]) -> Option<Scalar> {
+            if b.len() > 48 {
+                return None;
+            }
+
+            let mut x = ZERO;
+            for (i, &bi) in b.iter().rev().enumerate() {
+                x.v[i / 4] |= (bi as u32) << ((i % 4) * 8);
+            }
+
+            let (_, carry_sub) = x.sub_no_reduce(&N);
+            if carry_sub == 0 {
+                return None;
+            }
+            Some(x)
+        }
+
+        // convert a message digest into a scalar (SEC 1, 4.1.3, step 5):
+        // the leftmost 384 bits are taken, then reduced mod N.
+        pub fn from_hash(hash: &[u8]) -> Scalar {
+            let hash = if hash.len() > 48 {
+                &hash[..48]
+            } else {
+                hash
+            };
+
+            let mut x = ZERO;
+            for (i, &bi) in hash.iter().rev().enumerate() {
+                x.v[i / 4] |= (bi as u32) << ((i % 4) * 8);
+            }
+            // 2^384 < 2 * N
+            x.reduce_once(0)
+        }
+
+        // P384 < 2 * N
+        pub fn from_int384(x: &Int384) -> Scalar {
+            Scalar { v: x.v }.reduce_once(0)
+        }
+
+        pub fn to_int384(&self) -> Int384 {
+            Int384 { v: self.v }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{Scalar, N, ONE};
+
+        #[test]
+        fn test_scalar_inverse() {
+            let values = [
+                ONE,
+                Scalar { v: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12] },
+                Scalar { v: [0xccc52972, 0xecec196a, 0x48b0a77a, 0x581a0db2,
+                             0xf4372ddf, 0xc7634d81, 0xffffffff, 0xffffffff,
+                             0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff] },
+            ];
+            for a in values.iter() {
+                let a_inv = a.inverse();
+                assert_eq!(a_inv.mult(a).v, ONE.v);
+                assert_eq!(a_inv.inverse().v, a.v);
+            }
+
+            let n = N.to_int384().to_bytes();
+            assert!(Scalar::from_bytes(&n[]).is_none());
+            assert!(Scalar::from_hash(&n[]).is_zero());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{G, NPoint384, verify_ecdsa};
+    use super::scalar::{Scalar, N};
+    use super::int384::{Int384, ZERO};
+    use crypto::sha2::sha384;
+
+    #[test]
+    fn test_mult_scalar() {
+        let p = G.mult_scalar(&N.to_int384());
+        assert_eq!(p.z.compare(&ZERO), 0);
+
+        // 2G
+        let two = Int384 { v: [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] };
+        let p = G.mult_scalar(&two).normalize().to_uncompressed_bytes();
+        let expected = b"\x04\x08\xd9\x99\x05\x7b\xa3\xd2\xd9\x69\x26\x00\x45\xc5\x5b\x97\
+                         \xf0\x89\x02\x59\x59\xa6\xf4\x34\xd6\x51\xd2\x07\xd1\x9f\xb9\x6e\
+                         \x9e\x4f\xe0\xe8\x6e\xbe\x0e\x64\xf8\x5b\x96\xa9\xc7\x52\x95\xdf\
+                         \x61\x8e\x80\xf1\xfa\x5b\x1b\x3c\xed\xb7\xbf\xe8\xdf\xfd\x6d\xba\
+                         \x74\xb2\x75\xd8\x75\xbc\x6c\xc4\x3e\x90\x4e\x50\x5f\x25\x6a\xb4\
+                         \x25\x5f\xfd\x43\xe9\x4d\x39\xe2\x2d\x61\x50\x1e\x70\x0a\x94\x0e\
+                         \x80";
+        assert_eq!(&p[], &expected[]);
+        assert!(NPoint384::from_uncompressed_bytes(&p[]).is_some());
+
+        let mut broken = p.clone();
+        broken[96] ^= 1;
+        assert!(NPoint384::from_uncompressed_bytes(&broken[]).is_none());
+    }
+
+    #[test]
+    fn test_mult_double_scalar() {
+        let q = G.mult_scalar(&Scalar { v: [9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 0] }.to_int384());
+        let n = Scalar { v: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12] }.to_int384();
+        let m = Scalar { v: [0xdeadbeef, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x12345678] }.to_int384();
+
+        let expected = G.mult_scalar(&n).add(&q.mult_scalar(&m)).normalize();
+        let computed = G.mult_double_scalar(&n, &q, &m).normalize();
+        assert_eq!(expected.x.v, computed.x.v);
+        assert_eq!(expected.y.v, computed.y.v);
+    }
+
+    #[test]
+    fn test_verify_ecdsa() {
+        static KEY: &'static [u8] = &[
+            0x04, 0x2c, 0xa8, 0xc5, 0x60, 0xf9, 0x22, 0x80, 0x75, 0x6d, 0x4d, 0x4e,
+            0x59, 0x03, 0x04, 0x3a, 0x37, 0x05, 0x32, 0xb2, 0x46, 0x66, 0x9e, 0x61,
+            0x10, 0xb0, 0x33, 0xf9, 0xab, 0x15, 0x0d, 0x51, 0x86, 0x32, 0x08, 0xe2,
+            0x4b, 0xd7, 0x0c, 0xe3, 0x73, 0x8d, 0x02, 0x76, 0x38, 0xbb, 0xd5, 0x4c,
+            0xd2, 0x1f, 0xc9, 0x1a, 0x81, 0xa4, 0xcd, 0x7e, 0xa2, 0xab, 0x6c, 0x20,
+            0x59, 0x86, 0x76, 0x7e, 0x83, 0x09, 0x26, 0xc1, 0xbb, 0xf7, 0xef, 0xf7,
+            0x1c, 0x25, 0xa2, 0x24, 0x5d, 0x78, 0x7d, 0xd8, 0xcc, 0x01, 0x73, 0x1d,
+            0xb7, 0xc2, 0xd9, 0xb1, 0xf4, 0xab, 0x7c, 0xe1, 0x95, 0x9c, 0x83, 0x62,
+            0x4f,
+        ];
+        // ECDSA-SHA384 signature of "suruga"
+        static SIG: &'static [u8] = &[
+            0x30, 0x65, 0x02, 0x30, 0x47, 0x58, 0xc1, 0x73, 0x46, 0x8a, 0x4c, 0x4e,
+            0xae, 0x13, 0x17, 0x22, 0x40, 0xc3, 0xac, 0x2b, 0x9b, 0xd7, 0x3d, 0xd3,
+            0x19, 0x4a, 0xa7, 0x54, 0xbd, 0x29, 0xc4, 0x14, 0xdd, 0x03, 0x17, 0xcb,
+            0x1d, 0x46, 0x2a, 0xd3, 0x7a, 0xf0, 0xbb, 0x21, 0x9d, 0x3e, 0x34, 0x2a,
+            0x91, 0xd0, 0x67, 0x96, 0x02, 0x31, 0x00, 0xb7, 0xb3, 0x98, 0x99, 0x99,
+            0xa5, 0x5f, 0xd6, 0x1e, 0xb6, 0x5f, 0xe0, 0xc7, 0xd9, 0xd0, 0x78, 0x0f,
+            0xf1, 0xf2, 0x22, 0xef, 0xa6, 0x05, 0x2d, 0x6f, 0xa1, 0xea, 0xfb, 0xb8,
+            0x53, 0x82, 0x60, 0x3d, 0x38, 0x8b, 0xa2, 0xaa, 0x2f, 0x5a, 0x78, 0x2f,
+            0xd8, 0x4b, 0x06, 0x98, 0x67, 0x37, 0x17,
+        ];
+
+        let key = NPoint384::from_uncompressed_bytes(KEY).unwrap();
+        let hash = sha384(b"suruga");
+        assert!(verify_ecdsa(&key, &hash[], SIG));
+
+        let hash2 = sha384(b"surugA");
+        assert!(!verify_ecdsa(&key, &hash2[], SIG));
+
+        let mut sig = SIG.to_vec();
+        sig[10] ^= 1;
+        assert!(!verify_ecdsa(&key, &hash[], &sig[]));
+    }
+}
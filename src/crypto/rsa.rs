@@ -0,0 +1,452 @@
+// RSA signatures (RFC 3447)
+// private keys and their operations are SECRET. `Modulus::mod_exp` is constant-time.
+
+use std::cmp::Ordering;
+
+use tls_result::TlsResult;
+use der::DerReader;
+use util::crypto_compare;
+use crypto::bignum::{self, Modulus};
+use crypto::sha2::Hash;
+
+// DER encoding of DigestInfo without the digest itself.
+// the digest follows immediately.
+pub const SHA1_DIGEST_INFO: &'static [u8] = &[
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14,
+];
+pub const SHA256_DIGEST_INFO: &'static [u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+    0x05, 0x00, 0x04, 0x20,
+];
+pub const SHA384_DIGEST_INFO: &'static [u8] = &[
+    0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02,
+    0x05, 0x00, 0x04, 0x30,
+];
+pub const SHA512_DIGEST_INFO: &'static [u8] = &[
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+    0x05, 0x00, 0x04, 0x40,
+];
+
+// we don't accept toy keys.
+const MIN_MODULUS_BITS: usize = 1024;
+const MAX_MODULUS_BITS: usize = 8192;
+
+// MGF1 (RFC 3447 B.2.1)
+fn mgf1<H: Hash>(seed: &[u8], len: usize) -> Vec<u8> {
+    let mut seeded: H = Hash::new();
+    seeded.update(seed);
+
+    let mut mask = Vec::with_capacity(len + seeded.output_len());
+    let mut counter = 0u32;
+    while mask.len() < len {
+        let mut h = seeded.clone();
+        h.update(&[(counter >> 24) as u8, (counter >> 16) as u8,
+                   (counter >> 8) as u8, counter as u8]);
+        mask.push_all(&h.finalize()[]);
+        counter += 1;
+    }
+    mask.truncate(len);
+    mask
+}
+
+pub struct RsaPublicKey {
+    n: Modulus,
+    // big-endian without leading zeros
+    e: Vec<u8>,
+    // bit length of n
+    bits: usize,
+}
+
+impl RsaPublicKey {
+    pub fn new(n: &[u8], e: &[u8]) -> Option<RsaPublicKey> {
+        // n must be odd, and e must be odd and > 1
+        let n = match Modulus::new(n) {
+            Some(n) => n,
+            None => return None,
+        };
+        let e = bignum::strip_zeros(e);
+        if e.len() == 0 || e[e.len() - 1] & 1 == 0 ||
+           bignum::compare(e, &[1]) != Ordering::Greater {
+            return None;
+        }
+
+        let bits = n.bits();
+        if bits < MIN_MODULUS_BITS || bits > MAX_MODULUS_BITS {
+            return None;
+        }
+
+        Some(RsaPublicKey {
+            n: n,
+            e: e.to_vec(),
+            bits: bits,
+        })
+    }
+
+    // RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }
+    pub fn from_der(data: &[u8]) -> Option<RsaPublicKey> {
+        let mut reader = DerReader::new(data);
+        let mut seq = match reader.read_sequence() {
+            Ok(seq) => seq,
+            Err(..) => return None,
+        };
+        let n = match seq.read_unsigned_integer() {
+            Ok(n) => n,
+            Err(..) => return None,
+        };
+        let e = match seq.read_unsigned_integer() {
+            Ok(e) => e,
+            Err(..) => return None,
+        };
+        if seq.finish().is_err() || reader.finish().is_err() {
+            return None;
+        }
+
+        RsaPublicKey::new(n, e)
+    }
+
+    // byte length of the modulus
+    pub fn len(&self) -> usize {
+        self.n.len()
+    }
+
+    // RSAVP1: return `sig^e mod n` as `self.len()` bytes.
+    fn public_op(&self, sig: &[u8]) -> Option<Vec<u8>> {
+        if sig.len() != self.len() {
+            return None;
+        }
+        self.n.mod_exp(sig, &self.e[])
+    }
+
+    // EMSA-PKCS1-v1_5 encoding:
+    // EM = 0x00 || 0x01 || PS (0xff..) || 0x00 || T
+    fn encode_pkcs1(&self, digest_info: &[u8], hash: &[u8]) -> Option<Vec<u8>> {
+        let len = self.len();
+        let t_len = digest_info.len() + hash.len();
+        if len < t_len + 11 {
+            return None;
+        }
+
+        let mut em = Vec::with_capacity(len);
+        em.push(0x00);
+        em.push(0x01);
+        for _ in (0us..(len - t_len - 3)) {
+            em.push(0xff);
+        }
+        em.push(0x00);
+        em.push_all(digest_info);
+        em.push_all(hash);
+        Some(em)
+    }
+
+    // RSASSA-PKCS1-v1_5 verification.
+    // `digest_info` is one of `*_DIGEST_INFO` and `hash` is the corresponding digest.
+    pub fn verify_pkcs1(&self, digest_info: &[u8], hash: &[u8], sig: &[u8]) -> bool {
+        let em = match self.public_op(sig) {
+            Some(em) => em,
+            None => return false,
+        };
+
+        let expected = match self.encode_pkcs1(digest_info, hash) {
+            Some(expected) => expected,
+            None => return false,
+        };
+
+        crypto_compare(&em[], &expected[])
+    }
+
+    // RSASSA-PSS verification (RFC 3447 8.1.2) of the message digest `m_hash`.
+    // `H` is used for MGF1 too.
+    // the salt must be as long as the digest, which is what TLS requires.
+    pub fn verify_pss<H: Hash>(&self, m_hash: &[u8], sig: &[u8]) -> bool {
+        let em = match self.public_op(sig) {
+            Some(em) => em,
+            None => return false,
+        };
+
+        // EMSA-PSS-VERIFY (9.1.2) with emBits = modBits - 1
+        let em_bits = self.bits - 1;
+        let em_len = (em_bits + 7) / 8;
+        let mut hash: H = Hash::new();
+        let h_len = hash.output_len();
+        let s_len = h_len;
+        if m_hash.len() != h_len || em_len < h_len + s_len + 2 {
+            return false;
+        }
+        // `em` has `self.len()` bytes, which is one more than em_len if 8 divides em_bits.
+        let (top, em) = em.split_at(em.len() - em_len);
+        if top.iter().any(|b| *b != 0) || em[em_len - 1] != 0xbc {
+            return false;
+        }
+
+        let db_len = em_len - h_len - 1;
+        let masked_db = &em[..db_len];
+        let h = &em[db_len..(em_len - 1)];
+        let top_mask = 0xffu8 >> (8 * em_len - em_bits);
+        if masked_db[0] & !top_mask != 0 {
+            return false;
+        }
+
+        let mut db = mgf1::<H>(h, db_len);
+        for (d, m) in db.iter_mut().zip(masked_db.iter()) {
+            *d ^= *m;
+        }
+        db[0] &= top_mask;
+
+        // DB = PS (zeros) || 0x01 || salt
+        let ps_len = db_len - s_len - 1;
+        if db[..ps_len].iter().any(|b| *b != 0) || db[ps_len] != 0x01 {
+            return false;
+        }
+        let salt = &db[(ps_len + 1)..];
+
+        // M' = 0x00 * 8 || mHash || salt
+        hash.update(&[0u8; 8]);
+        hash.update(m_hash);
+        hash.update(salt);
+        crypto_compare(&hash.finalize()[], h)
+    }
+}
+
+pub struct RsaPrivateKey {
+    public: RsaPublicKey,
+    // SECRET
+    d: Vec<u8>,
+}
+
+impl RsaPrivateKey {
+    // RSAPrivateKey ::= SEQUENCE {
+    //     version Version,
+    //     modulus INTEGER,
+    //     publicExponent INTEGER,
+    //     privateExponent INTEGER,
+    //     prime1 INTEGER, prime2 INTEGER,
+    //     exponent1 INTEGER, exponent2 INTEGER,
+    //     coefficient INTEGER,
+    //     otherPrimeInfos OtherPrimeInfos OPTIONAL
+    // }
+    // only two-prime keys (version 0) are accepted.
+    pub fn from_der(data: &[u8]) -> Option<RsaPrivateKey> {
+        fn read(data: &[u8]) -> TlsResult<Option<RsaPrivateKey>> {
+            let mut reader = DerReader::new(data);
+            let mut seq = try!(reader.read_sequence());
+            try!(reader.finish());
+
+            let version = try!(seq.read_u32());
+            let n = try!(seq.read_unsigned_integer());
+            let e = try!(seq.read_unsigned_integer());
+            let d = try!(seq.read_unsigned_integer());
+            // CRT parameters are not used.
+            for _ in (0us..5) {
+                try!(seq.read_unsigned_integer());
+            }
+            try!(seq.finish());
+
+            if version != 0 {
+                return Ok(None);
+            }
+            let public = match RsaPublicKey::new(n, e) {
+                Some(public) => public,
+                None => return Ok(None),
+            };
+            // d < n, so it can't be longer.
+            let d = bignum::strip_zeros(d);
+            if d.len() > public.len() {
+                return Ok(None);
+            }
+
+            Ok(Some(RsaPrivateKey {
+                public: public,
+                d: d.to_vec(),
+            }))
+        }
+
+        match read(data) {
+            Ok(key) => key,
+            Err(..) => None,
+        }
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.public
+    }
+
+    // RSASSA-PKCS1-v1_5 signature generation.
+    // return None if the key is too short for `digest_info` and `hash`, or if the signature
+    // does not verify.
+    pub fn sign_pkcs1(&self, digest_info: &[u8], hash: &[u8]) -> Option<Vec<u8>> {
+        let em = match self.public.encode_pkcs1(digest_info, hash) {
+            Some(em) => em,
+            None => return None,
+        };
+
+        // em < n since it starts with 0x00
+        let sig = match self.public.n.mod_exp(&em[], &self.d[]) {
+            Some(sig) => sig,
+            None => return None,
+        };
+
+        // a faulty signature may reveal the factors of n. never let it out.
+        if !self.public.verify_pkcs1(digest_info, hash, &sig[]) {
+            return None;
+        }
+        Some(sig)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crypto::sha2::{sha256, sha384, sha512, Sha256, Sha384};
+    use super::{RsaPublicKey, RsaPrivateKey};
+    use super::{SHA256_DIGEST_INFO, SHA384_DIGEST_INFO, SHA512_DIGEST_INFO};
+
+    // RSAPublicKey of a 1024-bit test key
+    static PUBLIC_KEY: &'static [u8] =
+        b"\x30\x81\x89\x02\x81\x81\x00\xe7\x16\xd7\x3d\xcb\xd4\x94\xbe\xdb\
+          \x68\xd4\xe3\xd4\xde\xee\x15\x1c\xab\x9d\x18\xb8\x4b\x85\x01\x2f\
+          \x11\x37\xc9\x9d\x23\xd2\x12\x9d\x3a\x2b\xeb\xd3\xf3\xdd\xe1\xc8\
+          \x96\xab\xab\xab\x65\x16\x84\xbc\xca\x6f\x03\xc3\x24\x95\x6e\x69\
+          \x01\x0a\xd8\x0f\x72\x04\x79\x2e\xb5\x16\xc7\xc8\x52\x88\x86\xf5\
+          \xdf\xe6\x5e\xb0\x1a\x16\x60\x15\x4e\x66\xf1\x9e\x02\x81\x5f\x18\
+          \xb8\x15\xd3\x2f\x03\xb4\x6c\x0d\xf2\xd1\x0c\xee\xcd\xbc\xb7\x45\
+          \xd3\x34\x82\x11\x11\x26\xad\x5f\x29\xfc\x44\xca\x19\x24\xaf\xfc\
+          \x0e\x81\x18\xca\x9b\x62\x3b\x02\x03\x01\x00\x01";
+
+    // RSAPrivateKey corresponding to `PUBLIC_KEY`
+    static PRIVATE_KEY: &'static [u8] = include_bytes!("../test_data/rsa1024_key.der");
+
+    // PKCS #1 v1.5 SHA-256 signature of "suruga"
+    static SIGNATURE: &'static [u8] =
+        b"\x9b\x39\x67\x12\x13\x3a\x7f\x7e\x13\x12\xc3\xc1\x60\xc7\xba\x93\
+          \xe4\xf0\x1c\x6e\x7a\xbf\x77\x11\x51\x84\x24\x76\xfc\xa4\xac\x01\
+          \x8f\xe2\x9a\xc0\x90\x1a\x1a\x8e\xc0\x99\xbc\x40\xc7\xd7\xeb\x83\
+          \xe2\x57\xaa\xc5\x74\x40\x75\xb6\xad\x37\xea\xca\xdc\x5e\x73\xb9\
+          \x71\x67\x56\xb0\x28\xe1\x8d\xab\x7d\x37\x26\xc1\xdb\xe5\xe6\xe5\
+          \x53\x6f\xa5\x90\x42\xf9\xcb\x6e\x4b\x6d\xb2\x44\x11\x84\xf5\x0d\
+          \x2f\xe3\x44\xef\x2f\xfc\x24\xee\xb7\x62\xaa\x4e\xa0\x23\xa1\x60\
+          \xbb\xad\xb4\x21\x31\xf1\xe7\xd5\x2c\x05\x48\x6a\x1e\x60\xde\xc6";
+
+    #[test]
+    fn test_verify_pkcs1() {
+        let key = RsaPublicKey::from_der(PUBLIC_KEY).expect("failed to parse key");
+        assert_eq!(key.len(), 128);
+
+        let hash = sha256(b"suruga");
+        assert!(key.verify_pkcs1(SHA256_DIGEST_INFO, &hash[], SIGNATURE));
+
+        let hash = sha256(b"suruga!");
+        assert!(!key.verify_pkcs1(SHA256_DIGEST_INFO, &hash[], SIGNATURE));
+
+        let mut sig = SIGNATURE.to_vec();
+        sig[0] ^= 1;
+        let hash = sha256(b"suruga");
+        assert!(!key.verify_pkcs1(SHA256_DIGEST_INFO, &hash[], &sig[]));
+    }
+
+    #[test]
+    fn test_sign_pkcs1() {
+        let key = RsaPrivateKey::from_der(PRIVATE_KEY).expect("failed to parse key");
+        assert_eq!(key.public_key().len(), 128);
+
+        let hash = sha256(b"suruga");
+        let sig = key.sign_pkcs1(SHA256_DIGEST_INFO, &hash[]).unwrap();
+        assert_eq!(&sig[], SIGNATURE);
+        assert!(key.public_key().verify_pkcs1(SHA256_DIGEST_INFO, &hash[], &sig[]));
+
+        assert!(RsaPrivateKey::from_der(PUBLIC_KEY).is_none());
+
+        // a corrupted private exponent gives a wrong signature, which is not returned.
+        let mut key = key;
+        let last = key.d.len() - 1;
+        key.d[last] ^= 2;
+        assert!(key.sign_pkcs1(SHA256_DIGEST_INFO, &hash[]).is_none());
+    }
+
+    // PKCS #1 v1.5 SHA-384 and SHA-512 signatures of "suruga"
+    static SIGNATURE_SHA384: &'static [u8] =
+        b"\x90\xfa\x23\x61\x41\x8f\xbb\x0e\x0b\x6e\x0b\xe7\xaa\x6d\xe0\x8a\
+          \xe4\x41\x59\x62\xa6\x49\x2c\xbd\xc4\x09\x19\xcb\xe3\x87\x3b\xeb\
+          \x66\xe9\xac\x8d\x26\x1d\xc1\x1c\x9b\x8c\x9d\x56\xc3\xde\x11\x22\
+          \xe6\xdb\x64\x6f\x91\x65\x60\x5c\x69\x20\xf1\x12\xfd\x9c\x99\xf2\
+          \x82\x6d\xa8\x30\xa2\x64\xac\x70\x70\xc1\x4b\xaa\x61\x77\xb1\xd5\
+          \x39\x45\xf8\x92\x47\xcb\x44\x92\xe4\x8a\x2a\x07\x52\x34\x90\x7d\
+          \xd2\x5c\x3b\xa4\xa1\xfd\xff\x0f\x6a\x73\xa4\xad\xb6\x8e\xd8\xe0\
+          \x3b\xcf\xf2\xd5\x42\x51\x2c\x5b\xa4\xe1\x2f\xc5\x36\xf7\x6d\x3c";
+    static SIGNATURE_SHA512: &'static [u8] =
+        b"\xc6\x94\x1d\xd3\x11\x77\x2e\x59\xcb\x86\x90\x45\x3f\xb5\xdc\x6d\
+          \xb9\xf5\x6f\x50\x31\x45\x3c\x44\xb5\x69\x00\xce\x02\x50\xd4\x5c\
+          \x76\x2c\x83\xdd\xeb\xcc\xb5\xcf\x50\x83\x35\x6d\xbc\xc8\x97\xdb\
+          \xe0\xa0\x02\x7c\x50\x57\x1c\x13\xd0\xe3\x31\x17\x46\x05\xde\x7a\
+          \x51\xb3\x63\x49\x93\xde\x2d\xdf\x0f\xa1\x38\xfa\xc7\x26\xe2\x3e\
+          \xa0\x0b\x9e\x0e\x7e\x88\xaa\x44\xce\x29\xfe\x4a\x08\xf2\xfd\x31\
+          \x6c\xa8\x47\xfa\x25\x4b\xa4\x5e\x0d\xb1\xe9\xc4\x2b\xe1\xc4\x2f\
+          \xa2\xe4\xec\xe6\xe0\xb9\x50\xba\xa5\xf1\x13\xba\x77\x4b\x9e\x9a";
+
+    // RSASSA-PSS SHA-256 signature of "suruga" with 32-byte salt
+    static SIGNATURE_PSS: &'static [u8] =
+        b"\x4c\xa1\xc6\x89\x2c\x85\xe0\xbc\x11\xb7\x49\x52\xb9\x8b\x98\x3d\
+          \x51\xfd\x7e\x05\x5a\x2f\x7f\x5a\x27\x3a\x76\x7c\x2f\xbf\x1d\xbb\
+          \x3c\xf0\xa3\x8a\x1d\x12\xfa\xee\xe0\x90\xdf\x40\x33\x0e\xe1\xb7\
+          \xfd\x5c\x97\xb8\xb6\x72\x92\xe7\x77\x10\xf8\x7d\x0e\xb8\x67\x1c\
+          \x8d\x5e\xf0\xd7\xb5\xea\xf9\x2e\x32\x73\x72\x3a\x75\x7c\x73\x5c\
+          \x5e\x4a\xfa\xf2\x94\xaa\x0a\xc6\xe0\xf1\x04\x2b\x37\x73\xcf\x90\
+          \x12\x86\x33\x50\x74\x79\xb4\xb5\x5b\xa1\x07\xa4\x10\x25\x21\x1d\
+          \xfd\xdb\x30\x4c\x45\x61\xdc\xef\x68\x6b\xcd\x71\x51\xeb\xdc\x2a";
+
+    // RSAPublicKey of a 1025-bit key: the encoded message is shorter than the modulus.
+    static PUBLIC_KEY_1025: &'static [u8] =
+        b"\x30\x81\x89\x02\x81\x81\x01\x66\xc6\x24\xf5\xfe\x67\x11\x4b\x8a\
+          \x25\x9c\x77\x52\xde\xd3\xa3\x86\x45\x89\xdb\x0a\x94\x57\x0b\x09\
+          \x98\xad\x7b\x8c\xcb\xd1\xef\xf9\x31\xac\x50\x55\xc9\x42\xe1\x12\
+          \x2a\x78\xce\x80\xe2\x1e\xe3\xe3\x67\x3a\x74\x4d\x52\xf2\x09\xeb\
+          \xa4\x4e\x10\x81\xbe\x08\x89\x8e\x44\xe9\x5e\xdc\x86\x35\xc8\x9b\
+          \x2e\xa5\x11\x2e\xdb\xa5\x8d\x0b\x78\x0d\x24\xfd\x3d\xa8\xa1\x81\
+          \xcb\x62\xd5\x36\x78\x90\x23\xfc\xa6\x62\x19\xd0\x9f\x35\xdc\x4e\
+          \x46\xe4\x70\x69\x8e\xc8\x6e\x24\xce\xeb\x2a\x59\xa7\x83\x8f\x66\
+          \x0c\x0e\xd8\xa5\xe3\x16\x8b\x02\x03\x01\x00\x01";
+
+    // RSASSA-PSS SHA-384 signature of "suruga" by `PUBLIC_KEY_1025` with 48-byte salt
+    static SIGNATURE_PSS_1025: &'static [u8] =
+        b"\x00\x36\x00\x5a\xdc\x5d\xbc\xba\x52\xc5\x54\xcf\x6e\x45\x58\xd5\
+          \xe1\x10\xef\x02\x72\x6e\x86\x3d\xb5\xc0\xb1\xf7\x14\xeb\xa2\x2d\
+          \x19\x05\x35\xd2\x0a\x6d\x9e\x02\x85\xce\xcf\xc1\xa5\xdd\xa2\xdd\
+          \x7a\x8b\x15\x50\x18\x1b\x99\xec\x70\xe3\x37\xb8\xad\x3c\xf6\x2d\
+          \x51\xb5\xa6\x00\x9b\xc0\xd4\xf4\xf1\x57\xc1\x43\xaf\x52\xee\x86\
+          \xf8\x08\x06\xd0\xed\xd1\x71\x2a\xfe\x92\x65\xe9\x6b\x67\x98\x6f\
+          \x16\x35\xae\xb1\x7d\x6d\x40\xa6\x96\xb3\x89\x17\x0c\xdb\x59\xf9\
+          \x76\xa2\xc0\x4d\xe1\xaf\x3c\x82\x43\xf2\x35\xc6\xd9\x92\x87\x6b\
+          \x22";
+
+    #[test]
+    fn test_verify_pkcs1_sha384_sha512() {
+        let key = RsaPublicKey::from_der(PUBLIC_KEY).unwrap();
+
+        let hash = sha384(b"suruga");
+        assert!(key.verify_pkcs1(SHA384_DIGEST_INFO, &hash[], SIGNATURE_SHA384));
+        assert!(!key.verify_pkcs1(SHA256_DIGEST_INFO, &hash[], SIGNATURE_SHA384));
+
+        let hash = sha512(b"suruga");
+        assert!(key.verify_pkcs1(SHA512_DIGEST_INFO, &hash[], SIGNATURE_SHA512));
+        assert!(!key.verify_pkcs1(SHA512_DIGEST_INFO, &hash[], SIGNATURE_SHA384));
+    }
+
+    #[test]
+    fn test_verify_pss() {
+        let key = RsaPublicKey::from_der(PUBLIC_KEY).unwrap();
+        let hash = sha256(b"suruga");
+        assert!(key.verify_pss::<Sha256>(&hash[], SIGNATURE_PSS));
+        assert!(!key.verify_pss::<Sha256>(&sha256(b"surugA")[], SIGNATURE_PSS));
+        assert!(!key.verify_pss::<Sha384>(&sha384(b"suruga")[], SIGNATURE_PSS));
+        // a PKCS #1 v1.5 signature is not a PSS signature
+        assert!(!key.verify_pss::<Sha256>(&hash[], SIGNATURE));
+
+        let mut sig = SIGNATURE_PSS.to_vec();
+        sig[127] ^= 1;
+        assert!(!key.verify_pss::<Sha256>(&hash[], &sig[]));
+
+        let key = RsaPublicKey::from_der(PUBLIC_KEY_1025).unwrap();
+        assert_eq!(key.len(), 129);
+        let hash = sha384(b"suruga");
+        assert!(key.verify_pss::<Sha384>(&hash[], SIGNATURE_PSS_1025));
+        assert!(!key.verify_pss::<Sha384>(&hash[], &SIGNATURE_PSS_1025[1..]));
+    }
+}
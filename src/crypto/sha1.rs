@@ -0,0 +1,156 @@
+// http://csrc.nist.gov/publications/fips/fips180-4/fips-180-4.pdf 6.1
+// only for signing to peers without signature_algorithms (RFC 5246 7.4.1.4.1).
+// we never accept SHA-1 signatures.
+
+use crypto::sha2::{Hash, update_blocks};
+
+const INIT_VAL: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+fn compress(val: &mut [u32; 5], block: &[u8]) {
+    fn rot(a: u32, b: usize) -> u32 {
+        (a << b) | (a >> (32 - b))
+    }
+
+    let mut w = [0u32; 80];
+    for j in 0..16us {
+        let b0 = block[j * 4 + 0] as u32;
+        let b1 = block[j * 4 + 1] as u32;
+        let b2 = block[j * 4 + 2] as u32;
+        let b3 = block[j * 4 + 3] as u32;
+        w[j] = (b0 << 8 * 3) | (b1 << 8 * 2) | (b2 << 8 * 1) | b3;
+    }
+    for j in 16..80us {
+        w[j] = rot(w[j - 3] ^ w[j - 8] ^ w[j - 14] ^ w[j - 16], 1);
+    }
+
+    let mut a: u32 = val[0];
+    let mut b: u32 = val[1];
+    let mut c: u32 = val[2];
+    let mut d: u32 = val[3];
+    let mut e: u32 = val[4];
+
+    for j in 0..80us {
+        let (f, k) = if j < 20 {
+            ((b & c) | ((!b) & d), 0x5a827999u32)
+        } else if j < 40 {
+            (b ^ c ^ d, 0x6ed9eba1)
+        } else if j < 60 {
+            ((b & c) | (b & d) | (c & d), 0x8f1bbcdc)
+        } else {
+            (b ^ c ^ d, 0xca62c1d6)
+        };
+
+        let t = rot(a, 5) + f + e + k + w[j];
+        e = d;
+        d = c;
+        c = rot(b, 30);
+        b = a;
+        a = t;
+    }
+
+    val[0] += a;
+    val[1] += b;
+    val[2] += c;
+    val[3] += d;
+    val[4] += e;
+}
+
+#[derive(Copy)]
+pub struct Sha1 {
+    val: [u32; 5],
+    // pending input shorter than a block
+    buf: [u8; 64],
+    buf_len: usize,
+    // total input length in bytes
+    len: u64,
+}
+
+impl Clone for Sha1 {
+    fn clone(&self) -> Sha1 {
+        *self
+    }
+}
+
+impl Hash for Sha1 {
+    fn new() -> Sha1 {
+        Sha1 {
+            val: INIT_VAL,
+            buf: [0u8; 64],
+            buf_len: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        let val = &mut self.val;
+        update_blocks(&mut self.buf, &mut self.buf_len, data, |block| compress(val, block));
+    }
+
+    // same padding as SHA-256.
+    fn finalize(mut self) -> Vec<u8> {
+        let bitlen = self.len * 8;
+        let mut pad = [0u8; 1 + 63 + 8];
+        pad[0] = 0x80;
+        let pad_len = 1 + ((64 - 8 - 1 - self.buf_len) & 63) + 8;
+        for i in 0us..8 {
+            pad[pad_len - 1 - i] = (bitlen >> (8 * i)) as u8;
+        }
+        self.update(&pad[..pad_len]);
+        debug_assert_eq!(self.buf_len, 0);
+
+        let mut ret = Vec::with_capacity(20);
+        for i in 0..5us {
+            ret.push((self.val[i] >> 8 * 3) as u8);
+            ret.push((self.val[i] >> 8 * 2) as u8);
+            ret.push((self.val[i] >> 8 * 1) as u8);
+            ret.push(self.val[i] as u8);
+        }
+        ret
+    }
+
+    fn output_len(&self) -> usize {
+        20
+    }
+
+    fn block_len(&self) -> usize {
+        64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crypto::sha2::Hash;
+    use super::Sha1;
+
+    fn sha1(msg: &[u8]) -> Vec<u8> {
+        let mut hash: Sha1 = Hash::new();
+        hash.update(msg);
+        hash.finalize()
+    }
+
+    #[test]
+    fn test_sha1() {
+        static ANSWERS: &'static [(&'static [u8], &'static [u8])] = &[
+            (b"",
+             b"\xda\x39\xa3\xee\x5e\x6b\x4b\x0d\x32\x55\xbf\xef\x95\x60\x18\x90\
+               \xaf\xd8\x07\x09"),
+            (b"abc",
+             b"\xa9\x99\x3e\x36\x47\x06\x81\x6a\xba\x3e\x25\x71\x78\x50\xc2\x6c\
+               \x9c\xd0\xd8\x9d"),
+            (b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+             b"\x84\x98\x3e\x44\x1c\x3b\xd2\x6e\xba\xae\x4a\xa1\xf9\x51\x29\xe5\
+               \xe5\x46\x70\xf1"),
+        ];
+
+        for &(input, expected) in ANSWERS.iter() {
+            assert_eq!(expected, &sha1(input)[]);
+
+            let mut hash: Sha1 = Hash::new();
+            for b in input.iter() {
+                hash.update(&[*b]);
+            }
+            assert_eq!(expected, &hash.finalize()[]);
+        }
+    }
+}
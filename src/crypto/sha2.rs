@@ -2,6 +2,8 @@
 // not seriously audited.
 // no bit-level support. sorry
 
+use std::cmp;
+
 const INIT_VAL: [u32; 8] = [
     0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
     0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
@@ -31,107 +33,421 @@ macro_rules! be_u32 {
     })
 }
 
-pub fn sha256(msg: &[u8]) -> [u8; 32] {
+// incremental hash function. `clone` forks the state, e.g. to hash a prefix of a longer input.
+pub trait Hash: Clone {
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Vec<u8>;
+    // digest size in bytes
+    fn output_len(&self) -> usize;
+    // input block size in bytes (HMAC needs it)
+    fn block_len(&self) -> usize;
+}
+
+// buffer `data` into `buf` (holding `*buf_len` bytes) and call `compress` for every full block.
+// shared with SHA-1.
+pub fn update_blocks<F>(buf: &mut [u8], buf_len: &mut usize, data: &[u8], mut compress: F)
+    where F: FnMut(&[u8])
+{
+    let block_len = buf.len();
+    let mut data = data;
+
+    if *buf_len > 0 {
+        let n = cmp::min(block_len - *buf_len, data.len());
+        for (b, d) in buf[*buf_len..(*buf_len + n)].iter_mut().zip(data.iter()) {
+            *b = *d;
+        }
+        *buf_len += n;
+        data = &data[n..];
+        if *buf_len < block_len {
+            return;
+        }
+        compress(&buf[]);
+        *buf_len = 0;
+    }
+
+    while data.len() >= block_len {
+        compress(&data[..block_len]);
+        data = &data[block_len..];
+    }
+
+    for (b, d) in buf.iter_mut().zip(data.iter()) {
+        *b = *d;
+    }
+    *buf_len = data.len();
+}
+
+fn compress_256(val: &mut [u32; 8], block: &[u8]) {
     fn rot(a: u32, b: usize) -> u32 {
         (a >> b) | (a << (32 - b))
     }
 
-    let len = msg.len();
-    let mut msg = msg.to_vec();
+    let w = {
+        let mut w = [0u32; 64];
+        for j in 0..16us {
+            w[j] = be_u32!(block[j * 4]);
+        }
+
+        for j in 16..64us {
+            let wj15 = w[j - 15];
+            let sig0 = rot(wj15, 7) ^ rot(wj15, 18) ^ (wj15 >> 3);
 
-    msg.push(0x80);
-    for _ in 0..((64 - 8 - 1 - len) & 63) {
-        msg.push(0);
+            let wj2 = w[j - 2];
+            let sig1 = rot(wj2, 17) ^ rot(wj2, 19) ^ (wj2 >> 10);
+            w[j] = sig1 + w[j - 7] + sig0 + w[j - 16];
+        }
+
+        w
+    };
+
+    let mut a: u32 = val[0];
+    let mut b: u32 = val[1];
+    let mut c: u32 = val[2];
+    let mut d: u32 = val[3];
+    let mut e: u32 = val[4];
+    let mut f: u32 = val[5];
+    let mut g: u32 = val[6];
+    let mut h: u32 = val[7];
+
+    for j in 0..64us {
+        let ch = (e & f) ^ ((!e) & g);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+
+        let sig0 = rot(a, 2) ^ rot(a, 13) ^ rot(a, 22);
+        let sig1 = rot(e, 6) ^ rot(e, 11) ^ rot(e, 25);
+
+        let t1 = h + sig1 + ch + K[j] + w[j];
+        let t2 = sig0 + maj;
+
+        h = g;
+        g = f;
+        f = e;
+        e = d + t1;
+        d = c;
+        c = b;
+        b = a;
+        a = t1 + t2;
     }
 
-    let bitlen = (len as u64) * 8; // FIXME: is overflow intended in spec?
-    for i in (0us..8us).rev() {
-        let b = (bitlen >> (8 * i)) as u8;
-        msg.push(b);
+    val[0] += a;
+    val[1] += b;
+    val[2] += c;
+    val[3] += d;
+    val[4] += e;
+    val[5] += f;
+    val[6] += g;
+    val[7] += h;
+}
+
+#[derive(Copy)]
+pub struct Sha256 {
+    val: [u32; 8],
+    // pending input shorter than a block
+    buf: [u8; 64],
+    buf_len: usize,
+    // total input length in bytes
+    len: u64,
+}
+
+impl Clone for Sha256 {
+    fn clone(&self) -> Sha256 {
+        *self
     }
+}
 
-    debug_assert_eq!(msg.len() % (512 / 8), 0);
+impl Hash for Sha256 {
+    fn new() -> Sha256 {
+        Sha256 {
+            val: INIT_VAL,
+            buf: [0u8; 64],
+            buf_len: 0,
+            len: 0,
+        }
+    }
 
-    let nblk = msg.len() / (512 / 8);
+    fn update(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        let val = &mut self.val;
+        update_blocks(&mut self.buf, &mut self.buf_len, data, |block| compress_256(val, block));
+    }
 
-    let mut val = INIT_VAL;
+    fn finalize(mut self) -> Vec<u8> {
+        // 0x80, zeros and the 64-bit length fill up the last block.
+        let bitlen = self.len * 8; // FIXME: is overflow intended in spec?
+        let mut pad = [0u8; 1 + 63 + 8];
+        pad[0] = 0x80;
+        let pad_len = 1 + ((64 - 8 - 1 - self.buf_len) & 63) + 8;
+        for i in 0us..8 {
+            pad[pad_len - 1 - i] = (bitlen >> (8 * i)) as u8;
+        }
+        self.update(&pad[..pad_len]);
+        debug_assert_eq!(self.buf_len, 0);
 
-    for i in (0..nblk) {
-        let w = {
-            let mut w = [0u32; 64];
-            for j in 0..16us {
-                let b0 = msg[i * 64 + j * 4 + 0] as u32;
-                let b1 = msg[i * 64 + j * 4 + 1] as u32;
-                let b2 = msg[i * 64 + j * 4 + 2] as u32;
-                let b3 = msg[i * 64 + j * 4 + 3] as u32;
-                w[j] = (b0 << 8 * 3) | (b1 << 8 * 2) | (b2 << 8 * 1) | b3;
-            }
+        let mut ret = Vec::with_capacity(32);
+        for i in 0..8us {
+            ret.push((self.val[i] >> 8 * 3) as u8);
+            ret.push((self.val[i] >> 8 * 2) as u8);
+            ret.push((self.val[i] >> 8 * 1) as u8);
+            ret.push(self.val[i] as u8);
+        }
+        ret
+    }
 
-            for j in 16..64us {
-                let wj15 = w[j - 15];
-                let sig0 = rot(wj15, 7) ^ rot(wj15, 18) ^ (wj15 >> 3);
+    fn output_len(&self) -> usize {
+        32
+    }
 
-                let wj2 = w[j - 2];
-                let sig1 = rot(wj2, 17) ^ rot(wj2, 19) ^ (wj2 >> 10);
-                w[j] = sig1 + w[j - 7] + sig0 + w[j - 16];
+    fn block_len(&self) -> usize {
+        64
+    }
+}
+
+pub fn sha256(msg: &[u8]) -> [u8; 32] {
+    let mut hash: Sha256 = Hash::new();
+    hash.update(msg);
+    let mut ret = [0u8; 32];
+    for (r, h) in ret.iter_mut().zip(hash.finalize().iter()) {
+        *r = *h;
+    }
+    ret
+}
+
+const INIT_VAL_512: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const INIT_VAL_384: [u64; 8] = [
+    0xcbbb9d5dc1059ed8, 0x629a292a367cd507, 0x9159015a3070dd17, 0x152fecd8f70e5939,
+    0x67332667ffc00b31, 0x8eb44a8768581511, 0xdb0c2e0d64f98fa7, 0x47b5481dbefa4fa4,
+];
+
+static K_512: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+    0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+    0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+    0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+    0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+    0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+    0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+    0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+    0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+    0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+    0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+fn compress_512(val: &mut [u64; 8], block: &[u8]) {
+    fn rot(a: u64, b: usize) -> u64 {
+        (a >> b) | (a << (64 - b))
+    }
+
+    let w = {
+        let mut w = [0u64; 80];
+        for j in 0..16us {
+            let mut v = 0u64;
+            for k in 0..8us {
+                v = (v << 8) | (block[j * 8 + k] as u64);
             }
+            w[j] = v;
+        }
+
+        for j in 16..80us {
+            let wj15 = w[j - 15];
+            let sig0 = rot(wj15, 1) ^ rot(wj15, 8) ^ (wj15 >> 7);
+
+            let wj2 = w[j - 2];
+            let sig1 = rot(wj2, 19) ^ rot(wj2, 61) ^ (wj2 >> 6);
+            w[j] = sig1 + w[j - 7] + sig0 + w[j - 16];
+        }
+
+        w
+    };
+
+    let mut a: u64 = val[0];
+    let mut b: u64 = val[1];
+    let mut c: u64 = val[2];
+    let mut d: u64 = val[3];
+    let mut e: u64 = val[4];
+    let mut f: u64 = val[5];
+    let mut g: u64 = val[6];
+    let mut h: u64 = val[7];
+
+    for j in 0..80us {
+        let ch = (e & f) ^ ((!e) & g);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+
+        let sig0 = rot(a, 28) ^ rot(a, 34) ^ rot(a, 39);
+        let sig1 = rot(e, 14) ^ rot(e, 18) ^ rot(e, 41);
+
+        let t1 = h + sig1 + ch + K_512[j] + w[j];
+        let t2 = sig0 + maj;
+
+        h = g;
+        g = f;
+        f = e;
+        e = d + t1;
+        d = c;
+        c = b;
+        b = a;
+        a = t1 + t2;
+    }
+
+    val[0] += a;
+    val[1] += b;
+    val[2] += c;
+    val[3] += d;
+    val[4] += e;
+    val[5] += f;
+    val[6] += g;
+    val[7] += h;
+}
 
-            w
-        };
+// SHA-512 state. SHA-384 differs only in initial values and truncation.
+#[derive(Copy)]
+struct Sha512State {
+    val: [u64; 8],
+    // pending input shorter than a block
+    buf: [u8; 128],
+    buf_len: usize,
+    // total input length in bytes
+    len: u64,
+}
 
-        let mut a: u32 = val[0];
-        let mut b: u32 = val[1];
-        let mut c: u32 = val[2];
-        let mut d: u32 = val[3];
-        let mut e: u32 = val[4];
-        let mut f: u32 = val[5];
-        let mut g: u32 = val[6];
-        let mut h: u32 = val[7];
+impl Clone for Sha512State {
+    fn clone(&self) -> Sha512State {
+        *self
+    }
+}
 
-        for j in 0..64us {
-            let ch = (e & f) ^ ((!e) & g);
-            let maj = (a & b) ^ (a & c) ^ (b & c);
+impl Sha512State {
+    fn new(init: [u64; 8]) -> Sha512State {
+        Sha512State {
+            val: init,
+            buf: [0u8; 128],
+            buf_len: 0,
+            len: 0,
+        }
+    }
 
-            let sig0 = rot(a, 2) ^ rot(a, 13) ^ rot(a, 22);
-            let sig1 = rot(e, 6) ^ rot(e, 11) ^ rot(e, 25);
+    fn update(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        let val = &mut self.val;
+        update_blocks(&mut self.buf, &mut self.buf_len, data, |block| compress_512(val, block));
+    }
 
-            let t1 = h + sig1 + ch + K[j] + w[j];
-            let t2 = sig0 + maj;
+    // the first `len` bytes of the digest.
+    fn finalize(mut self, len: usize) -> Vec<u8> {
+        // 0x80, zeros and the 128-bit length fill up the last block.
+        // upper 64 bits of the length are always zero for us.
+        let bitlen = self.len * 8;
+        let mut pad = [0u8; 1 + 127 + 16];
+        pad[0] = 0x80;
+        let pad_len = 1 + ((128 - 16 - 1 - self.buf_len) & 127) + 16;
+        for i in 0us..8 {
+            pad[pad_len - 1 - i] = (bitlen >> (8 * i)) as u8;
+        }
+        self.update(&pad[..pad_len]);
+        debug_assert_eq!(self.buf_len, 0);
 
-            h = g;
-            g = f;
-            f = e;
-            e = d + t1;
-            d = c;
-            c = b;
-            b = a;
-            a = t1 + t2;
+        let mut ret = Vec::with_capacity(len);
+        for i in 0..len {
+            ret.push((self.val[i / 8] >> (8 * (7 - i % 8))) as u8);
         }
+        ret
+    }
+}
 
-        val[0] += a;
-        val[1] += b;
-        val[2] += c;
-        val[3] += d;
-        val[4] += e;
-        val[5] += f;
-        val[6] += g;
-        val[7] += h;
+#[derive(Clone)]
+pub struct Sha512 {
+    state: Sha512State,
+}
 
+impl Hash for Sha512 {
+    fn new() -> Sha512 {
+        Sha512 {
+            state: Sha512State::new(INIT_VAL_512),
+        }
     }
 
-    let mut ret = [0u8; 32];
-    for i in 0..8us {
-        ret[i * 4 + 0] = (val[i] >> 8 * 3) as u8;
-        ret[i * 4 + 1] = (val[i] >> 8 * 2) as u8;
-        ret[i * 4 + 2] = (val[i] >> 8 * 1) as u8;
-        ret[i * 4 + 3] = val[i] as u8;
+    fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        self.state.finalize(64)
+    }
+
+    fn output_len(&self) -> usize {
+        64
+    }
+
+    fn block_len(&self) -> usize {
+        128
+    }
+}
+
+#[derive(Clone)]
+pub struct Sha384 {
+    state: Sha512State,
+}
+
+impl Hash for Sha384 {
+    fn new() -> Sha384 {
+        Sha384 {
+            state: Sha512State::new(INIT_VAL_384),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        self.state.finalize(48)
+    }
+
+    fn output_len(&self) -> usize {
+        48
+    }
+
+    fn block_len(&self) -> usize {
+        128
+    }
+}
+
+pub fn sha512(msg: &[u8]) -> [u8; 64] {
+    let mut hash: Sha512 = Hash::new();
+    hash.update(msg);
+    let mut ret = [0u8; 64];
+    for (r, h) in ret.iter_mut().zip(hash.finalize().iter()) {
+        *r = *h;
+    }
+    ret
+}
+
+pub fn sha384(msg: &[u8]) -> [u8; 48] {
+    let mut hash: Sha384 = Hash::new();
+    hash.update(msg);
+    let mut ret = [0u8; 48];
+    for (r, h) in ret.iter_mut().zip(hash.finalize().iter()) {
+        *r = *h;
     }
     ret
 }
 
 #[cfg(test)]
 mod test {
-    use super::sha256;
+    use std::iter::repeat;
+    use super::{sha256, sha384, sha512, Hash, Sha256, Sha384, Sha512};
 
     #[test]
     fn test_sha256() {
@@ -152,4 +468,87 @@ mod test {
             assert_eq!(expected, &computed[]);
         }
     }
+
+    #[test]
+    fn test_sha384_sha512() {
+        static MSG: &'static [u8] =
+            b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmno\
+              ijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+
+        static ANSWERS_384: &'static [(&'static [u8], &'static [u8])] = &[
+            (b"abc",
+             b"\xcb\x00\x75\x3f\x45\xa3\x5e\x8b\xb5\xa0\x3d\x69\x9a\xc6\x50\x07\
+               \x27\x2c\x32\xab\x0e\xde\xd1\x63\x1a\x8b\x60\x5a\x43\xff\x5b\xed\
+               \x80\x86\x07\x2b\xa1\xe7\xcc\x23\x58\xba\xec\xa1\x34\xc8\x25\xa7"),
+            (MSG,
+             b"\x09\x33\x0c\x33\xf7\x11\x47\xe8\x3d\x19\x2f\xc7\x82\xcd\x1b\x47\
+               \x53\x11\x1b\x17\x3b\x3b\x05\xd2\x2f\xa0\x80\x86\xe3\xb0\xf7\x12\
+               \xfc\xc7\xc7\x1a\x55\x7e\x2d\xb9\x66\xc3\xe9\xfa\x91\x74\x60\x39"),
+        ];
+
+        static ANSWERS_512: &'static [(&'static [u8], &'static [u8])] = &[
+            (b"",
+             b"\xcf\x83\xe1\x35\x7e\xef\xb8\xbd\xf1\x54\x28\x50\xd6\x6d\x80\x07\
+               \xd6\x20\xe4\x05\x0b\x57\x15\xdc\x83\xf4\xa9\x21\xd3\x6c\xe9\xce\
+               \x47\xd0\xd1\x3c\x5d\x85\xf2\xb0\xff\x83\x18\xd2\x87\x7e\xec\x2f\
+               \x63\xb9\x31\xbd\x47\x41\x7a\x81\xa5\x38\x32\x7a\xf9\x27\xda\x3e"),
+            (MSG,
+             b"\x8e\x95\x9b\x75\xda\xe3\x13\xda\x8c\xf4\xf7\x28\x14\xfc\x14\x3f\
+               \x8f\x77\x79\xc6\xeb\x9f\x7f\xa1\x72\x99\xae\xad\xb6\x88\x90\x18\
+               \x50\x1d\x28\x9e\x49\x00\xf7\xe4\x33\x1b\x99\xde\xc4\xb5\x43\x3a\
+               \xc7\xd3\x29\xee\xb6\xdd\x26\x54\x5e\x96\xe5\x5b\x87\x4b\xe9\x09"),
+        ];
+
+        for &(input, expected) in ANSWERS_384.iter() {
+            let computed = sha384(input);
+            assert_eq!(expected, &computed[]);
+        }
+
+        for &(input, expected) in ANSWERS_512.iter() {
+            let computed = sha512(input);
+            assert_eq!(expected, &computed[]);
+        }
+    }
+
+    fn check_streaming<H: Hash>(oneshot: fn(&[u8]) -> Vec<u8>) {
+        let msg: Vec<u8> = (0us..1000).map(|i| (i * 7) as u8).collect();
+
+        // feed pieces crossing block boundaries in different ways
+        for &step in [1us, 3, 63, 64, 65, 127, 128, 129, 1000].iter() {
+            let mut hash: H = Hash::new();
+            for chunk in msg.chunks(step) {
+                hash.update(chunk);
+            }
+            assert_eq!(hash.finalize(), oneshot(&msg[]));
+        }
+
+        // a clone taken mid-stream hashes only the prefix
+        let mut hash: H = Hash::new();
+        hash.update(&msg[..300]);
+        let prefix = hash.clone();
+        hash.update(&msg[300..]);
+        assert_eq!(prefix.finalize(), oneshot(&msg[..300]));
+        assert_eq!(hash.finalize(), oneshot(&msg[]));
+    }
+
+    #[test]
+    fn test_streaming() {
+        fn sha256_vec(msg: &[u8]) -> Vec<u8> { sha256(msg).to_vec() }
+        fn sha384_vec(msg: &[u8]) -> Vec<u8> { sha384(msg).to_vec() }
+        fn sha512_vec(msg: &[u8]) -> Vec<u8> { sha512(msg).to_vec() }
+
+        check_streaming::<Sha256>(sha256_vec);
+        check_streaming::<Sha384>(sha384_vec);
+        check_streaming::<Sha512>(sha512_vec);
+
+        // FIPS 180-2 long message: one million 'a's
+        let chunk: Vec<u8> = repeat(0x61u8).take(1000).collect();
+        let mut hash: Sha256 = Hash::new();
+        for _ in 0us..1000 {
+            hash.update(&chunk[]);
+        }
+        let expected: &[u8] = b"\xcd\xc7\x6e\x5c\x99\x14\xfb\x92\x81\xa1\xc7\xe2\x84\xd7\x3e\x67\
+                                \xf1\x80\x9a\x48\xa4\x97\x20\x0e\x04\x6d\x39\xcc\xc7\x11\x2c\xd0";
+        assert_eq!(expected, &hash.finalize()[]);
+    }
 }
@@ -0,0 +1,249 @@
+// minimal DER reader and writer (ITU-T X.690)
+// only single-byte tags and definite lengths are supported.
+// it is enough for X.509 certificates, keys and signatures.
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::DecodeError;
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_PRINTABLE_STRING: u8 = 0x13;
+pub const TAG_IA5_STRING: u8 = 0x16;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+
+// [n] EXPLICIT
+pub fn context_explicit(n: u8) -> u8 {
+    0xa0 | n
+}
+
+// [n] IMPLICIT for primitive types
+pub fn context_implicit(n: u8) -> u8 {
+    0x80 | n
+}
+
+// encode `contents` with `tag`.
+pub fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let len = contents.len();
+    let mut ret = Vec::with_capacity(len + 6);
+    ret.push(tag);
+    if len < 0x80 {
+        ret.push(len as u8);
+    } else {
+        let mut len_bytes = Vec::new();
+        let mut n = len;
+        while n > 0 {
+            len_bytes.insert(0, n as u8);
+            n >>= 8;
+        }
+        ret.push(0x80 | (len_bytes.len() as u8));
+        ret.push_all(&len_bytes[]);
+    }
+    ret.push_all(contents);
+    ret
+}
+
+// encode big-endian `value` as a non-negative INTEGER.
+pub fn encode_unsigned_integer(value: &[u8]) -> Vec<u8> {
+    let mut start = 0;
+    while start + 1 < value.len() && value[start] == 0 {
+        start += 1;
+    }
+    let value = &value[start..];
+
+    let mut contents = Vec::with_capacity(value.len() + 1);
+    if value.len() == 0 || value[0] & 0x80 != 0 {
+        contents.push(0);
+    }
+    contents.push_all(value);
+    encode(TAG_INTEGER, &contents[])
+}
+
+pub struct DerReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DerReader<'a> {
+    pub fn new(data: &'a [u8]) -> DerReader<'a> {
+        DerReader {
+            data: data,
+            pos: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.data[self.pos])
+        }
+    }
+
+    // return (tag, contents, whole encoding including tag and length)
+    pub fn read_raw(&mut self) -> TlsResult<(u8, &'a [u8], &'a [u8])> {
+        let data: &'a [u8] = self.data;
+        let rest = &data[self.pos..];
+        if rest.len() < 2 {
+            return tls_err!(DecodeError, "DER: unexpected end of data");
+        }
+
+        let tag = rest[0];
+        if tag & 0x1f == 0x1f {
+            return tls_err!(DecodeError, "DER: high tag number is not supported");
+        }
+
+        let (len, len_len) = {
+            let b = rest[1] as usize;
+            if b < 0x80 {
+                (b, 1)
+            } else {
+                let n = b & 0x7f;
+                // indefinite length (n == 0) is BER-only.
+                if n == 0 || n > 4 {
+                    return tls_err!(DecodeError, "DER: bad length octet: {}", b);
+                }
+                if rest.len() < 2 + n {
+                    return tls_err!(DecodeError, "DER: unexpected end of data");
+                }
+                let mut len = 0us;
+                for i in (0us..n) {
+                    len = (len << 8) | (rest[2 + i] as usize);
+                }
+                if len < 0x80 || rest[2] == 0 {
+                    return tls_err!(DecodeError, "DER: length is not minimally encoded");
+                }
+                (len, 1 + n)
+            }
+        };
+
+        let header_len = 1 + len_len;
+        if rest.len() - header_len < len {
+            return tls_err!(DecodeError, "DER: unexpected end of data");
+        }
+
+        let contents = &rest[header_len..(header_len + len)];
+        let whole = &rest[..(header_len + len)];
+        self.pos += header_len + len;
+
+        Ok((tag, contents, whole))
+    }
+
+    pub fn read_tagged(&mut self, expected: u8) -> TlsResult<&'a [u8]> {
+        let (tag, contents, _) = try!(self.read_raw());
+        if tag != expected {
+            return tls_err!(DecodeError, "DER: expected tag {} but found {}", expected, tag);
+        }
+        Ok(contents)
+    }
+
+    // read the next element only if its tag is `expected`.
+    pub fn read_optional(&mut self, expected: u8) -> TlsResult<Option<&'a [u8]>> {
+        if self.peek_tag() != Some(expected) {
+            return Ok(None);
+        }
+        let contents = try!(self.read_tagged(expected));
+        Ok(Some(contents))
+    }
+
+    pub fn read_sequence(&mut self) -> TlsResult<DerReader<'a>> {
+        let contents = try!(self.read_tagged(TAG_SEQUENCE));
+        Ok(DerReader::new(contents))
+    }
+
+    // non-negative INTEGER as big-endian bytes, without the leading zero octet.
+    pub fn read_unsigned_integer(&mut self) -> TlsResult<&'a [u8]> {
+        let contents = try!(self.read_tagged(TAG_INTEGER));
+        if contents.len() == 0 {
+            return tls_err!(DecodeError, "DER: empty INTEGER");
+        }
+        if contents[0] & 0x80 != 0 {
+            return tls_err!(DecodeError, "DER: negative INTEGER");
+        }
+        if contents.len() > 1 && contents[0] == 0 {
+            if contents[1] & 0x80 == 0 {
+                return tls_err!(DecodeError, "DER: INTEGER is not minimally encoded");
+            }
+            return Ok(&contents[1..]);
+        }
+        Ok(contents)
+    }
+
+    // BIT STRING whose length is a multiple of 8 (keys, signatures)
+    pub fn read_bit_string(&mut self) -> TlsResult<&'a [u8]> {
+        let (unused_bits, bits) = try!(self.read_named_bits());
+        if unused_bits != 0 {
+            return tls_err!(DecodeError, "DER: unexpected BIT STRING padding");
+        }
+        Ok(bits)
+    }
+
+    // BIT STRING as (number of unused bits, bytes)
+    pub fn read_named_bits(&mut self) -> TlsResult<(u8, &'a [u8])> {
+        let contents = try!(self.read_tagged(TAG_BIT_STRING));
+        if contents.len() == 0 {
+            return tls_err!(DecodeError, "DER: empty BIT STRING");
+        }
+        let unused_bits = contents[0];
+        if unused_bits > 7 || (contents.len() == 1 && unused_bits != 0) {
+            return tls_err!(DecodeError, "DER: bad BIT STRING padding");
+        }
+        Ok((unused_bits, &contents[1..]))
+    }
+
+    pub fn read_boolean(&mut self) -> TlsResult<bool> {
+        let contents = try!(self.read_tagged(TAG_BOOLEAN));
+        if contents.len() != 1 {
+            return tls_err!(DecodeError, "DER: bad BOOLEAN");
+        }
+        match contents[0] {
+            0x00 => Ok(false),
+            0xff => Ok(true),
+            _ => tls_err!(DecodeError, "DER: BOOLEAN is not minimally encoded"),
+        }
+    }
+
+    // small non-negative INTEGER
+    pub fn read_u32(&mut self) -> TlsResult<u32> {
+        let contents = try!(self.read_unsigned_integer());
+        if contents.len() > 4 {
+            return tls_err!(DecodeError, "DER: INTEGER too large");
+        }
+        let mut n = 0u32;
+        for &b in contents.iter() {
+            n = (n << 8) | (b as u32);
+        }
+        Ok(n)
+    }
+
+    pub fn read_oid(&mut self) -> TlsResult<&'a [u8]> {
+        self.read_tagged(TAG_OID)
+    }
+
+    pub fn read_null(&mut self) -> TlsResult<()> {
+        let contents = try!(self.read_tagged(TAG_NULL));
+        if contents.len() != 0 {
+            return tls_err!(DecodeError, "DER: non-empty NULL");
+        }
+        Ok(())
+    }
+
+    // ensure that every element is consumed.
+    pub fn finish(&self) -> TlsResult<()> {
+        if !self.is_empty() {
+            return tls_err!(DecodeError, "DER: trailing data");
+        }
+        Ok(())
+    }
+}
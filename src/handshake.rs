@@ -1,11 +1,14 @@
-use std::io::MemReader;
+use std::io::BufReader;
 
 use tls::TLS_VERSION;
 use tls_result::TlsResult;
-use tls_result::TlsErrorKind::{InternalError, UnexpectedMessage};
+use tls_result::TlsErrorKind::{InternalError, UnexpectedMessage, DecodeError};
 use tls_item::{TlsItem, DummyItem, ObscureData};
-use signature::SignatureAndHashAlgorithmVec;
+use signature::{SignatureAndHashAlgorithm, SignatureAndHashAlgorithmVec, DigitallySigned,
+                HashAlgorithm};
 use cipher::CipherSuite;
+use cipher::prf;
+use crypto::sha2::{Hash, Sha256, Sha384};
 
 // This is actually `struct { gmt_unix_time: u32, random_bytes: [u8, ..28] }`
 // cf: http://tools.ietf.org/html/draft-mathewson-no-gmtunixtime-00
@@ -37,6 +40,11 @@ tls_enum!(u16, enum NamedCurve {
     secp192r1 (19), secp224k1 (20), secp224r1 (21),
     secp256k1 (22), secp256r1 (23), secp384r1 (24),
     secp521r1 (25),
+    // RFC 7748, draft-ietf-tls-rfc4492bis
+    x25519 (29),
+    // RFC 7919
+    ffdhe2048 (256), ffdhe3072 (257), ffdhe4096 (258),
+    ffdhe6144 (259), ffdhe8192 (260),
     arbitrary_explicit_prime_curves(0xFF01),
     arbitrary_explicit_char2_curves(0xFF02)
 });
@@ -48,6 +56,31 @@ tls_enum!(u8, enum ECPointFormat {
 });
 tls_vec!(ECPointFormatList = ECPointFormat(1, (1 << 8) - 1));
 
+// RFC 6066
+
+tls_enum!(u8, enum NameType {
+    host_name(0)
+});
+tls_vec!(HostName = u8(1, (1 << 16) - 1));
+tls_struct!(struct ServerName {
+    name_type: NameType,
+    host_name: HostName
+});
+tls_vec!(ServerNameList = ServerName(1, (1 << 16) - 1));
+
+// RFC 5077
+tls_vec!(SessionTicket = u8(0, (1 << 16) - 1));
+
+// RFC 5746
+tls_vec!(RenegotiatedConnection = u8(0, (1 << 8) - 1));
+
+// RFC 7627: `extension_data` is always empty.
+tls_vec!(ExtendedMasterSecret = u8(0, (1 << 16) - 1));
+
+// RFC 7301
+tls_vec!(ProtocolName = u8(1, (1 << 8) - 1));
+tls_vec!(ProtocolNameList = ProtocolName(2, (1 << 16) - 1));
+
 // FIXME: Extension has the following structure:
 // struct Extension {
 //     extension_type: u8,
@@ -56,12 +89,22 @@ tls_vec!(ECPointFormatList = ECPointFormat(1, (1 << 8) - 1));
 // and actual structure of `extension_data` depends on `extension_type`.
 // Note that this is not exactly what `tls_enum_struct` wants!
 // It's why we define horrible structs here.
+// server_name in ServerHello has empty `extension_data`.
+tls_vec!(ServerNameListList = ServerNameList(0, (1 << 16) - 1));
 tls_vec!(EllipticCurveListList = EllipticCurveList(1, (1 << 16) - 1));
 tls_vec!(ECPointFormatListList = ECPointFormatList(1, (1 << 16) - 1));
+// this always has exactly one element.
+tls_vec!(RenegotiationInfo = RenegotiatedConnection(1, (1 << 16) - 1));
+// this always has exactly one element.
+tls_vec!(ProtocolNameListList = ProtocolNameList(1, (1 << 16) - 1));
+// this always has exactly one element.
+tls_vec!(SignatureAndHashAlgorithmVecList = SignatureAndHashAlgorithmVec(1, (1 << 16) - 1));
+// `extension_data` of extensions we don't know.
+tls_vec!(ExtensionData = u8(0, (1 << 16) - 1));
 
 tls_enum_struct!(u16, enum Extension {
     // RFC 6066
-    //server_name(0),
+    server_name(ServerNameListList) = 0,
     //max_fragment_length(1),
     //client_certificate_url(2),
     //trusted_ca_keys(3),
@@ -69,11 +112,31 @@ tls_enum_struct!(u16, enum Extension {
     //status_request(5),
     // RFC 4492
     elliptic_curves(EllipticCurveListList) = 10,
-    ec_point_formats(ECPointFormatListList) = 11
+    ec_point_formats(ECPointFormatListList) = 11,
     // RFC 5246
-    //signature_algorithms(13)
-});
+    signature_algorithms(SignatureAndHashAlgorithmVecList) = 13,
+    // RFC 7301
+    application_layer_protocol_negotiation(ProtocolNameListList) = 16,
+    // RFC 7627
+    extended_master_secret(ExtendedMasterSecret) = 23,
+    // RFC 5077: `extension_data` is the ticket itself.
+    session_ticket(SessionTicket) = 35,
+    // RFC 5746
+    renegotiation_info(RenegotiationInfo) = 0xff01
+} UnknownExtension(ExtensionData));
 impl Extension {
+    // `host_name` is a DNS name without the trailing dot.
+    pub fn new_server_name(host_name: Vec<u8>) -> TlsResult<Extension> {
+        let name = ServerName {
+            name_type: NameType::host_name,
+            host_name: try!(HostName::new(host_name)),
+        };
+        let list = try!(ServerNameList::new(vec!(name)));
+        let list = try!(ServerNameListList::new(vec!(list)));
+        let list = Extension::server_name(list);
+        Ok(list)
+    }
+
     pub fn new_elliptic_curve_list(list: Vec<NamedCurve>) -> TlsResult<Extension> {
         let list = try!(EllipticCurveList::new(list));
         let list = try!(EllipticCurveListList::new(vec!(list)));
@@ -87,6 +150,80 @@ impl Extension {
         let list = Extension::ec_point_formats(list);
         Ok(list)
     }
+
+    // `ticket` is empty if the client has no ticket or the server will issue a new one.
+    pub fn new_session_ticket(ticket: Vec<u8>) -> TlsResult<Extension> {
+        let ticket = try!(SessionTicket::new(ticket));
+        Ok(Extension::session_ticket(ticket))
+    }
+
+    // `renegotiated_connection` is empty for the initial handshake.
+    // otherwise it is the verify data of the previous handshake.
+    pub fn new_renegotiation_info(renegotiated_connection: Vec<u8>) -> TlsResult<Extension> {
+        let info = try!(RenegotiatedConnection::new(renegotiated_connection));
+        let info = try!(RenegotiationInfo::new(vec!(info)));
+        Ok(Extension::renegotiation_info(info))
+    }
+
+    pub fn new_extended_master_secret() -> TlsResult<Extension> {
+        let data = try!(ExtendedMasterSecret::new(Vec::new()));
+        Ok(Extension::extended_master_secret(data))
+    }
+
+    pub fn new_signature_algorithms(list: Vec<SignatureAndHashAlgorithm>)
+                                    -> TlsResult<Extension> {
+        let list = try!(SignatureAndHashAlgorithmVec::new(list));
+        let list = try!(SignatureAndHashAlgorithmVecList::new(vec!(list)));
+        Ok(Extension::signature_algorithms(list))
+    }
+
+    // ServerHello has exactly one protocol.
+    pub fn new_application_layer_protocol_negotiation(protocols: Vec<Vec<u8>>)
+                                                      -> TlsResult<Extension> {
+        let mut names = Vec::with_capacity(protocols.len());
+        for protocol in protocols.into_iter() {
+            names.push(try!(ProtocolName::new(protocol)));
+        }
+        let list = try!(ProtocolNameList::new(names));
+        let list = try!(ProtocolNameListList::new(vec!(list)));
+        Ok(Extension::application_layer_protocol_negotiation(list))
+    }
+}
+
+impl RenegotiationInfo {
+    pub fn renegotiated_connection(&self) -> TlsResult<&[u8]> {
+        if self.len() != 1 {
+            return tls_err!(DecodeError, "bad renegotiation_info extension");
+        }
+        Ok(&(**self)[0][])
+    }
+}
+
+impl ExtendedMasterSecret {
+    pub fn check(&self) -> TlsResult<()> {
+        if self.len() != 0 {
+            return tls_err!(DecodeError, "bad extended_master_secret extension");
+        }
+        Ok(())
+    }
+}
+
+impl SignatureAndHashAlgorithmVecList {
+    pub fn algorithms(&self) -> TlsResult<&[SignatureAndHashAlgorithm]> {
+        if self.len() != 1 {
+            return tls_err!(DecodeError, "bad signature_algorithms extension");
+        }
+        Ok(&(**self)[0][])
+    }
+}
+
+impl ProtocolNameListList {
+    pub fn protocol_names(&self) -> TlsResult<Vec<Vec<u8>>> {
+        if self.len() != 1 {
+            return tls_err!(DecodeError, "bad application_layer_protocol_negotiation extension");
+        }
+        Ok((**self)[0].iter().map(|name| name.to_vec()).collect())
+    }
 }
 
 tls_vec!(ExtensionVec = Extension(0, (1 << 16) - 1));
@@ -180,12 +317,13 @@ tls_handshake!(
     client_hello(ClientHello) = 1,
     server_hello(ServerHello) = 2,
     // hello_verify_request(..) = 3, RFC 6347: DTLS
-    // NewSessionTicket(..) = 4, RFC 5077: session resumption w/o server-side state
+    // RFC 5077: session resumption w/o server-side state
+    new_session_ticket(NewSessionTicket) = 4,
     certificate(CertificateList) = 11,
     server_key_exchange(ObscureData) = 12,
     certificate_request(CertificateRequest) = 13,
     server_hello_done(DummyItem) = 14,
-    // certificate_verify = 15,
+    certificate_verify(DigitallySigned) = 15,
     client_key_exchange(ObscureData) = 16,
     finished(VerifyData) = 20,
 );
@@ -213,7 +351,9 @@ tls_vec!(CertificateList = Asn1Cert(0, (1 << 24) - 1));
 tls_enum!(u8, enum ClientCertificateType {
       rsa_sign(1), dss_sign(2), rsa_fixed_dh(3), dss_fixed_dh(4),
       rsa_ephemeral_dh_RESERVED(5), dss_ephemeral_dh_RESERVED(6),
-      fortezza_dms_RESERVED(20)
+      fortezza_dms_RESERVED(20),
+      // RFC 4492
+      ecdsa_sign(64), rsa_fixed_ecdh(65), ecdsa_fixed_ecdh(66)
 });
 tls_vec!(CertificiateTypeVec = ClientCertificateType(1, (1 << 8) - 1));
 
@@ -226,6 +366,12 @@ tls_struct!(struct CertificateRequest {
     certificate_authorities: DistinguishedNameVec
 });
 
+// RFC 5077
+tls_struct!(struct NewSessionTicket {
+    ticket_lifetime_hint: u32,
+    ticket: SessionTicket
+});
+
 // FIXME TLS 1.2 says the length can be longer for future ciphe suites.
 tls_array!(VerifyData = [u8, ..12]);
 
@@ -243,8 +389,9 @@ impl HandshakeBuffer {
         self.buf.push_all(&fragment[]);
     }
 
+    // return the message with its encoding as received.
     // if message is arrived but has unknown type, the message is discarded and returns error.
-    pub fn get_message(&mut self) -> TlsResult<Option<Handshake>> {
+    pub fn get_message(&mut self) -> TlsResult<Option<(Handshake, Vec<u8>)>> {
         let len = self.buf.len();
         // we need to read at least ty and length
         if len < 4 {
@@ -270,17 +417,63 @@ impl HandshakeBuffer {
         };
         self.buf = remaining;
 
-        let mut reader = MemReader::new(message);
-        let message: Handshake = try!(TlsItem::tls_read(&mut reader));
-        let ret = Ok(Some(message));
+        let parsed: Handshake = {
+            let mut reader = BufReader::new(&message[]);
+            try!(TlsItem::tls_read(&mut reader))
+        };
+        Ok(Some((parsed, message)))
+    }
+}
+
+// RFC 5246 7.4.9: handshake messages of the current handshake, exactly as they were sent or
+// received. Finished, CertificateVerify and the session hash of RFC 7627 are computed over it.
+// a ClientHello starts a new transcript and HelloRequest is never included (7.4.1.1).
+// running hashes are kept for the PRF hashes of our cipher suites.
+pub struct HandshakeTranscript {
+    msgs: Vec<u8>,
+    sha256: Sha256,
+    sha384: Sha384,
+}
+
+impl HandshakeTranscript {
+    pub fn new() -> HandshakeTranscript {
+        HandshakeTranscript {
+            msgs: Vec::new(),
+            sha256: Hash::new(),
+            sha384: Hash::new(),
+        }
+    }
+
+    // `msg` is a whole handshake message including the type and length.
+    pub fn add_message(&mut self, msg: &[u8]) {
+        match msg[0] {
+            0 => return, // hello_request
+            1 => *self = HandshakeTranscript::new(), // client_hello
+            _ => {}
+        }
+        self.msgs.push_all(msg);
+        self.sha256.update(msg);
+        self.sha384.update(msg);
+    }
 
-        ret
+    pub fn messages(&self) -> &[u8] {
+        &self.msgs[]
+    }
+
+    pub fn hash(&self, hash: HashAlgorithm) -> Vec<u8> {
+        match hash {
+            HashAlgorithm::sha256 => self.sha256.clone().finalize(),
+            HashAlgorithm::sha384 => self.sha384.clone().finalize(),
+            _ => prf::hash(hash, &self.msgs[]),
+        }
     }
 }
 
 impl Handshake {
+    // `session_id` is empty unless the client tries to resume a session.
     pub fn new_client_hello(random: Random,
-                            cipher_suite: CipherSuite,
+                            session_id: Vec<u8>,
+                            cipher_suites: Vec<CipherSuite>,
                             extensions: Vec<Extension>) -> TlsResult<Handshake> {
         let client_hello_body = {
             let client_version = {
@@ -292,16 +485,9 @@ impl Handshake {
                 }
             };
 
-            // TODO support session resumption
-            let session_id = {
-                let data = Vec::new();
-                try!(SessionId::new(data))
-            };
+            let session_id = try!(SessionId::new(session_id));
 
-            let cipher_suites = {
-                let data = vec!(cipher_suite);
-                try!(CipherSuiteVec::new(data))
-            };
+            let cipher_suites = try!(CipherSuiteVec::new(cipher_suites));
 
             let compression_methods = {
                 let data = vec!(CompressionMethod::null);
@@ -327,11 +513,82 @@ impl Handshake {
         Ok(Handshake::client_hello(client_hello_body))
     }
 
+    // `session_id` is empty if the session can't be resumed.
+    pub fn new_server_hello(random: Random,
+                            session_id: Vec<u8>,
+                            cipher_suite: CipherSuite,
+                            extensions: Vec<Extension>) -> TlsResult<Handshake> {
+        let server_version = {
+            let (major, minor) = TLS_VERSION;
+
+            ProtocolVersion {
+                major: major,
+                minor: minor,
+            }
+        };
+
+        let session_id = try!(SessionId::new(session_id));
+
+        let extensions = if extensions.len() == 0 {
+            None
+        } else {
+            let ext = try!(ExtensionVec::new(extensions));
+            Some(ext)
+        };
+
+        let server_hello_body = ServerHello {
+            server_version: server_version,
+            random: random,
+            session_id: session_id,
+            cipher_suite: cipher_suite,
+            compression_method: CompressionMethod::null,
+            extensions: extensions,
+        };
+
+        Ok(Handshake::server_hello(server_hello_body))
+    }
+
+    // `certs` are DER-encoded certificates, the sender's own certificate first.
+    pub fn new_certificate(certs: Vec<Vec<u8>>) -> TlsResult<Handshake> {
+        let mut list = Vec::with_capacity(certs.len());
+        for cert in certs.into_iter() {
+            list.push(try!(Asn1Cert::new(cert)));
+        }
+        let list = try!(CertificateList::new(list));
+        Ok(Handshake::certificate(list))
+    }
+
+    pub fn new_server_key_exchange(data: Vec<u8>) -> TlsResult<Handshake> {
+        let data = ObscureData::new(data);
+        Ok(Handshake::server_key_exchange(data))
+    }
+
+    // any certificate authority is accepted.
+    pub fn new_certificate_request(certificate_types: Vec<ClientCertificateType>,
+                                   algorithms: Vec<SignatureAndHashAlgorithm>)
+                                   -> TlsResult<Handshake> {
+        let request = CertificateRequest {
+            certificate_types: try!(CertificiateTypeVec::new(certificate_types)),
+            supported_signature_algorithms: try!(SignatureAndHashAlgorithmVec::new(algorithms)),
+            certificate_authorities: try!(DistinguishedNameVec::new(Vec::new())),
+        };
+        Ok(Handshake::certificate_request(request))
+    }
+
     pub fn new_client_key_exchange(data: Vec<u8>) -> TlsResult<Handshake> {
         let data = ObscureData::new(data);
         Ok(Handshake::client_key_exchange(data))
     }
 
+    // `lifetime_hint` is in seconds.
+    pub fn new_new_session_ticket(lifetime_hint: u32, ticket: Vec<u8>) -> TlsResult<Handshake> {
+        let body = NewSessionTicket {
+            ticket_lifetime_hint: lifetime_hint,
+            ticket: try!(SessionTicket::new(ticket)),
+        };
+        Ok(Handshake::new_session_ticket(body))
+    }
+
     pub fn new_finished(data: Vec<u8>) -> TlsResult<Handshake> {
         let data = try!(VerifyData::new(data));
         Ok(Handshake::finished(data))
@@ -341,11 +598,15 @@ impl Handshake {
 #[cfg(test)]
 mod test {
     use std::io::MemReader;
+    use std::iter::repeat;
     use tls_item::TlsItem;
     use cipher::CipherSuite;
+    use cipher::prf;
+    use signature::{SignatureAndHashAlgorithm, HashAlgorithm, SignatureAlgorithm};
 
     use super::{ProtocolVersion, SessionId, CipherSuiteVec, CompressionMethod,
-                CompressionMethodVec, ClientHello, Handshake, Random};
+                CompressionMethodVec, ClientHello, Handshake, Random, Extension,
+                HandshakeBuffer, HandshakeTranscript};
 
     #[test]
     fn test_parse_client_hello() {
@@ -404,4 +665,200 @@ mod test {
 
         assert_eq!(packet, packet_2);
     }
+
+    #[test]
+    fn test_handshake_buffer() {
+        // ServerHello with an empty extensions block
+        let random = Random::new(repeat(1u8).take(32).collect()).unwrap();
+        let suite = CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256;
+        let server_hello = Handshake::new_server_hello(random, Vec::new(), suite, Vec::new());
+        let mut msg = Vec::new();
+        server_hello.unwrap().tls_write(&mut msg).unwrap();
+        msg.push_all(&[0x00, 0x00]);
+        msg[3] += 2;
+
+        // the message is split over two records, followed by a part of the next one.
+        let mut buffer = HandshakeBuffer::new();
+        buffer.add_record(msg[..10].to_vec());
+        assert!(buffer.get_message().unwrap().is_none());
+        let mut rest = msg[10..].to_vec();
+        rest.push_all(&[14, 0]);
+        buffer.add_record(rest);
+
+        match buffer.get_message().unwrap() {
+            Some((Handshake::server_hello(..), data)) => assert_eq!(data, msg),
+            _ => panic!(),
+        }
+        assert!(buffer.get_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_handshake_transcript() {
+        let mut transcript = HandshakeTranscript::new();
+        transcript.add_message(&[1, 0, 0, 1, 7]);
+        transcript.add_message(&[2, 0, 0, 0]);
+        assert_eq!(transcript.messages(), &[1u8, 0, 0, 1, 7, 2, 0, 0, 0][]);
+
+        // HelloRequest is not a part of any handshake.
+        transcript.add_message(&[0, 0, 0, 0]);
+        assert_eq!(transcript.messages().len(), 9);
+
+        // renegotiation starts over.
+        transcript.add_message(&[1, 0, 0, 1, 8]);
+        assert_eq!(transcript.messages(), &[1u8, 0, 0, 1, 8][]);
+
+        transcript.add_message(&[2, 0, 0, 0]);
+        for &hash in [HashAlgorithm::sha256, HashAlgorithm::sha384, HashAlgorithm::sha512].iter() {
+            assert_eq!(transcript.hash(hash), prf::hash(hash, transcript.messages()));
+        }
+    }
+
+    #[test]
+    fn test_server_name_extension() {
+        let ext = Extension::new_server_name(b"suruga.example".to_vec()).unwrap();
+        let mut packet = Vec::new();
+        ext.tls_write(&mut packet).unwrap();
+
+        let mut expected = vec!(0x00, 0x00, 0x00, 0x13, 0x00, 0x11, 0x00, 0x00, 0x0e);
+        expected.push_all(b"suruga.example");
+        assert_eq!(packet, expected);
+
+        // empty server_name in ServerHello
+        let mut reader = MemReader::new(vec!(0x00, 0x00, 0x00, 0x00));
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::server_name(ref list) => assert_eq!(list.len(), 0),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_session_ticket_extension() {
+        let ext = Extension::new_session_ticket(vec!(1, 2, 3)).unwrap();
+        let mut packet = Vec::new();
+        ext.tls_write(&mut packet).unwrap();
+        assert_eq!(packet, vec!(0x00, 0x23, 0x00, 0x03, 1, 2, 3));
+
+        // empty session_ticket in ServerHello
+        let mut reader = MemReader::new(vec!(0x00, 0x23, 0x00, 0x00));
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::session_ticket(ref ticket) => assert_eq!(ticket.len(), 0),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_alpn_extension() {
+        let protocols = vec!(b"h2".to_vec(), b"http/1.1".to_vec());
+        let ext = Extension::new_application_layer_protocol_negotiation(protocols).unwrap();
+        let mut packet = Vec::new();
+        ext.tls_write(&mut packet).unwrap();
+
+        let mut expected = vec!(0x00, 0x10, 0x00, 0x0e, 0x00, 0x0c, 0x02);
+        expected.push_all(b"h2");
+        expected.push(0x08);
+        expected.push_all(b"http/1.1");
+        assert_eq!(packet, expected);
+
+        let mut reader = MemReader::new(packet);
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::application_layer_protocol_negotiation(ref list) => {
+                let names = list.protocol_names().unwrap();
+                assert_eq!(names, vec!(b"h2".to_vec(), b"http/1.1".to_vec()));
+            }
+            _ => panic!(),
+        }
+
+        // empty protocol names are not allowed
+        assert!(Extension::new_application_layer_protocol_negotiation(vec!(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_renegotiation_info_extension() {
+        let ext = Extension::new_renegotiation_info(Vec::new()).unwrap();
+        let mut packet = Vec::new();
+        ext.tls_write(&mut packet).unwrap();
+        assert_eq!(packet, vec!(0xff, 0x01, 0x00, 0x01, 0x00));
+
+        let mut reader = MemReader::new(vec!(0xff, 0x01, 0x00, 0x03, 0x02, 1, 2));
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::renegotiation_info(ref info) => {
+                assert_eq!(info.renegotiated_connection().unwrap(), &[1u8, 2][]);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_extended_master_secret_extension() {
+        let ext = Extension::new_extended_master_secret().unwrap();
+        let mut packet = Vec::new();
+        ext.tls_write(&mut packet).unwrap();
+        assert_eq!(packet, vec!(0x00, 0x17, 0x00, 0x00));
+
+        let mut reader = MemReader::new(packet);
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::extended_master_secret(ref data) => assert!(data.check().is_ok()),
+            _ => panic!(),
+        }
+
+        let mut reader = MemReader::new(vec!(0x00, 0x17, 0x00, 0x01, 0x00));
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::extended_master_secret(ref data) => assert!(data.check().is_err()),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_unknown_extension() {
+        // RFC 7685 padding
+        let packet = vec!(0x00, 0x15, 0x00, 0x03, 0, 0, 0);
+        let mut reader = MemReader::new(packet.clone());
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::UnknownExtension(21, ref data) => assert_eq!(&**data, &[0u8, 0, 0][]),
+            _ => panic!(),
+        }
+        assert_eq!(ext.tls_size(), 7);
+
+        let mut written = Vec::new();
+        ext.tls_write(&mut written).unwrap();
+        assert_eq!(written, packet);
+    }
+
+    #[test]
+    fn test_signature_algorithms_extension() {
+        let list = vec!(
+            SignatureAndHashAlgorithm {
+                hash: HashAlgorithm::sha256,
+                signature: SignatureAlgorithm::ecdsa,
+            },
+            SignatureAndHashAlgorithm {
+                hash: HashAlgorithm::intrinsic,
+                signature: SignatureAlgorithm::rsa_pss_rsae_sha256,
+            }
+        );
+        let ext = Extension::new_signature_algorithms(list).unwrap();
+        let mut packet = Vec::new();
+        ext.tls_write(&mut packet).unwrap();
+        assert_eq!(packet, vec!(0x00, 0x0d, 0x00, 0x06, 0x00, 0x04, 0x04, 0x03, 0x08, 0x04));
+
+        let mut reader = MemReader::new(packet);
+        let ext: Extension = TlsItem::tls_read(&mut reader).unwrap();
+        match ext {
+            Extension::signature_algorithms(ref list) => {
+                let algorithms = list.algorithms().unwrap();
+                assert_eq!(algorithms.len(), 2);
+                assert_eq!(algorithms[1].signature, SignatureAlgorithm::rsa_pss_rsae_sha256);
+            }
+            _ => panic!(),
+        }
+
+        assert!(Extension::new_signature_algorithms(Vec::new()).is_err());
+    }
 }
@@ -8,20 +8,34 @@
 
 #[macro_use]
 extern crate log;
+extern crate time;
 
 pub use tls::Tls;
-pub use client::TlsClient;
+pub use client::{TlsClient, ClientConfig};
+pub use server::{TlsServer, ServerConfig};
+pub use trust_store::TrustStore;
+pub use server_name::ServerName;
+pub use session::{SessionStore, LruSessionStore, ServerSessionCache, TicketKey};
 
 #[macro_use]
 pub mod macros;
 pub mod util;
+pub mod der;
+pub mod pem;
 
 // basic crypto primitives
 pub mod crypto {
+    pub mod sha1;
     pub mod sha2;
     pub mod p256;
+    pub mod p384;
     pub mod poly1305;
     pub mod chacha20;
+    pub mod rsa;
+    pub mod aes;
+    pub mod gcm;
+    pub mod curve25519;
+    pub mod bignum;
 }
 
 pub mod tls_result;
@@ -33,10 +47,15 @@ pub mod record;
 pub mod cipher;
 
 pub mod signature;
+pub mod certificate;
+pub mod trust_store;
+pub mod server_name;
+pub mod session;
 pub mod alert;
 pub mod handshake;
 
 pub mod tls;
 pub mod client;
+pub mod server;
 
 #[cfg(test)] mod test;
@@ -0,0 +1,116 @@
+// PEM decoder (RFC 7468)
+// only the strict form is accepted: no headers, and nothing but base64
+// between the encapsulation boundaries.
+
+use std::str;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::DecodeError;
+
+// return the contents of every block labeled `label` in `data`.
+// text outside of the blocks is ignored.
+pub fn decode(data: &[u8], label: &str) -> TlsResult<Vec<Vec<u8>>> {
+    let text = match str::from_utf8(data) {
+        Ok(text) => text,
+        Err(..) => return tls_err!(DecodeError, "PEM: not a text"),
+    };
+
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    let mut blocks = Vec::new();
+    let mut base64: Option<Vec<u8>> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if base64.is_none() {
+            if line == &begin[] {
+                base64 = Some(Vec::new());
+            }
+            continue;
+        }
+
+        if line == &end[] {
+            let encoded = base64.take().unwrap();
+            blocks.push(try!(decode_base64(&encoded[])));
+        } else {
+            base64.as_mut().unwrap().push_all(line.as_bytes());
+        }
+    }
+
+    if base64.is_some() {
+        return tls_err!(DecodeError, "PEM: missing {}", end);
+    }
+
+    Ok(blocks)
+}
+
+fn decode_base64(data: &[u8]) -> TlsResult<Vec<u8>> {
+    if data.len() % 4 != 0 {
+        return tls_err!(DecodeError, "PEM: bad base64 length");
+    }
+
+    let chunks = data.len() / 4;
+    let mut ret = Vec::with_capacity(chunks * 3);
+    for (i, chunk) in data.chunks(4).enumerate() {
+        let mut acc = 0u32;
+        let mut padding = 0us;
+        for &c in chunk.iter() {
+            let v = match c {
+                b'A'...b'Z' => c - b'A',
+                b'a'...b'z' => c - b'a' + 26,
+                b'0'...b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                // padding is only allowed at the end
+                b'=' if i == chunks - 1 => {
+                    padding += 1;
+                    0
+                }
+                _ => return tls_err!(DecodeError, "PEM: bad base64 character: {}", c),
+            };
+            if padding > 0 && c != b'=' {
+                return tls_err!(DecodeError, "PEM: bad base64 padding");
+            }
+            acc = (acc << 6) | (v as u32);
+        }
+        if padding > 2 {
+            return tls_err!(DecodeError, "PEM: bad base64 padding");
+        }
+
+        ret.push((acc >> 16) as u8);
+        if padding < 2 {
+            ret.push((acc >> 8) as u8);
+        }
+        if padding < 1 {
+            ret.push(acc as u8);
+        }
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode;
+
+    #[test]
+    fn test_decode() {
+        let data = b"garbage\n\
+                     -----BEGIN TEST-----\n\
+                     c3VydWdh\n\
+                     -----END TEST-----\n\
+                     -----BEGIN OTHER-----\n\
+                     AAAA\n\
+                     -----END OTHER-----\n\
+                     -----BEGIN TEST-----\n\
+                     c3Vy\n\
+                     dWc=\n\
+                     -----END TEST-----\n";
+        let blocks = decode(data, "TEST").unwrap();
+        assert_eq!(blocks, vec!(b"suruga".to_vec(), b"surug".to_vec()));
+
+        assert!(decode(b"-----BEGIN TEST-----\nc3VydWdh\n", "TEST").is_err());
+        assert!(decode(b"-----BEGIN TEST-----\nc3=ydWdh\n-----END TEST-----", "TEST").is_err());
+        assert!(decode(b"-----BEGIN TEST-----\nc3VydW\n-----END TEST-----", "TEST").is_err());
+    }
+}
@@ -1,9 +1,11 @@
 use std::num::FromPrimitive;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 use tls_result::TlsResult;
 use tls_result::TlsErrorKind::{UnexpectedMessage, RecordOverflow, BadRecordMac, AlertReceived};
-use alert::Alert;
-use handshake::{Handshake, HandshakeBuffer};
+use alert::{Alert, AlertLevel, AlertDescription};
+use handshake::{Handshake, HandshakeBuffer, HandshakeTranscript};
 use util::u64_be_array;
 use cipher::{Encryptor, Decryptor};
 use tls_item::TlsItem;
@@ -84,14 +86,17 @@ pub struct RecordWriter<W: Writer> {
     // if encryptor is None, handshake is not done yet.
     encryptor: Option<Box<Encryptor + 'static>>,
     write_count: u64,
+    // shared with the reader.
+    transcript: Rc<RefCell<HandshakeTranscript>>,
 }
 
 impl<W: Writer> RecordWriter<W> {
-    pub fn new(writer: W) -> RecordWriter<W> {
+    pub fn new(writer: W, transcript: Rc<RefCell<HandshakeTranscript>>) -> RecordWriter<W> {
         RecordWriter {
             writer: writer,
             encryptor: None,
             write_count: 0,
+            transcript: transcript,
         }
     }
 
@@ -158,7 +163,9 @@ impl<W: Writer> RecordWriter<W> {
     pub fn write_handshake(&mut self, handshake: &Handshake) -> TlsResult<()> {
         let mut data = Vec::new();
         try!(handshake.tls_write(&mut data));
-        self.write_data(HandshakeTy, &data[])
+        try!(self.write_data(HandshakeTy, &data[]));
+        self.transcript.borrow_mut().add_message(&data[]);
+        Ok(())
     }
 
     pub fn write_alert(&mut self, alert: &Alert) -> TlsResult<()> {
@@ -192,15 +199,18 @@ pub struct RecordReader<R: Reader> {
     decryptor: Option<Box<Decryptor + 'static>>,
     read_count: u64,
     handshake_buffer: HandshakeBuffer,
+    // shared with the writer.
+    transcript: Rc<RefCell<HandshakeTranscript>>,
 }
 
 impl<R: Reader> RecordReader<R> {
-    pub fn new(reader: R) -> RecordReader<R> {
+    pub fn new(reader: R, transcript: Rc<RefCell<HandshakeTranscript>>) -> RecordReader<R> {
         RecordReader {
             reader: reader,
             decryptor: None,
             read_count: 0,
             handshake_buffer: HandshakeBuffer::new(),
+            transcript: transcript,
         }
     }
 
@@ -247,16 +257,16 @@ impl<R: Reader> RecordReader<R> {
                 ad.push(enc_record.ver_major);
                 ad.push(enc_record.ver_minor);
 
-                let mac_len = decryptor.mac_len();
+                let overhead = decryptor.explicit_nonce_len() + decryptor.mac_len();
                 let total_len = enc_record.fragment.len();
-                if total_len < mac_len {
+                if total_len < overhead {
                     return tls_err!(BadRecordMac, "encrypted message too short: {}", total_len);
                 }
-                let frag_len = (total_len - mac_len) as u16;
+                let frag_len = (total_len - overhead) as u16;
                 ad.push((frag_len >> 8) as u8);
                 ad.push(frag_len as u8);
 
-                // TODO: "seq_num as nonce" is chacha20poly1305-specific
+                // the decryptor builds the actual nonce from seq_num or the explicit nonce.
                 let data = try!(decryptor.decrypt(&seq_num[],
                                                   &*enc_record.fragment,
                                                   &ad[]));
@@ -273,12 +283,23 @@ impl<R: Reader> RecordReader<R> {
         Ok(record)
     }
 
+    // the next buffered handshake message, added to the transcript as received.
+    fn get_handshake_message(&mut self) -> TlsResult<Option<Handshake>> {
+        match try!(self.handshake_buffer.get_message()) {
+            Some((handshake_msg, data)) => {
+                self.transcript.borrow_mut().add_message(&data[]);
+                Ok(Some(handshake_msg))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// read records until a "complete" message is found, and return the message.
     /// if invalid ChangeCipherSpec/Alert/Handshake message is found, return Err.
     /// (application record is always considered "complete" and "valid"
     /// since it is opaque to TLS layer.)
     pub fn read_message(&mut self) -> TlsResult<Message> {
-        match try!(self.handshake_buffer.get_message()) {
+        match try!(self.get_handshake_message()) {
             Some(handshake_msg) => return Ok(HandshakeMessage(handshake_msg)),
             None => {}
         }
@@ -326,7 +347,7 @@ impl<R: Reader> RecordReader<R> {
                     }
                     self.handshake_buffer.add_record(record.fragment);
 
-                    match try!(self.handshake_buffer.get_message()) {
+                    match try!(self.get_handshake_message()) {
                         Some(handshake_msg) => return Ok(HandshakeMessage(handshake_msg)),
                         _ => {}
                     }
@@ -338,19 +359,23 @@ impl<R: Reader> RecordReader<R> {
         }
     }
 
-    pub fn read_application_data(&mut self) -> TlsResult<Vec<u8>> {
+    /// read a message after the handshake.
+    /// return ApplicationData, Handshake (renegotiation) or warning Alert messages.
+    /// the caller must handle the latter two.
+    pub fn read_application_data(&mut self) -> TlsResult<Message> {
         if self.decryptor.is_none() {
             panic!("ApplicationData called before handshake");
         }
-        loop {
-            let msg = try!(self.read_message());
-            match msg {
-                ApplicationDataMessage(msg) => return Ok(msg),
-                // TODO: handle other cases
-                AlertMessage(..) => unimplemented!(),
-                ChangeCipherSpecMessage(..) => unimplemented!(), // this should not come here
-                HandshakeMessage(..) => unimplemented!(), // TODO: re-handshake
+        match try!(self.read_message()) {
+            ChangeCipherSpecMessage => tls_err!(UnexpectedMessage, "unexpected ChangeCipherSpec"),
+            AlertMessage(alert) => {
+                if alert.level == AlertLevel::fatal ||
+                   alert.description == AlertDescription::close_notify {
+                    return tls_err!(AlertReceived, "alert: {:?}", alert.description);
+                }
+                Ok(AlertMessage(alert))
             }
+            msg => Ok(msg),
         }
     }
 
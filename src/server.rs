@@ -0,0 +1,833 @@
+use std::io::net::tcp::TcpStream;
+use std::slice::bytes::copy_memory;
+use std::cmp;
+use std::io::{IoResult, IoError, OtherIoError};
+use std::rand::{Rng, OsRng};
+use std::sync::Arc;
+
+use time;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::{UnexpectedMessage, InternalError, DecryptError, IllegalParameter,
+                               HandshakeFailure, NoApplicationProtocol};
+use util::crypto_compare;
+use cipher::{self, Aead};
+use cipher::prf;
+use cipher::psk::ServerPskCallback;
+use tls_item::DummyItem;
+use handshake::{self, Handshake};
+use signature::{self, PrivateKey, SignatureAndHashAlgorithm, HashAlgorithm, SignatureAlgorithm};
+use certificate::{Certificate, OID_CLIENT_AUTH};
+use trust_store::TrustStore;
+use session::{Session, ServerSessionCache, TicketKey, TICKET_LIFETIME};
+use record::Message;
+use alert::AlertDescription;
+use tls::{Tls, TLS_VERSION};
+
+// expect specific HandshakeMessage. otherwise return Err
+macro_rules! expect {
+    ($tls:expr, $var:ident) => ({
+        match try!($tls.reader.read_handshake()) {
+            handshake::Handshake::$var(data) => data,
+            _ => return tls_err!(UnexpectedMessage, "unexpected handshake message found"),
+        }
+    })
+}
+
+// configuration shared by connections.
+#[derive(Clone)]
+pub struct ServerConfig {
+    // DER-encoded certificates sent to clients.
+    // the first one is the server certificate and the rest are intermediates.
+    pub certificates: Vec<Vec<u8>>,
+    // SECRET: key of the server certificate.
+    pub private_key: Arc<PrivateKey>,
+    // if set, clients must present a certificate chaining up to one of these.
+    pub client_trust_store: Option<Arc<TrustStore>>,
+    // if set, session ids are issued and clients may resume sessions.
+    pub session_cache: Option<Arc<ServerSessionCache>>,
+    // if set, session tickets (RFC 5077) are issued and accepted.
+    pub ticket_key: Option<Arc<TicketKey>>,
+    // RFC 7301: protocols we speak, in order of preference.
+    // if the client offers none of them, the handshake fails.
+    pub alpn_protocols: Vec<Vec<u8>>,
+    // in order of preference. the client's preference is ignored.
+    pub cipher_suites: Vec<cipher::CipherSuite>,
+    // curves for ECDHE and groups for DHE, in order of preference.
+    // the client's preference is ignored.
+    pub elliptic_curves: Vec<handshake::NamedCurve>,
+    // if set, PSK cipher suites are accepted as well.
+    pub psk_callback: Option<Arc<ServerPskCallback>>,
+    // sent to clients of PSK suites. may be empty.
+    pub psk_identity_hint: Vec<u8>,
+    // whether to accept renegotiation started by the client (RFC 5746 only).
+    // each one costs us a full handshake, so it is refused by default.
+    pub allow_client_renegotiation: bool,
+}
+
+impl ServerConfig {
+    pub fn new(certificates: Vec<Vec<u8>>, private_key: PrivateKey) -> ServerConfig {
+        ServerConfig {
+            certificates: certificates,
+            private_key: Arc::new(private_key),
+            client_trust_store: None,
+            session_cache: None,
+            ticket_key: None,
+            alpn_protocols: Vec::new(),
+            cipher_suites: cipher::CipherSuite::supported(),
+            elliptic_curves: cipher::supported_groups(),
+            psk_callback: None,
+            psk_identity_hint: Vec::new(),
+            allow_client_renegotiation: false,
+        }
+    }
+
+    // `callback` returns the psk of a psk_identity.
+    pub fn set_psk_callback<F>(&mut self, callback: F)
+        where F: Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync
+    {
+        self.psk_callback = Some(Arc::new(Box::new(callback) as ServerPskCallback));
+    }
+}
+
+// handshake is done during construction.
+pub struct TlsServer<R: Reader, W: Writer> {
+    tls: Tls<R, W>,
+    config: ServerConfig,
+    buf: Vec<u8>,
+    // RFC 5746: the client signaled secure renegotiation in the initial handshake.
+    secure_renegotiation: bool,
+    // verify data of the last handshake. empty before the initial handshake is done.
+    client_verify_data: Vec<u8>,
+    server_verify_data: Vec<u8>,
+    // protocol selected from the client's offer
+    alpn_protocol: Option<Vec<u8>>,
+}
+
+impl<R: Reader, W: Writer> TlsServer<R, W> {
+    pub fn new(reader: R,
+               writer: W,
+               rng: OsRng,
+               config: ServerConfig) -> TlsResult<TlsServer<R, W>> {
+        let mut server = TlsServer {
+            tls: Tls::new(reader, writer, rng),
+            config: config,
+            buf: Vec::new(),
+            secure_renegotiation: false,
+            client_verify_data: Vec::new(),
+            server_verify_data: Vec::new(),
+            alpn_protocol: None,
+        };
+
+        // handshake failed. send alert if necessary
+        match server.handshake() {
+            Ok(()) => {}
+            Err(err) => return Err(server.tls.send_tls_alert(err)),
+        }
+        Ok(server)
+    }
+
+    // this does not send alert when error occurs
+    fn handshake(&mut self) -> TlsResult<()> {
+        let client_hello_data = expect!(self.tls, client_hello);
+        self.handshake_with_hello(client_hello_data)
+    }
+
+    // the rest of the handshake after ClientHello.
+    // this is also used for renegotiation.
+    fn handshake_with_hello(&mut self, client_hello_data: handshake::ClientHello)
+                            -> TlsResult<()> {
+        // cipher suites supported by both sides, in order of our preference.
+        // our key must be able to sign ServerKeyExchange of the suite.
+        let key_algorithm = self.config.private_key.signature_algorithm();
+        let mut offered_cipher_suites = Vec::new();
+        // PSK suites need the callback instead.
+        for &suite in self.config.cipher_suites.iter() {
+            let usable = match suite.signature_algorithm() {
+                SignatureAlgorithm::anonymous => self.config.psk_callback.is_some(),
+                algorithm => algorithm == key_algorithm,
+            };
+            if !usable {
+                continue;
+            }
+            if client_hello_data.cipher_suites.iter().any(|s| *s == suite) {
+                offered_cipher_suites.push(suite);
+            }
+        }
+        // the above with the group for the key exchange of each suite.
+        let shared_cipher_suites: Vec<(cipher::CipherSuite, Option<handshake::NamedCurve>)>;
+        // to sign ServerKeyExchange. None if the client accepts none we can sign with.
+        let signature_algorithm: Option<SignatureAndHashAlgorithm>;
+
+        let renegotiating = self.client_verify_data.len() > 0;
+        let mut client_sent_point_formats = false;
+        // RFC 5077: None if the client does not support tickets.
+        let mut client_ticket = None;
+        // RFC 5746: None if the client does not support secure renegotiation.
+        let mut client_renegotiation_info = None;
+        let mut client_alpn_protocols = None;
+        let mut client_curves = None;
+        // RFC 5246 7.4.1.4.1: None if the client did not send signature_algorithms.
+        let mut client_signature_algorithms = None;
+        // RFC 7627
+        let mut client_ems = false;
+        {
+            let client_major = client_hello_data.client_version.major;
+            let client_minor = client_hello_data.client_version.minor;
+            if (client_major, client_minor) < TLS_VERSION {
+                return tls_err!(IllegalParameter,
+                                "unsupported client version: {} {}",
+                                client_major,
+                                client_minor);
+            }
+
+            if offered_cipher_suites.len() == 0 {
+                return tls_err!(HandshakeFailure, "no shared cipher suite");
+            }
+
+            let null_compression = client_hello_data.compression_methods.iter().any(|method| {
+                *method == handshake::CompressionMethod::null
+            });
+            if !null_compression {
+                return tls_err!(IllegalParameter, "client does not support null compression");
+            }
+
+            match client_hello_data.extensions {
+                Some(ref extensions) => {
+                    for extension in extensions.iter() {
+                        match *extension {
+                            handshake::Extension::elliptic_curves(ref list) => {
+                                let mut curves = Vec::new();
+                                for list in list.iter() {
+                                    for curve in list.iter() {
+                                        curves.push(*curve);
+                                    }
+                                }
+                                client_curves = Some(curves);
+                            }
+                            handshake::Extension::signature_algorithms(ref list) => {
+                                client_signature_algorithms = Some(try!(list.algorithms())
+                                                                       .to_vec());
+                            }
+                            handshake::Extension::ec_point_formats(ref list) => {
+                                let supported = list.iter().any(|formats| {
+                                    formats.iter().any(|format| {
+                                        *format == handshake::ECPointFormat::uncompressed
+                                    })
+                                });
+                                if !supported {
+                                    return tls_err!(IllegalParameter,
+                                                    "client does not support uncompressed points");
+                                }
+                                client_sent_point_formats = true;
+                            }
+                            handshake::Extension::session_ticket(ref ticket) => {
+                                client_ticket = Some(ticket.to_vec());
+                            }
+                            handshake::Extension::renegotiation_info(ref info) => {
+                                let info = try!(info.renegotiated_connection());
+                                client_renegotiation_info = Some(info.to_vec());
+                            }
+                            handshake::Extension::application_layer_protocol_negotiation(
+                                ref list) => {
+                                client_alpn_protocols = Some(try!(list.protocol_names()));
+                            }
+                            handshake::Extension::extended_master_secret(ref data) => {
+                                try!(data.check());
+                                client_ems = true;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                None => {}
+            }
+
+            // RFC 5246 7.4.1.4.1: without signature_algorithms, the client accepts SHA-1 only.
+            let client_signature_algorithms = match client_signature_algorithms {
+                Some(algorithms) => algorithms,
+                None => {
+                    let signatures = [SignatureAlgorithm::rsa,
+                                      SignatureAlgorithm::dsa,
+                                      SignatureAlgorithm::ecdsa];
+                    signatures.iter().map(|&signature| {
+                        SignatureAndHashAlgorithm {
+                            hash: HashAlgorithm::sha1,
+                            signature: signature,
+                        }
+                    }).collect()
+                }
+            };
+            let private_key = &self.config.private_key;
+            signature_algorithm =
+                private_key.choose_signature_algorithm(&client_signature_algorithms[]);
+
+            // drop suites whose key exchange has no group we can agree on,
+            // or whose ServerKeyExchange we can't sign for the client.
+            let client_curves = client_curves.as_ref().map(|curves| &curves[]);
+            shared_cipher_suites = offered_cipher_suites.iter().filter_map(|&suite| {
+                if suite.signature_algorithm() != SignatureAlgorithm::anonymous &&
+                   signature_algorithm.is_none() {
+                    return None;
+                }
+
+                let kex = suite.new_kex();
+                if !kex.uses_group() {
+                    return Some((suite, None));
+                }
+                kex.choose_group(&self.config.elliptic_curves[], client_curves).map(|group| {
+                    (suite, Some(group))
+                })
+            }).collect();
+            if shared_cipher_suites.len() == 0 {
+                return tls_err!(HandshakeFailure, "no shared group or signature algorithm");
+            }
+
+            // RFC 5746 3.6, 3.7
+            let scsv = client_hello_data.cipher_suites.iter().any(|suite| {
+                *suite == cipher::CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV
+            });
+            if renegotiating {
+                if scsv {
+                    return tls_err!(HandshakeFailure, "unexpected SCSV in renegotiation");
+                }
+                match client_renegotiation_info {
+                    Some(ref info) if *info == self.client_verify_data => {}
+                    _ => return tls_err!(HandshakeFailure, "renegotiation_info mismatch"),
+                }
+            } else {
+                match client_renegotiation_info {
+                    Some(ref info) if info.len() > 0 => {
+                        return tls_err!(HandshakeFailure, "renegotiation_info mismatch");
+                    }
+                    _ => {}
+                }
+                self.secure_renegotiation = scsv || client_renegotiation_info.is_some();
+            }
+
+            // our preference wins.
+            self.alpn_protocol = match client_alpn_protocols {
+                Some(ref offered) if self.config.alpn_protocols.len() > 0 => {
+                    let protocol = self.config.alpn_protocols.iter().find(|protocol| {
+                        offered.iter().any(|p| p == *protocol)
+                    });
+                    match protocol {
+                        Some(protocol) => Some(protocol.clone()),
+                        None => return tls_err!(NoApplicationProtocol,
+                                                "no shared application protocol"),
+                    }
+                }
+                _ => None,
+            };
+        }
+        let cli_random = client_hello_data.random.to_vec();
+        let cli_session_id = client_hello_data.session_id.to_vec();
+
+        // resume the session if the client offers a valid ticket or a known session id.
+        // the client learns that we accept the ticket by the echoed session id,
+        // so a ticket without session id is not accepted.
+        let ticket_session = match (&self.config.ticket_key, &client_ticket) {
+            (&Some(ref key), &Some(ref ticket)) if cli_session_id.len() > 0 => {
+                match key.open(&ticket[], time::get_time().sec) {
+                    Some(mut session) => {
+                        session.session_id = cli_session_id.clone();
+                        Some(session)
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+        let resumed_by_ticket = ticket_session.is_some();
+        let session = match (ticket_session, &self.config.session_cache) {
+            (Some(session), _) => Some(session),
+            (None, &Some(ref cache)) if cli_session_id.len() > 0 => cache.get(&cli_session_id[]),
+            (None, _) => None,
+        };
+        let resumed_session = match session {
+            Some(session) => {
+                // RFC 7627 5.3: a session with extended master secret is never resumed without it,
+                // and one without it gets a full handshake if the client now supports it.
+                if session.extended_master_secret && !client_ems {
+                    return tls_err!(HandshakeFailure,
+                                    "client dropped extended master secret on resumption");
+                }
+                let suite = session.cipher_suite;
+                if shared_cipher_suites.iter().any(|&(s, _)| s == suite) &&
+                   session.extended_master_secret == client_ems {
+                    Some(session)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+
+        // the group is used only by a full handshake.
+        let (cipher_suite, group) = match resumed_session {
+            Some(ref session) => {
+                let suite = session.cipher_suite;
+                *shared_cipher_suites.iter().find(|&&(s, _)| s == suite).unwrap()
+            }
+            None => shared_cipher_suites[0],
+        };
+
+        // we always renew the ticket if the client supports it.
+        let issue_ticket = self.config.ticket_key.is_some() && client_ticket.is_some();
+
+        let session_id = match (&resumed_session, &self.config.session_cache) {
+            (&Some(ref session), _) => session.session_id.clone(),
+            (&None, &Some(..)) => {
+                let mut session_id = [0u8; 32];
+                self.tls.rng.fill_bytes(&mut session_id);
+                session_id.to_vec()
+            }
+            (&None, &None) => Vec::new(),
+        };
+
+        let serv_random = {
+            let mut random_bytes = [0u8; 32];
+            self.tls.rng.fill_bytes(&mut random_bytes);
+            random_bytes.to_vec()
+        };
+
+        let mut extensions = Vec::new();
+        if client_sent_point_formats {
+            let format_list = vec!(handshake::ECPointFormat::uncompressed);
+            extensions.push(try!(handshake::Extension::new_ec_point_formats(format_list)));
+        }
+        if issue_ticket {
+            extensions.push(try!(handshake::Extension::new_session_ticket(Vec::new())));
+        }
+        if self.secure_renegotiation {
+            let mut info = self.client_verify_data.clone();
+            info.push_all(&self.server_verify_data[]);
+            extensions.push(try!(handshake::Extension::new_renegotiation_info(info)));
+        }
+        if client_ems {
+            extensions.push(try!(handshake::Extension::new_extended_master_secret()));
+        }
+        match self.alpn_protocol {
+            Some(ref protocol) => {
+                let alpn = vec!(protocol.clone());
+                let alpn = handshake::Extension::new_application_layer_protocol_negotiation(alpn);
+                extensions.push(try!(alpn));
+            }
+            None => {}
+        }
+
+        let random = try!(handshake::Random::new(serv_random.clone()));
+        let server_hello = try!(Handshake::new_server_hello(random,
+                                                            session_id.clone(),
+                                                            cipher_suite,
+                                                            extensions));
+        try!(self.tls.writer.write_handshake(&server_hello));
+
+        let aead = cipher_suite.new_aead();
+        let prf_hash = cipher_suite.prf_hash();
+
+        // a resumed session keeps its issue time.
+        let issued = match resumed_session {
+            Some(ref session) => session.issued,
+            None => time::get_time().sec,
+        };
+
+        // SECRET
+        let master_secret = match resumed_session {
+            Some(session) => {
+                // abbreviated handshake: we send ChangeCipherSpec and Finished first.
+                let master_secret = session.master_secret;
+
+                // SECRET
+                let key_block = prf::key_block(prf_hash,
+                                               &master_secret[],
+                                               &cli_random[],
+                                               &serv_random[],
+                                               aead.key_size(),
+                                               aead.fixed_iv_len());
+
+                if issue_ticket {
+                    try!(self.send_new_session_ticket(cipher_suite,
+                                                      &master_secret[],
+                                                      client_ems,
+                                                      issued));
+                }
+
+                try!(self.tls.writer.write_change_cipher_spec());
+                self.tls.writer.set_encryptor(aead.new_encryptor(key_block.server_write_key,
+                                                                 key_block.server_write_iv));
+
+                let server_verify_data = prf::verify_data(prf_hash,
+                                                          &master_secret[],
+                                                          b"server finished",
+                                                          &self.tls.handshake_hash(prf_hash)[],
+                                                          cipher_suite.verify_data_len());
+                let finished = try!(Handshake::new_finished(server_verify_data.clone()));
+                try!(self.tls.writer.write_handshake(&finished));
+
+                try!(self.tls.reader.read_change_cipher_spec());
+                self.tls.reader.set_decryptor(aead.new_decryptor(key_block.client_write_key,
+                                                                 key_block.client_write_iv));
+
+                let client_verify_data = prf::verify_data(prf_hash,
+                                                          &master_secret[],
+                                                          b"client finished",
+                                                          &self.tls.handshake_hash(prf_hash)[],
+                                                          cipher_suite.verify_data_len());
+                let client_finished = expect!(self.tls, finished);
+                if !crypto_compare(&client_finished[], &client_verify_data[]) {
+                    return tls_err!(DecryptError, "client sent wrong verify data");
+                }
+
+                self.client_verify_data = client_verify_data;
+                self.server_verify_data = server_verify_data;
+
+                master_secret
+            }
+            None => try!(self.full_handshake(cipher_suite,
+                                             group,
+                                             signature_algorithm.as_ref(),
+                                             &cli_random[],
+                                             &serv_random[],
+                                             issue_ticket,
+                                             client_ems)),
+        };
+
+        match self.config.session_cache {
+            Some(ref cache) if !resumed_by_ticket => {
+                let session = Session {
+                    session_id: session_id,
+                    cipher_suite: cipher_suite,
+                    master_secret: master_secret,
+                    extended_master_secret: client_ems,
+                    ticket: None,
+                    issued: issued,
+                };
+                cache.put(session);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // the rest of the full handshake after ServerHello.
+    // return the master secret.
+    // SECRET
+    fn full_handshake(&mut self,
+                      cipher_suite: cipher::CipherSuite,
+                      group: Option<handshake::NamedCurve>,
+                      signature_algorithm: Option<&SignatureAndHashAlgorithm>,
+                      cli_random: &[u8],
+                      serv_random: &[u8],
+                      issue_ticket: bool,
+                      extended_master_secret: bool) -> TlsResult<Vec<u8>> {
+        // PSK suites have no certificate.
+        let psk_suite = cipher_suite.signature_algorithm() == SignatureAlgorithm::anonymous;
+        let prf_hash = cipher_suite.prf_hash();
+
+        if !psk_suite {
+            let certificate = try!(Handshake::new_certificate(self.config.certificates.clone()));
+            try!(self.tls.writer.write_handshake(&certificate));
+        }
+
+        let kex = cipher_suite.new_kex();
+        let server_key = self.config.private_key.clone();
+        let signer = if psk_suite {
+            None
+        } else {
+            signature_algorithm.map(|algorithm| (&*server_key, algorithm))
+        };
+        // SECRET
+        let (server_key_ex_data, kex_secret) = {
+            let hint = &self.config.psk_identity_hint[];
+            try!(kex.server_key_exchange(cli_random,
+                                         serv_random,
+                                         signer,
+                                         group,
+                                         hint,
+                                         &mut self.tls.rng))
+        };
+        if server_key_ex_data.len() > 0 {
+            let server_key_exchange = try!(Handshake::new_server_key_exchange(server_key_ex_data));
+            try!(self.tls.writer.write_handshake(&server_key_exchange));
+        }
+
+        // RFC 4279 2: the client is not asked for a certificate with PSK.
+        let client_trust_store = if psk_suite {
+            None
+        } else {
+            self.config.client_trust_store.clone()
+        };
+        // CertificateVerify must use one of these.
+        let certificate_verify_algorithms = signature::supported_signature_algorithms();
+        if client_trust_store.is_some() {
+            let certificate_types = vec!(handshake::ClientCertificateType::rsa_sign,
+                                         handshake::ClientCertificateType::ecdsa_sign);
+            let algorithms = certificate_verify_algorithms.clone();
+            let certificate_request = try!(Handshake::new_certificate_request(certificate_types,
+                                                                              algorithms));
+            try!(self.tls.writer.write_handshake(&certificate_request));
+        }
+
+        let server_hello_done = Handshake::server_hello_done(DummyItem);
+        try!(self.tls.writer.write_handshake(&server_hello_done));
+
+        let client_cert = match client_trust_store {
+            Some(ref trust_store) => {
+                let certificate_list = expect!(self.tls, certificate);
+                let certs = {
+                    let mut certs = Vec::new();
+                    for cert in certificate_list.iter() {
+                        certs.push(try!(Certificate::parse(&cert[])));
+                    }
+                    certs
+                };
+                if certs.len() == 0 {
+                    return tls_err!(HandshakeFailure, "client sent no certificate");
+                }
+                let now = time::get_time().sec;
+                try!(trust_store.verify_chain(&certs[], OID_CLIENT_AUTH, now));
+                certs.into_iter().next()
+            }
+            None => None,
+        };
+
+        let client_key_ex_data = expect!(self.tls, client_key_exchange);
+        // SECRET
+        let pre_master_secret = {
+            let psk = self.config.psk_callback.as_ref().map(|psk| &**psk);
+            try!(kex.compute_server_keys(&*client_key_ex_data, group, &kex_secret[], psk))
+        };
+
+        // RFC 7627 3: the session hash covers the messages up to ClientKeyExchange.
+        let master_secret = if extended_master_secret {
+            let session_hash = self.tls.handshake_hash(prf_hash);
+            prf::extended_master_secret(prf_hash,
+                                        pre_master_secret,
+                                        &session_hash[])
+        } else {
+            prf::master_secret(prf_hash,
+                               pre_master_secret,
+                               cli_random,
+                               serv_random)
+        };
+
+        // CertificateVerify signs every handshake message so far.
+        match client_cert {
+            Some(ref cert) => {
+                let msgs = self.tls.handshake_messages();
+                let signed = expect!(self.tls, certificate_verify);
+                try!(signed.verify(&cert.public_key, &msgs[], &certificate_verify_algorithms[]));
+            }
+            None => {}
+        }
+
+        let aead = cipher_suite.new_aead();
+
+        // SECRET
+        let key_block = prf::key_block(prf_hash,
+                                       &master_secret[],
+                                       cli_random,
+                                       serv_random,
+                                       aead.key_size(),
+                                       aead.fixed_iv_len());
+
+        // from now client starts encryption.
+        try!(self.tls.reader.read_change_cipher_spec());
+        self.tls.reader.set_decryptor(aead.new_decryptor(key_block.client_write_key,
+                                                         key_block.client_write_iv));
+
+        let client_verify_data = prf::verify_data(prf_hash,
+                                                  &master_secret[],
+                                                  b"client finished",
+                                                  &self.tls.handshake_hash(prf_hash)[],
+                                                  cipher_suite.verify_data_len());
+        let client_finished = expect!(self.tls, finished);
+        if !crypto_compare(&client_finished[], &client_verify_data[]) {
+            return tls_err!(DecryptError, "client sent wrong verify data");
+        }
+
+        if issue_ticket {
+            try!(self.send_new_session_ticket(cipher_suite,
+                                              &master_secret[],
+                                              extended_master_secret,
+                                              time::get_time().sec));
+        }
+
+        try!(self.tls.writer.write_change_cipher_spec());
+        self.tls.writer.set_encryptor(aead.new_encryptor(key_block.server_write_key,
+                                                         key_block.server_write_iv));
+
+        let server_verify_data = prf::verify_data(prf_hash,
+                                                  &master_secret[],
+                                                  b"server finished",
+                                                  &self.tls.handshake_hash(prf_hash)[],
+                                                  cipher_suite.verify_data_len());
+        let finished = try!(Handshake::new_finished(server_verify_data.clone()));
+        try!(self.tls.writer.write_handshake(&finished));
+
+        self.client_verify_data = client_verify_data;
+        self.server_verify_data = server_verify_data;
+
+        Ok(master_secret)
+    }
+
+    // NewSessionTicket goes right before our ChangeCipherSpec.
+    fn send_new_session_ticket(&mut self,
+                               cipher_suite: cipher::CipherSuite,
+                               master_secret: &[u8],
+                               extended_master_secret: bool,
+                               issued: i64) -> TlsResult<()> {
+        let session = Session {
+            session_id: Vec::new(),
+            cipher_suite: cipher_suite,
+            master_secret: master_secret.to_vec(),
+            extended_master_secret: extended_master_secret,
+            ticket: None,
+            issued: issued,
+        };
+        // an empty ticket tells the client that we could not issue one.
+        let ticket = match self.config.ticket_key {
+            Some(ref key) => try!(key.seal(&session, &mut self.tls.rng)),
+            None => Vec::new(),
+        };
+
+        let new_session_ticket = try!(Handshake::new_new_session_ticket(TICKET_LIFETIME, ticket));
+        try!(self.tls.writer.write_handshake(&new_session_ticket));
+        Ok(())
+    }
+
+    // the application protocol selected by us (RFC 7301), if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_ref().map(|protocol| &protocol[])
+    }
+
+    // ask the client for a new handshake, e.g. to refresh the keys of a long-lived connection.
+    // return false if the client declines.
+    // ApplicationData arriving in the meantime is kept for `read`.
+    pub fn renegotiate(&mut self) -> TlsResult<bool> {
+        if !self.secure_renegotiation {
+            return tls_err!(HandshakeFailure, "client does not support secure renegotiation");
+        }
+        try!(self.tls.writer.write_handshake(&Handshake::hello_request(DummyItem)));
+
+        loop {
+            let result = match try!(self.tls.reader.read_application_data()) {
+                Message::ApplicationDataMessage(data) => {
+                    self.buf.push_all(&data[]);
+                    continue;
+                }
+                Message::HandshakeMessage(Handshake::client_hello(client_hello)) => {
+                    self.handshake_with_hello(client_hello)
+                }
+                Message::HandshakeMessage(..) => {
+                    tls_err!(UnexpectedMessage, "unexpected handshake message found")
+                }
+                Message::AlertMessage(alert) => {
+                    if alert.description == AlertDescription::no_renegotiation {
+                        return Ok(false);
+                    }
+                    // other warning alerts are ignored.
+                    continue;
+                }
+                Message::ChangeCipherSpecMessage => unreachable!(),
+            };
+            return match result {
+                Ok(()) => Ok(true),
+                Err(err) => Err(self.tls.send_tls_alert(err)),
+            };
+        }
+    }
+
+    // read ApplicationData. the client may start renegotiation in between.
+    fn read_application_data(&mut self) -> TlsResult<Vec<u8>> {
+        loop {
+            let result = match try!(self.tls.reader.read_application_data()) {
+                Message::ApplicationDataMessage(data) => return Ok(data),
+                Message::HandshakeMessage(Handshake::client_hello(client_hello)) => {
+                    if self.config.allow_client_renegotiation && self.secure_renegotiation {
+                        self.handshake_with_hello(client_hello)
+                    } else {
+                        self.tls.refuse_renegotiation()
+                    }
+                }
+                Message::HandshakeMessage(..) => {
+                    tls_err!(UnexpectedMessage, "unexpected handshake message found")
+                }
+                // warning alerts are ignored.
+                _ => Ok(()),
+            };
+            match result {
+                Ok(()) => {}
+                Err(err) => return Err(self.tls.send_tls_alert(err)),
+            }
+        }
+    }
+
+    pub fn close(&mut self) -> TlsResult<()> {
+        self.tls.close()
+    }
+}
+
+impl TlsServer<TcpStream, TcpStream> {
+    pub fn from_tcp(stream: TcpStream,
+                    config: ServerConfig) -> TlsResult<TlsServer<TcpStream, TcpStream>> {
+        let rng = match OsRng::new() {
+            Ok(rng) => rng,
+            Err(..) => return tls_err!(InternalError, "failed to create OsRng"),
+        };
+
+        let reader = stream.clone();
+        let writer = stream;
+        TlsServer::new(reader, writer, rng, config)
+    }
+}
+
+impl<R: Reader, W: Writer> Writer for TlsServer<R, W> {
+    // if ssl connection is failed, return `EndOfFile`.
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        let result = self.tls.writer.write_application_data(buf);
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let err = self.tls.send_tls_alert(err);
+                // FIXME more verbose io error
+                Err(IoError {
+                    kind: OtherIoError,
+                    desc: "TLS write error",
+                    detail: Some(err.desc),
+                })
+            }
+        }
+    }
+}
+
+impl<R: Reader, W: Writer> Reader for TlsServer<R, W> {
+    // if ssl connection is failed, return `EndOfFile`.
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let mut pos = 0us;
+        let len = buf.len();
+        while pos < len {
+            let remaining = len - pos;
+            if self.buf.len() == 0 {
+                let data = match self.read_application_data() {
+                    Ok(data) => data,
+                    Err(_err) => {
+                        break; // FIXME: stop if EOF. otherwise raise error?
+                    }
+                };
+                self.buf.push_all(&data[]);
+            }
+
+            let selflen = self.buf.len();
+            let necessary = cmp::min(remaining, selflen);
+            copy_memory(&mut buf[pos .. pos + necessary], &self.buf[.. necessary]);
+            pos += necessary;
+
+            self.buf = self.buf[necessary..].to_vec();
+        }
+
+        Ok(pos)
+    }
+}
@@ -0,0 +1,162 @@
+// server identity verification (RFC 6125)
+
+use std::str;
+use std::ascii::AsciiExt;
+use std::io::net::ip::IpAddr;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::BadCertificate;
+use certificate::{Certificate, GeneralName};
+
+// expected identity of the server
+#[derive(Clone, PartialEq, Show)]
+pub enum ServerName {
+    DnsName(String),
+    // network byte order: 4 octets for IPv4, 16 octets for IPv6
+    IpAddress(Vec<u8>),
+}
+
+impl ServerName {
+    // `name` is either an IP address literal or a DNS name.
+    pub fn new(name: &str) -> ServerName {
+        match name.parse::<IpAddr>() {
+            Some(IpAddr::Ipv4Addr(a, b, c, d)) => ServerName::IpAddress(vec!(a, b, c, d)),
+            Some(IpAddr::Ipv6Addr(a, b, c, d, e, f, g, h)) => {
+                let mut addr = Vec::with_capacity(16);
+                for &x in [a, b, c, d, e, f, g, h].iter() {
+                    addr.push((x >> 8) as u8);
+                    addr.push(x as u8);
+                }
+                ServerName::IpAddress(addr)
+            }
+            None => ServerName::DnsName(name.to_string()),
+        }
+    }
+
+    // check if `cert` is issued for `self`.
+    // if the certificate has subjectAltName, the common name is ignored.
+    pub fn verify(&self, cert: &Certificate) -> TlsResult<()> {
+        let matched = match (self, &cert.extensions.subject_alt_names) {
+            (&ServerName::DnsName(ref name), &Some(ref alt_names)) => {
+                alt_names.iter().any(|alt_name| match *alt_name {
+                    GeneralName::DnsName(ref pattern) => match_dns_name(&pattern[], &name[]),
+                    _ => false,
+                })
+            }
+            (&ServerName::IpAddress(ref addr), &Some(ref alt_names)) => {
+                alt_names.iter().any(|alt_name| match *alt_name {
+                    GeneralName::IpAddress(ref alt_addr) => alt_addr == addr,
+                    _ => false,
+                })
+            }
+            (&ServerName::DnsName(ref name), &None) => {
+                match cert.subject.common_name().map(str::from_utf8) {
+                    Some(Ok(cn)) => match_dns_name(cn, &name[]),
+                    _ => false,
+                }
+            }
+            (&ServerName::IpAddress(..), &None) => false,
+        };
+
+        if !matched {
+            return tls_err!(BadCertificate, "certificate is not valid for {:?}", self);
+        }
+        Ok(())
+    }
+}
+
+// `pattern` may have a wildcard only as the whole left-most label.
+// comparison is case-insensitive.
+fn match_dns_name(pattern: &str, name: &str) -> bool {
+    // "example.com." is same as "example.com"
+    fn trim_dot(name: &str) -> &str {
+        if name.ends_with(".") {
+            &name[..(name.len() - 1)]
+        } else {
+            name
+        }
+    }
+
+    let pattern_labels: Vec<&str> = trim_dot(pattern).split('.').collect();
+    let name_labels: Vec<&str> = trim_dot(name).split('.').collect();
+    if pattern_labels.len() != name_labels.len() {
+        return false;
+    }
+
+    for (i, (p, n)) in pattern_labels.iter().zip(name_labels.iter()).enumerate() {
+        if p.len() == 0 || n.len() == 0 || n.contains("*") {
+            return false;
+        }
+
+        if i == 0 && *p == "*" {
+            // "*.com" is too broad.
+            if pattern_labels.len() < 3 {
+                return false;
+            }
+            continue;
+        }
+
+        if p.contains("*") || !p.eq_ignore_ascii_case(*n) {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use std::iter::repeat;
+
+    use certificate::Certificate;
+    use super::{ServerName, match_dns_name};
+
+    static LEAF_EC: &'static [u8] = include_bytes!("test_data/leaf_ec.der");
+
+    #[test]
+    fn test_match_dns_name() {
+        static MATCHES: &'static [(&'static str, &'static str, bool)] = &[
+            ("example.com", "example.com", true),
+            ("example.com", "EXAMPLE.com.", true),
+            ("example.com", "www.example.com", false),
+            ("*.example.com", "www.example.com", true),
+            ("*.example.com", "WWW.Example.Com", true),
+            ("*.example.com", "example.com", false),
+            ("*.example.com", "a.b.example.com", false),
+            ("*.example.com", "*.example.com", false),
+            ("*.com", "example.com", false),
+            ("w*.example.com", "www.example.com", false),
+            ("www.*.com", "www.example.com", false),
+            ("example..com", "example..com", false),
+        ];
+
+        for &(pattern, name, expected) in MATCHES.iter() {
+            assert_eq!(match_dns_name(pattern, name), expected);
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let cert = Certificate::parse(LEAF_EC).unwrap();
+
+        let dns = |name: &str| ServerName::DnsName(name.to_string());
+        assert!(dns("suruga.example").verify(&cert).is_ok());
+        assert!(dns("www.suruga.example").verify(&cert).is_ok());
+        assert!(dns("www.suruga.example.com").verify(&cert).is_err());
+        assert!(dns("127.0.0.1").verify(&cert).is_err());
+
+        assert!(ServerName::IpAddress(vec!(127, 0, 0, 1)).verify(&cert).is_ok());
+        assert!(ServerName::IpAddress(vec!(127, 0, 0, 2)).verify(&cert).is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(ServerName::new("suruga.example"),
+                   ServerName::DnsName("suruga.example".to_string()));
+        assert_eq!(ServerName::new("127.0.0.1"), ServerName::IpAddress(vec!(127, 0, 0, 1)));
+
+        let mut v6: Vec<u8> = repeat(0).take(15).collect();
+        v6.push(1);
+        assert_eq!(ServerName::new("::1"), ServerName::IpAddress(v6));
+    }
+}
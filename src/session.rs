@@ -0,0 +1,404 @@
+// session resumption (RFC 5246 7.3, F.1.4) and session tickets (RFC 5077)
+
+use std::io::BufReader;
+use std::rand::Rng;
+use std::sync::Mutex;
+
+use time;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::InternalError;
+use tls_item::TlsItem;
+use util::u64_be_array;
+use cipher::{CipherSuite, Aead};
+use cipher::chacha20_poly1305::ChaCha20Poly1305;
+use server_name::ServerName;
+
+// state needed for the abbreviated handshake.
+#[derive(Clone)]
+pub struct Session {
+    pub session_id: Vec<u8>,
+    pub cipher_suite: CipherSuite,
+    // SECRET
+    pub master_secret: Vec<u8>,
+    // whether the master secret is bound to the handshake (RFC 7627).
+    pub extended_master_secret: bool,
+    // RFC 5077 ticket issued by the server, opaque to clients.
+    pub ticket: Option<Vec<u8>>,
+    // when the full handshake established the session, in seconds since 1970-01-01T00:00:00Z.
+    // resumption does not renew it.
+    pub issued: i64,
+}
+
+impl Session {
+    fn expired(&self, lifetime: u32, now: i64) -> bool {
+        now < self.issued || now - self.issued > lifetime as i64
+    }
+}
+
+// RFC 5246 F.1.4 suggests an upper limit of 24 hours for cached sessions.
+pub const SESSION_LIFETIME: u32 = 24 * 60 * 60;
+
+// client-side cache of sessions, keyed by the server they are established with.
+// implementations are shared between connections, so they need interior mutability.
+pub trait SessionStore: Send + Sync {
+    fn get(&self, server_name: &ServerName) -> Option<Session>;
+    fn put(&self, server_name: &ServerName, session: Session);
+    fn remove(&self, server_name: &ServerName);
+}
+
+// keeps the `capacity` most recently used sessions in memory.
+// sessions older than `lifetime` seconds are dropped.
+pub struct LruSessionStore {
+    sessions: Mutex<Lru<ServerName, Session>>,
+    lifetime: u32,
+}
+
+impl LruSessionStore {
+    pub fn new(capacity: usize) -> LruSessionStore {
+        LruSessionStore::with_lifetime(capacity, SESSION_LIFETIME)
+    }
+
+    pub fn with_lifetime(capacity: usize, lifetime: u32) -> LruSessionStore {
+        LruSessionStore {
+            sessions: Mutex::new(Lru::new(capacity)),
+            lifetime: lifetime,
+        }
+    }
+}
+
+impl SessionStore for LruSessionStore {
+    fn get(&self, server_name: &ServerName) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(server_name) {
+            Some(ref session) if session.expired(self.lifetime, time::get_time().sec) => {
+                sessions.remove(server_name);
+                None
+            }
+            session => session,
+        }
+    }
+
+    fn put(&self, server_name: &ServerName, session: Session) {
+        self.sessions.lock().unwrap().put(server_name.clone(), session);
+    }
+
+    fn remove(&self, server_name: &ServerName) {
+        self.sessions.lock().unwrap().remove(server_name);
+    }
+}
+
+// server-side cache of sessions, keyed by session id.
+// sessions older than `lifetime` seconds are dropped.
+pub struct ServerSessionCache {
+    sessions: Mutex<Lru<Vec<u8>, Session>>,
+    lifetime: u32,
+}
+
+impl ServerSessionCache {
+    pub fn new(capacity: usize) -> ServerSessionCache {
+        ServerSessionCache::with_lifetime(capacity, SESSION_LIFETIME)
+    }
+
+    pub fn with_lifetime(capacity: usize, lifetime: u32) -> ServerSessionCache {
+        ServerSessionCache {
+            sessions: Mutex::new(Lru::new(capacity)),
+            lifetime: lifetime,
+        }
+    }
+
+    pub fn get(&self, session_id: &[u8]) -> Option<Session> {
+        let session_id = session_id.to_vec();
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(&session_id) {
+            Some(ref session) if session.expired(self.lifetime, time::get_time().sec) => {
+                sessions.remove(&session_id);
+                None
+            }
+            session => session,
+        }
+    }
+
+    pub fn put(&self, session: Session) {
+        self.sessions.lock().unwrap().put(session.session_id.clone(), session);
+    }
+}
+
+// tickets are valid for this many seconds.
+pub const TICKET_LIFETIME: u32 = SESSION_LIFETIME;
+
+const TICKET_NONCE_LEN: usize = 8;
+const MASTER_SECRET_LEN: usize = 48;
+
+// server-side key to protect session tickets.
+// the ticket is `nonce || AEAD(cipher_suite || issued_time || ems_flag || master_secret)`.
+// servers sharing a key accept each other's tickets.
+// SECRET
+pub struct TicketKey {
+    key: Vec<u8>,
+}
+
+impl TicketKey {
+    // `key` must be 32 random bytes.
+    pub fn new(key: Vec<u8>) -> TlsResult<TicketKey> {
+        if key.len() != ChaCha20Poly1305.key_size() {
+            return tls_err!(InternalError, "ticket key must be {} bytes",
+                            ChaCha20Poly1305.key_size());
+        }
+        Ok(TicketKey {
+            key: key,
+        })
+    }
+
+    // the ticket keeps the issue time of the session, so renewing it does not extend the lifetime.
+    pub fn seal<R: Rng>(&self, session: &Session, rng: &mut R) -> TlsResult<Vec<u8>> {
+        // SECRET
+        let mut state = Vec::new();
+        try!(session.cipher_suite.tls_write(&mut state));
+        state.push_all(&u64_be_array(session.issued as u64)[]);
+        state.push(session.extended_master_secret as u8);
+        state.push_all(&session.master_secret[]);
+
+        // FIXME random nonces may collide after 2^32 tickets.
+        let mut nonce = [0u8; TICKET_NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let mut encryptor = ChaCha20Poly1305.new_encryptor(self.key.clone(), Vec::new());
+        let mut ticket = nonce.to_vec();
+        ticket.push_all(&encryptor.encrypt(&nonce, &state[], &[])[]);
+        Ok(ticket)
+    }
+
+    // return None if the ticket is broken, forged or expired.
+    // `now` is seconds since 1970-01-01T00:00:00Z.
+    // the returned session has an empty session id.
+    pub fn open(&self, ticket: &[u8], now: i64) -> Option<Session> {
+        if ticket.len() < TICKET_NONCE_LEN {
+            return None;
+        }
+        let nonce = &ticket[..TICKET_NONCE_LEN];
+        let encrypted = &ticket[TICKET_NONCE_LEN..];
+
+        let mut decryptor = ChaCha20Poly1305.new_decryptor(self.key.clone(), Vec::new());
+        // SECRET
+        let state = match decryptor.decrypt(nonce, encrypted, &[]) {
+            Ok(state) => state,
+            Err(..) => return None,
+        };
+        if state.len() != 2 + 8 + 1 + MASTER_SECRET_LEN {
+            return None;
+        }
+
+        let mut reader = BufReader::new(&state[]);
+        let cipher_suite: CipherSuite = match TlsItem::tls_read(&mut reader) {
+            Ok(CipherSuite::UnknownCipherSuite) | Err(..) => return None,
+            Ok(cipher_suite) => cipher_suite,
+        };
+        let issued = match reader.read_be_u64() {
+            Ok(issued) => issued as i64,
+            Err(..) => return None,
+        };
+        if now < issued || now - issued > TICKET_LIFETIME as i64 {
+            return None;
+        }
+        let extended_master_secret = match state[2 + 8] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+
+        Some(Session {
+            session_id: Vec::new(),
+            cipher_suite: cipher_suite,
+            master_secret: state[(2 + 8 + 1)..].to_vec(),
+            extended_master_secret: extended_master_secret,
+            ticket: None,
+            issued: issued,
+        })
+    }
+}
+
+// FIXME linear search. this is fine for client-side stores, but not for busy servers.
+struct Lru<K, V> {
+    capacity: usize,
+    // least recently used first
+    entries: Vec<(K, V)>,
+}
+
+impl<K: PartialEq, V: Clone> Lru<K, V> {
+    fn new(capacity: usize) -> Lru<K, V> {
+        Lru {
+            capacity: capacity,
+            entries: Vec::new(),
+        }
+    }
+
+    fn position(&self, key: &K) -> Option<usize> {
+        self.entries.iter().position(|&(ref k, _)| k == key)
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        match self.position(key) {
+            Some(pos) => {
+                let entry = self.entries.remove(pos);
+                let value = entry.1.clone();
+                self.entries.push(entry);
+                Some(value)
+            }
+            None => None,
+        }
+    }
+
+    fn put(&mut self, key: K, value: V) {
+        self.remove(&key);
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((key, value));
+    }
+
+    fn remove(&mut self, key: &K) {
+        match self.position(key) {
+            Some(pos) => {
+                self.entries.remove(pos);
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::iter::repeat;
+
+    use time;
+
+    use cipher::CipherSuite;
+    use server_name::ServerName;
+    use std::rand::OsRng;
+
+    use super::{Session, SessionStore, LruSessionStore, ServerSessionCache, TicketKey,
+                SESSION_LIFETIME, TICKET_LIFETIME};
+
+    fn session(id: u8) -> Session {
+        Session {
+            session_id: vec!(id),
+            cipher_suite: CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+            master_secret: repeat(id).take(48).collect(),
+            extended_master_secret: id % 2 == 1,
+            ticket: None,
+            issued: time::get_time().sec,
+        }
+    }
+
+    fn session_issued_at(id: u8, issued: i64) -> Session {
+        Session {
+            issued: issued,
+            ..session(id)
+        }
+    }
+
+    #[test]
+    fn test_lru_session_store() {
+        let store = LruSessionStore::new(2);
+        let a = ServerName::new("a.example");
+        let b = ServerName::new("b.example");
+        let c = ServerName::new("c.example");
+
+        store.put(&a, session(1));
+        store.put(&b, session(2));
+        assert_eq!(store.get(&a).unwrap().session_id, vec!(1));
+
+        // b is the least recently used one.
+        store.put(&c, session(3));
+        assert!(store.get(&b).is_none());
+        assert_eq!(store.get(&a).unwrap().session_id, vec!(1));
+        assert_eq!(store.get(&c).unwrap().session_id, vec!(3));
+
+        store.put(&a, session(4));
+        assert_eq!(store.get(&a).unwrap().session_id, vec!(4));
+
+        store.remove(&a);
+        assert!(store.get(&a).is_none());
+        assert_eq!(store.get(&c).unwrap().session_id, vec!(3));
+    }
+
+    #[test]
+    fn test_lru_session_store_lifetime() {
+        let now = time::get_time().sec;
+        let store = LruSessionStore::new(10);
+        let a = ServerName::new("a.example");
+        let b = ServerName::new("b.example");
+
+        store.put(&a, session_issued_at(1, now - SESSION_LIFETIME as i64 - 1));
+        assert!(store.get(&a).is_none());
+
+        // issued in the future: the clock went backwards.
+        store.put(&b, session_issued_at(2, now + 60 * 60));
+        assert!(store.get(&b).is_none());
+
+        let store = LruSessionStore::with_lifetime(10, 60);
+        store.put(&a, session_issued_at(1, now - 120));
+        assert!(store.get(&a).is_none());
+        store.put(&b, session_issued_at(2, now - 30));
+        assert!(store.get(&b).is_some());
+    }
+
+    #[test]
+    fn test_server_session_cache() {
+        let cache = ServerSessionCache::new(1);
+        cache.put(session(1));
+        assert_eq!(cache.get(&[1]).unwrap().master_secret[0], 1);
+
+        cache.put(session(2));
+        assert!(cache.get(&[1]).is_none());
+        assert!(cache.get(&[2]).is_some());
+
+        let now = time::get_time().sec;
+        let cache = ServerSessionCache::with_lifetime(10, 60);
+        cache.put(session_issued_at(1, now - 120));
+        assert!(cache.get(&[1]).is_none());
+        cache.put(session_issued_at(2, now - 30));
+        assert!(cache.get(&[2]).is_some());
+        let cache = ServerSessionCache::new(10);
+        cache.put(session_issued_at(3, now - SESSION_LIFETIME as i64 - 1));
+        assert!(cache.get(&[3]).is_none());
+    }
+
+    #[test]
+    fn test_ticket_key() {
+        let mut rng = OsRng::new().unwrap();
+        let key = TicketKey::new(repeat(7).take(32).collect()).unwrap();
+        let now = 1500000000;
+
+        let ticket = key.seal(&session_issued_at(1, now), &mut rng).unwrap();
+        let opened = key.open(&ticket[], now + 10).unwrap();
+        assert_eq!(opened.master_secret, session(1).master_secret);
+        assert!(opened.extended_master_secret);
+        assert!(opened.session_id.is_empty());
+        assert_eq!(opened.issued, now);
+
+        // a renewed ticket expires with the original one.
+        let renewed = key.seal(&opened, &mut rng).unwrap();
+        assert!(key.open(&renewed[], now + TICKET_LIFETIME as i64 + 1).is_none());
+
+        let ticket_2 = key.seal(&session_issued_at(2, now), &mut rng).unwrap();
+        assert!(!key.open(&ticket_2[], now).unwrap().extended_master_secret);
+
+        // expired
+        assert!(key.open(&ticket[], now + TICKET_LIFETIME as i64 + 1).is_none());
+
+        // forged
+        let mut forged = ticket.clone();
+        forged[10] ^= 1;
+        assert!(key.open(&forged[], now).is_none());
+
+        let other_key = TicketKey::new(repeat(8).take(32).collect()).unwrap();
+        assert!(other_key.open(&ticket[], now).is_none());
+
+        assert!(TicketKey::new(vec!(1, 2, 3)).is_err());
+    }
+}
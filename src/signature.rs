@@ -1,14 +1,30 @@
 // 7.4.1.4.1 Signature algorithm
 
+use std::rand::Rng;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::{DecryptError, IllegalParameter, BadCertificate, DecodeError,
+                               InternalError, UnsupportedCertificate};
 use tls_item::TlsItem;
+use der::{self, DerReader};
+use crypto::sha1::Sha1;
+use crypto::sha2::{Hash, Sha256, Sha384, Sha512};
+use crypto::rsa::{self, RsaPublicKey, RsaPrivateKey};
+use crypto::p256;
+use crypto::p384;
+use crypto::p256::scalar::Scalar;
+use pem;
 
-tls_enum!(u8, enum HashAlgorithm {
+// RFC 8446 4.2.3: RSA-PSS schemes are (intrinsic, rsa_pss_rsae_*) in TLS 1.2 terms.
+// their hash is determined by the signature algorithm.
+tls_enum!(u8, #[derive(Show)] enum HashAlgorithm {
    none(0), md5(1), sha1(2), sha224(3), sha256(4), sha384(5),
-   sha512(6)
+   sha512(6), intrinsic(8)
 });
 
-tls_enum!(u8, enum SignatureAlgorithm {
-  anonymous(0), rsa(1), dsa(2), ecdsa(3)
+tls_enum!(u8, #[derive(Show)] enum SignatureAlgorithm {
+  anonymous(0), rsa(1), dsa(2), ecdsa(3),
+  rsa_pss_rsae_sha256(4), rsa_pss_rsae_sha384(5), rsa_pss_rsae_sha512(6)
 });
 
 tls_struct!(struct SignatureAndHashAlgorithm {
@@ -17,8 +33,432 @@ tls_struct!(struct SignatureAndHashAlgorithm {
 });
 tls_vec!(SignatureAndHashAlgorithmVec = SignatureAndHashAlgorithm(2, (1 << 16) - 2));
 
+impl Clone for SignatureAndHashAlgorithm {
+    fn clone(&self) -> SignatureAndHashAlgorithm {
+        SignatureAndHashAlgorithm {
+            hash: self.hash,
+            signature: self.signature,
+        }
+    }
+}
+
+impl PartialEq for SignatureAndHashAlgorithm {
+    fn eq(&self, other: &SignatureAndHashAlgorithm) -> bool {
+        self.hash == other.hash && self.signature == other.signature
+    }
+}
+
+// algorithms we can verify, in order of preference.
+pub fn supported_signature_algorithms() -> Vec<SignatureAndHashAlgorithm> {
+    let algorithms = [
+        (HashAlgorithm::sha256, SignatureAlgorithm::ecdsa),
+        (HashAlgorithm::intrinsic, SignatureAlgorithm::rsa_pss_rsae_sha256),
+        (HashAlgorithm::sha256, SignatureAlgorithm::rsa),
+        (HashAlgorithm::sha384, SignatureAlgorithm::ecdsa),
+        (HashAlgorithm::intrinsic, SignatureAlgorithm::rsa_pss_rsae_sha384),
+        (HashAlgorithm::sha384, SignatureAlgorithm::rsa),
+        (HashAlgorithm::sha512, SignatureAlgorithm::ecdsa),
+        (HashAlgorithm::intrinsic, SignatureAlgorithm::rsa_pss_rsae_sha512),
+        (HashAlgorithm::sha512, SignatureAlgorithm::rsa),
+    ];
+    algorithms.iter().map(|&(hash, signature)| {
+        SignatureAndHashAlgorithm {
+            hash: hash,
+            signature: signature,
+        }
+    }).collect()
+}
+
 tls_vec!(Signature = u8(0, (1 << 16) - 1));
 tls_struct!(struct DigitallySigned {
     algorithm: SignatureAndHashAlgorithm,
     signature: Signature
 });
+
+// 1.2.840.113549.1.1.1
+static OID_RSA_ENCRYPTION: &'static [u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+// 1.2.840.10045.2.1
+static OID_EC_PUBLIC_KEY: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+// 1.2.840.10045.3.1.7
+static OID_PRIME256V1: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+// 1.3.132.0.34
+static OID_SECP384R1: &'static [u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+
+pub enum PublicKey {
+    Rsa(RsaPublicKey),
+    EcP256(p256::NPoint256),
+    EcP384(p384::NPoint384),
+}
+
+impl PublicKey {
+    // SubjectPublicKeyInfo ::= SEQUENCE {
+    //     algorithm AlgorithmIdentifier,
+    //     subjectPublicKey BIT STRING
+    // }
+    pub fn from_spki(spki: &[u8]) -> TlsResult<PublicKey> {
+        let mut reader = DerReader::new(spki);
+        let mut spki = try!(reader.read_sequence());
+        let mut algorithm = try!(spki.read_sequence());
+        let oid = try!(algorithm.read_oid());
+        let key = try!(spki.read_bit_string());
+        try!(spki.finish());
+
+        if oid == OID_RSA_ENCRYPTION {
+            try!(algorithm.read_null());
+            try!(algorithm.finish());
+            match RsaPublicKey::from_der(key) {
+                Some(key) => Ok(PublicKey::Rsa(key)),
+                None => tls_err!(BadCertificate, "invalid RSA public key"),
+            }
+        } else if oid == OID_EC_PUBLIC_KEY {
+            // we only accept namedCurve
+            let curve = try!(algorithm.read_oid());
+            try!(algorithm.finish());
+            let key = if curve == OID_PRIME256V1 {
+                p256::NPoint256::from_uncompressed_bytes(key).map(PublicKey::EcP256)
+            } else if curve == OID_SECP384R1 {
+                p384::NPoint384::from_uncompressed_bytes(key).map(PublicKey::EcP384)
+            } else {
+                return tls_err!(UnsupportedCertificate, "unsupported elliptic curve");
+            };
+            match key {
+                Some(key) => Ok(key),
+                None => tls_err!(BadCertificate, "invalid EC public key"),
+            }
+        } else {
+            tls_err!(UnsupportedCertificate, "unsupported public key algorithm")
+        }
+    }
+}
+
+impl PublicKey {
+    pub fn signature_algorithm(&self) -> SignatureAlgorithm {
+        match *self {
+            PublicKey::Rsa(..) => SignatureAlgorithm::rsa,
+            PublicKey::EcP256(..) | PublicKey::EcP384(..) => SignatureAlgorithm::ecdsa,
+        }
+    }
+
+    // verify that `sig` is a valid signature of `data` by `self`.
+    pub fn verify(&self,
+                  algorithm: &SignatureAndHashAlgorithm,
+                  data: &[u8],
+                  sig: &[u8]) -> TlsResult<()> {
+        let hash_algorithm = match algorithm.signature {
+            SignatureAlgorithm::rsa_pss_rsae_sha256 |
+            SignatureAlgorithm::rsa_pss_rsae_sha384 |
+            SignatureAlgorithm::rsa_pss_rsae_sha512 if algorithm.hash !=
+                                                        HashAlgorithm::intrinsic => {
+                return tls_err!(IllegalParameter,
+                                "RSA-PSS with hash algorithm: {:?}",
+                                algorithm.hash);
+            }
+            SignatureAlgorithm::rsa_pss_rsae_sha256 => HashAlgorithm::sha256,
+            SignatureAlgorithm::rsa_pss_rsae_sha384 => HashAlgorithm::sha384,
+            SignatureAlgorithm::rsa_pss_rsae_sha512 => HashAlgorithm::sha512,
+            _ => algorithm.hash,
+        };
+        let verified = match hash_algorithm {
+            HashAlgorithm::sha256 => {
+                self.verify_with::<Sha256>(algorithm.signature, rsa::SHA256_DIGEST_INFO, data, sig)
+            }
+            HashAlgorithm::sha384 => {
+                self.verify_with::<Sha384>(algorithm.signature, rsa::SHA384_DIGEST_INFO, data, sig)
+            }
+            HashAlgorithm::sha512 => {
+                self.verify_with::<Sha512>(algorithm.signature, rsa::SHA512_DIGEST_INFO, data, sig)
+            }
+            hash => return tls_err!(IllegalParameter, "unsupported hash algorithm: {:?}", hash),
+        };
+
+        if !try!(verified) {
+            return tls_err!(DecryptError, "signature verification failed");
+        }
+        Ok(())
+    }
+
+    // `digest_info` is the DigestInfo prefix for `H`, used by PKCS #1 v1.5.
+    fn verify_with<H: Hash>(&self,
+                            signature: SignatureAlgorithm,
+                            digest_info: &[u8],
+                            data: &[u8],
+                            sig: &[u8]) -> TlsResult<bool> {
+        let mut hash: H = Hash::new();
+        hash.update(data);
+        let hash = hash.finalize();
+
+        let verified = match (self, signature) {
+            (&PublicKey::Rsa(ref key), SignatureAlgorithm::rsa) => {
+                key.verify_pkcs1(digest_info, &hash[], sig)
+            }
+            (&PublicKey::Rsa(ref key), SignatureAlgorithm::rsa_pss_rsae_sha256) |
+            (&PublicKey::Rsa(ref key), SignatureAlgorithm::rsa_pss_rsae_sha384) |
+            (&PublicKey::Rsa(ref key), SignatureAlgorithm::rsa_pss_rsae_sha512) => {
+                key.verify_pss::<H>(&hash[], sig)
+            }
+            (&PublicKey::EcP256(ref key), SignatureAlgorithm::ecdsa) => {
+                p256::verify_ecdsa(key, &hash[], sig)
+            }
+            (&PublicKey::EcP384(ref key), SignatureAlgorithm::ecdsa) => {
+                p384::verify_ecdsa(key, &hash[], sig)
+            }
+            (_, signature) => {
+                return tls_err!(IllegalParameter,
+                                "signature algorithm does not match the key: {:?}",
+                                signature);
+            }
+        };
+        Ok(verified)
+    }
+}
+
+// SECRET
+pub enum PrivateKey {
+    Rsa(RsaPrivateKey),
+    EcP256(Scalar),
+}
+
+impl PrivateKey {
+    // PKCS #1 RSAPrivateKey or SEC 1 ECPrivateKey (P-256 only)
+    pub fn from_der(data: &[u8]) -> TlsResult<PrivateKey> {
+        match RsaPrivateKey::from_der(data) {
+            Some(key) => return Ok(PrivateKey::Rsa(key)),
+            None => {}
+        }
+        match read_ec_private_key(data) {
+            Ok(key) => Ok(PrivateKey::EcP256(key)),
+            Err(..) => tls_err!(DecodeError, "unsupported or invalid private key"),
+        }
+    }
+
+    // "RSA PRIVATE KEY" or "EC PRIVATE KEY" block
+    pub fn from_pem(data: &[u8]) -> TlsResult<PrivateKey> {
+        let mut keys = try!(pem::decode(data, "RSA PRIVATE KEY"));
+        keys.push_all(&try!(pem::decode(data, "EC PRIVATE KEY"))[]);
+        if keys.len() != 1 {
+            return tls_err!(DecodeError, "expected exactly one private key, found {}", keys.len());
+        }
+        PrivateKey::from_der(&keys[0][])
+    }
+
+    pub fn signature_algorithm(&self) -> SignatureAlgorithm {
+        match *self {
+            PrivateKey::Rsa(..) => SignatureAlgorithm::rsa,
+            PrivateKey::EcP256(..) => SignatureAlgorithm::ecdsa,
+        }
+    }
+
+    // the algorithm to sign with for a peer accepting `theirs`. None if we have none of them.
+    // SHA-1 is used only if the peer accepts nothing else.
+    pub fn choose_signature_algorithm(&self, theirs: &[SignatureAndHashAlgorithm])
+                                      -> Option<SignatureAndHashAlgorithm> {
+        let hashes = [HashAlgorithm::sha256, HashAlgorithm::sha384, HashAlgorithm::sha512,
+                      HashAlgorithm::sha1];
+        for &hash in hashes.iter() {
+            let algorithm = SignatureAndHashAlgorithm {
+                hash: hash,
+                signature: self.signature_algorithm(),
+            };
+            if theirs.contains(&algorithm) {
+                return Some(algorithm);
+            }
+        }
+        None
+    }
+
+    // sign `data` with `algorithm`, usually chosen by `choose_signature_algorithm`.
+    // `rng` is used by ECDSA.
+    pub fn sign<R: Rng>(&self,
+                        algorithm: &SignatureAndHashAlgorithm,
+                        data: &[u8],
+                        rng: &mut R) -> TlsResult<DigitallySigned> {
+        if algorithm.signature != self.signature_algorithm() {
+            return tls_err!(InternalError,
+                            "signature algorithm does not match the key: {:?}",
+                            algorithm.signature);
+        }
+        let signature = match algorithm.hash {
+            HashAlgorithm::sha1 => self.sign_with::<Sha1, R>(rsa::SHA1_DIGEST_INFO, data, rng),
+            HashAlgorithm::sha256 => {
+                self.sign_with::<Sha256, R>(rsa::SHA256_DIGEST_INFO, data, rng)
+            }
+            HashAlgorithm::sha384 => {
+                self.sign_with::<Sha384, R>(rsa::SHA384_DIGEST_INFO, data, rng)
+            }
+            HashAlgorithm::sha512 => {
+                self.sign_with::<Sha512, R>(rsa::SHA512_DIGEST_INFO, data, rng)
+            }
+            hash => return tls_err!(InternalError, "unsupported hash algorithm: {:?}", hash),
+        };
+
+        Ok(DigitallySigned {
+            algorithm: algorithm.clone(),
+            signature: try!(Signature::new(try!(signature))),
+        })
+    }
+
+    // `digest_info` is the DigestInfo prefix for `H`, used by PKCS #1 v1.5.
+    fn sign_with<H: Hash, R: Rng>(&self,
+                                  digest_info: &[u8],
+                                  data: &[u8],
+                                  rng: &mut R) -> TlsResult<Vec<u8>> {
+        let mut hash: H = Hash::new();
+        hash.update(data);
+        let hash = hash.finalize();
+
+        match *self {
+            PrivateKey::Rsa(ref key) => {
+                match key.sign_pkcs1(digest_info, &hash[]) {
+                    Some(signature) => Ok(signature),
+                    None => tls_err!(InternalError, "RSA key is too short or signing failed"),
+                }
+            }
+            PrivateKey::EcP256(ref key) => Ok(p256::sign_ecdsa(key, &hash[], rng)),
+        }
+    }
+}
+
+// ECPrivateKey ::= SEQUENCE {
+//     version INTEGER { ecPrivkeyVer1(1) },
+//     privateKey OCTET STRING,
+//     parameters [0] ECParameters OPTIONAL,
+//     publicKey [1] BIT STRING OPTIONAL
+// }
+// SECRET
+fn read_ec_private_key(data: &[u8]) -> TlsResult<Scalar> {
+    let mut reader = DerReader::new(data);
+    let mut key = try!(reader.read_sequence());
+    try!(reader.finish());
+
+    if try!(key.read_u32()) != 1 {
+        return tls_err!(DecodeError, "unsupported EC private key version");
+    }
+    let d = try!(key.read_tagged(der::TAG_OCTET_STRING));
+
+    // we can't guess the curve if parameters are absent.
+    let params = match try!(key.read_optional(der::context_explicit(0))) {
+        Some(params) => params,
+        None => return tls_err!(DecodeError, "EC private key without curve"),
+    };
+    let mut params = DerReader::new(params);
+    let curve = try!(params.read_oid());
+    try!(params.finish());
+    if curve != OID_PRIME256V1 {
+        return tls_err!(DecodeError, "unsupported elliptic curve");
+    }
+
+    // the public key is not needed for signing.
+    try!(key.read_optional(der::context_explicit(1)));
+    try!(key.finish());
+
+    if d.len() != 32 {
+        return tls_err!(DecodeError, "invalid EC private key");
+    }
+    match Scalar::from_bytes(d) {
+        Some(ref d) if !d.is_zero() => Ok(*d),
+        _ => tls_err!(DecodeError, "invalid EC private key"),
+    }
+}
+
+impl DigitallySigned {
+    // verify that `self` is a valid signature of `data` by `key`.
+    // the algorithm must be one of `offered`, which we sent to the peer.
+    pub fn verify(&self,
+                  key: &PublicKey,
+                  data: &[u8],
+                  offered: &[SignatureAndHashAlgorithm]) -> TlsResult<()> {
+        if !offered.iter().any(|alg| *alg == self.algorithm) {
+            return tls_err!(IllegalParameter,
+                            "signature algorithm we did not offer: {:?} {:?}",
+                            self.algorithm.hash,
+                            self.algorithm.signature);
+        }
+        key.verify(&self.algorithm, data, &self.signature[])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rand::OsRng;
+
+    use certificate::Certificate;
+    use crypto::p384;
+    use tls_result::TlsErrorKind::IllegalParameter;
+    use super::{PublicKey, PrivateKey, SignatureAlgorithm, SignatureAndHashAlgorithm,
+                HashAlgorithm, supported_signature_algorithms};
+
+    static LEAF_EC: &'static [u8] = include_bytes!("test_data/leaf_ec.der");
+    static LEAF_EC_KEY: &'static [u8] = include_bytes!("test_data/leaf_ec_key.der");
+    static LEAF_RSA: &'static [u8] = include_bytes!("test_data/leaf_rsa.der");
+    static LEAF_RSA_KEY: &'static [u8] = include_bytes!("test_data/leaf_rsa_key.der");
+
+    #[test]
+    fn test_private_key_sign() {
+        let mut rng = OsRng::new().unwrap();
+
+        for &(cert, key, algorithm) in [(LEAF_EC, LEAF_EC_KEY, SignatureAlgorithm::ecdsa),
+                                        (LEAF_RSA, LEAF_RSA_KEY, SignatureAlgorithm::rsa)].iter() {
+            let cert = Certificate::parse(cert).unwrap();
+            let key = PrivateKey::from_der(key).unwrap();
+            assert!(key.signature_algorithm() == algorithm);
+
+            let offered = supported_signature_algorithms();
+            let chosen = key.choose_signature_algorithm(&offered[]).unwrap();
+            assert!(chosen.hash == HashAlgorithm::sha256);
+            let signed = key.sign(&chosen, b"suruga", &mut rng).unwrap();
+            signed.verify(&cert.public_key, b"suruga", &offered[]).unwrap();
+            assert!(signed.verify(&cert.public_key, b"surugA", &offered[]).is_err());
+
+            // a valid signature with an algorithm we did not offer
+            let sha384 = SignatureAndHashAlgorithm {
+                hash: HashAlgorithm::sha384,
+                signature: algorithm,
+            };
+            let err = signed.verify(&cert.public_key, b"suruga", &[sha384.clone()]).err().unwrap();
+            assert_eq!(err.kind, IllegalParameter);
+
+            let signed = key.sign(&sha384, b"suruga", &mut rng).unwrap();
+            signed.verify(&cert.public_key, b"suruga", &offered[]).unwrap();
+
+            // the default of RFC 5246 7.4.1.4.1
+            let sha1 = SignatureAndHashAlgorithm {
+                hash: HashAlgorithm::sha1,
+                signature: algorithm,
+            };
+            let chosen = key.choose_signature_algorithm(&[sha1.clone()]).unwrap();
+            assert!(chosen == sha1);
+            let signed = key.sign(&chosen, b"suruga", &mut rng).unwrap();
+            assert!(signed.algorithm == sha1);
+
+            let pss = SignatureAndHashAlgorithm {
+                hash: HashAlgorithm::intrinsic,
+                signature: SignatureAlgorithm::rsa_pss_rsae_sha256,
+            };
+            assert!(key.choose_signature_algorithm(&[pss.clone()]).is_none());
+            assert!(key.sign(&pss, b"suruga", &mut rng).is_err());
+        }
+
+        assert!(PrivateKey::from_der(LEAF_EC).is_err());
+    }
+
+    #[test]
+    fn test_public_key_p384() {
+        // SubjectPublicKeyInfo header for id-ecPublicKey with secp384r1
+        let mut spki = b"\x30\x76\x30\x10\x06\x07\x2a\x86\x48\xce\x3d\x02\x01\
+                         \x06\x05\x2b\x81\x04\x00\x22\x03\x62\x00".to_vec();
+        let point = p384::G.normalize().to_uncompressed_bytes();
+        spki.push_all(&point[]);
+
+        let key = PublicKey::from_spki(&spki[]).unwrap();
+        assert!(key.signature_algorithm() == SignatureAlgorithm::ecdsa);
+        match key {
+            PublicKey::EcP384(..) => {}
+            _ => panic!("not a P-384 key"),
+        }
+
+        // not on the curve
+        let last = spki.len() - 1;
+        spki[last] ^= 1;
+        assert!(PublicKey::from_spki(&spki[]).is_err());
+    }
+}
@@ -1,10 +1,24 @@
-use std::io::{MemReader, ByRefReader, ByRefWriter};
+use std::io::{MemReader, ByRefReader, ByRefWriter, ChanReader, ChanWriter};
 use std::rand::OsRng;
 use std::iter::repeat;
+use std::sync::mpsc::channel;
+use std::thread::Thread;
+use std::sync::Arc;
 
 use tls::Tls;
-use tls_result::TlsResult;
-use cipher::{Encryptor, Decryptor};
+use tls_result::{TlsResult, TlsErrorKind};
+use client::{TlsClient, ClientConfig};
+use server::{TlsServer, ServerConfig};
+use server_name::ServerName;
+use session::{Session, SessionStore, LruSessionStore, ServerSessionCache, TicketKey};
+use signature::{PrivateKey, SignatureAndHashAlgorithm, HashAlgorithm, SignatureAlgorithm};
+use signature::supported_signature_algorithms;
+use certificate::Certificate;
+use trust_store::TrustStore;
+use handshake::{NamedCurve, Handshake, Random, Extension, ExtensionData};
+use cipher::{Encryptor, Decryptor, CipherSuite, KeyExchange};
+use cipher::ecdhe::EllipticDiffieHellman;
+use cipher::dhe::DiffieHellman;
 use record::Message::{ApplicationDataMessage, ChangeCipherSpecMessage};
 use record::RECORD_MAX_LEN;
 
@@ -24,6 +38,7 @@ impl Decryptor for NullDecryptor {
         Ok(encrypted.to_vec())
     }
     fn mac_len(&self) -> usize { 0 }
+    fn explicit_nonce_len(&self) -> usize { 0 }
 }
 
 fn null_tls<R: Reader, W: Writer>(reader: R, writer: W) -> Tls<R, W> {
@@ -97,3 +112,667 @@ fn test_application_message() {
         }
     }
 }
+
+static ROOT: &'static [u8] = include_bytes!("test_data/root.der");
+static INTER: &'static [u8] = include_bytes!("test_data/inter.der");
+static LEAF_RSA: &'static [u8] = include_bytes!("test_data/leaf_rsa.der");
+static LEAF_RSA_KEY: &'static [u8] = include_bytes!("test_data/leaf_rsa_key.der");
+static LEAF_EC: &'static [u8] = include_bytes!("test_data/leaf_ec.der");
+static LEAF_EC_KEY: &'static [u8] = include_bytes!("test_data/leaf_ec_key.der");
+
+fn client_config() -> ClientConfig {
+    let mut trust_store = TrustStore::new();
+    trust_store.add_der(ROOT).unwrap();
+    ClientConfig::new(trust_store)
+}
+
+fn server_config() -> ServerConfig {
+    let key = PrivateKey::from_der(LEAF_RSA_KEY).unwrap();
+    ServerConfig::new(vec!(LEAF_RSA.to_vec(), INTER.to_vec()), key)
+}
+
+fn ec_server_config() -> ServerConfig {
+    let key = PrivateKey::from_der(LEAF_EC_KEY).unwrap();
+    ServerConfig::new(vec!(LEAF_EC.to_vec(), INTER.to_vec()), key)
+}
+
+// connect a client to an echo server over channels and send "suruga".
+fn echo(client_config: ClientConfig, server_config: ServerConfig) {
+    let (client_tx, server_rx) = channel();
+    let (server_tx, client_rx) = channel();
+
+    let server = Thread::scoped(move || {
+        let reader = ChanReader::new(server_rx);
+        let writer = ChanWriter::new(server_tx);
+        let rng = OsRng::new().unwrap();
+        let mut server = TlsServer::new(reader, writer, rng, server_config).unwrap();
+
+        let msg = server.read_exact(6).unwrap();
+        server.write(&msg[]).unwrap();
+    });
+
+    let reader = ChanReader::new(client_rx);
+    let writer = ChanWriter::new(client_tx);
+    let rng = OsRng::new().unwrap();
+    let server_name = ServerName::new("suruga.example");
+    let mut client = TlsClient::new_with_server_name(reader, writer, rng, client_config,
+                                                     server_name).unwrap();
+
+    client.write(b"suruga").unwrap();
+    let msg = client.read_exact(6).unwrap();
+    assert_eq!(msg, b"suruga".to_vec());
+
+    server.join().ok().expect("server failed");
+}
+
+#[test]
+fn test_client_server() {
+    echo(client_config(), server_config());
+}
+
+#[test]
+fn test_client_certificate() {
+    let mut client_config = client_config();
+    // the server accepts both, so the first one is used.
+    let ec_key = PrivateKey::from_der(LEAF_EC_KEY).unwrap();
+    client_config.add_client_certificate(vec!(LEAF_EC.to_vec(), INTER.to_vec()), ec_key);
+    let rsa_key = PrivateKey::from_der(LEAF_RSA_KEY).unwrap();
+    client_config.add_client_certificate(vec!(LEAF_RSA.to_vec(), INTER.to_vec()), rsa_key);
+
+    let mut server_config = server_config();
+    server_config.client_trust_store = Some(client_config.trust_store.clone());
+
+    echo(client_config, server_config);
+}
+
+#[test]
+fn test_client_certificate_missing() {
+    let mut server_config = server_config();
+    server_config.client_trust_store = Some(client_config().trust_store);
+
+    let (client_tx, server_rx) = channel();
+    let (server_tx, client_rx) = channel();
+
+    let server = Thread::scoped(move || {
+        let reader = ChanReader::new(server_rx);
+        let writer = ChanWriter::new(server_tx);
+        let rng = OsRng::new().unwrap();
+        TlsServer::new(reader, writer, rng, server_config).is_err()
+    });
+
+    let reader = ChanReader::new(client_rx);
+    let writer = ChanWriter::new(client_tx);
+    let rng = OsRng::new().unwrap();
+    assert!(TlsClient::new(reader, writer, rng, client_config()).is_err());
+
+    assert!(server.join().ok().expect("server panicked"));
+}
+
+fn cached_session(config: &ClientConfig) -> Session {
+    let name = ServerName::new("suruga.example");
+    config.session_store.as_ref().unwrap().get(&name).unwrap()
+}
+
+#[test]
+fn test_session_resumption() {
+    let mut client_config = client_config();
+    client_config.set_session_store(LruSessionStore::new(10));
+    let mut server_config = server_config();
+    server_config.session_cache = Some(Arc::new(ServerSessionCache::new(10)));
+
+    echo(client_config.clone(), server_config.clone());
+    let session = cached_session(&client_config);
+
+    // the server would issue a new session id for a full handshake.
+    echo(client_config.clone(), server_config.clone());
+    let resumed = cached_session(&client_config);
+    assert_eq!(resumed.session_id, session.session_id);
+    assert_eq!(resumed.master_secret, session.master_secret);
+    assert_eq!(resumed.issued, session.issued);
+
+    // the server forgot the session. fall back to the full handshake.
+    server_config.session_cache = Some(Arc::new(ServerSessionCache::new(10)));
+    echo(client_config.clone(), server_config);
+    let renewed = cached_session(&client_config);
+    assert!(renewed.session_id != session.session_id);
+}
+
+#[test]
+fn test_session_resumption_sha384() {
+    // the abbreviated handshake must use the PRF hash of the resumed cipher suite.
+    let mut client_config = client_config();
+    client_config.set_session_store(LruSessionStore::new(10));
+    client_config.cipher_suites = vec!(CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384);
+    let mut server_config = server_config();
+    server_config.session_cache = Some(Arc::new(ServerSessionCache::new(10)));
+
+    echo(client_config.clone(), server_config.clone());
+    let session = cached_session(&client_config);
+    assert_eq!(session.cipher_suite, CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384);
+
+    echo(client_config.clone(), server_config);
+    let resumed = cached_session(&client_config);
+    assert_eq!(resumed.session_id, session.session_id);
+}
+
+#[test]
+fn test_session_ticket() {
+    let mut client_config = client_config();
+    client_config.set_session_store(LruSessionStore::new(10));
+    let mut server_config = server_config();
+    let key = TicketKey::new(repeat(7).take(32).collect()).unwrap();
+    server_config.ticket_key = Some(Arc::new(key));
+
+    echo(client_config.clone(), server_config.clone());
+    let session = cached_session(&client_config);
+    assert!(session.ticket.is_some());
+    assert!(session.session_id.is_empty());
+
+    // the ticket is renewed on resumption.
+    echo(client_config.clone(), server_config.clone());
+    let resumed = cached_session(&client_config);
+    assert_eq!(resumed.master_secret, session.master_secret);
+    assert!(resumed.ticket.is_some() && resumed.ticket != session.ticket);
+    assert_eq!(resumed.issued, session.issued);
+
+    // the server can't decrypt the ticket. fall back to the full handshake.
+    let key = TicketKey::new(repeat(8).take(32).collect()).unwrap();
+    server_config.ticket_key = Some(Arc::new(key));
+    echo(client_config.clone(), server_config);
+    let renewed = cached_session(&client_config);
+    assert!(renewed.master_secret != session.master_secret);
+    assert!(renewed.ticket.is_some());
+}
+
+#[test]
+fn test_extended_master_secret() {
+    let mut client_config = client_config();
+    client_config.set_session_store(LruSessionStore::new(10));
+    client_config.require_extended_master_secret = true;
+    let mut server_config = server_config();
+    server_config.session_cache = Some(Arc::new(ServerSessionCache::new(10)));
+
+    echo(client_config.clone(), server_config.clone());
+    let session = cached_session(&client_config);
+    assert!(session.extended_master_secret);
+
+    echo(client_config.clone(), server_config.clone());
+    assert_eq!(cached_session(&client_config).session_id, session.session_id);
+
+    // a session without extended master secret is not resumed by the server.
+    let mut legacy = session.clone();
+    legacy.extended_master_secret = false;
+    server_config.session_cache.as_ref().unwrap().put(legacy.clone());
+    let name = ServerName::new("suruga.example");
+    client_config.session_store.as_ref().unwrap().put(&name, legacy);
+    client_config.require_extended_master_secret = false;
+    echo(client_config.clone(), server_config);
+    let renewed = cached_session(&client_config);
+    assert!(renewed.session_id != session.session_id);
+    assert!(renewed.extended_master_secret);
+
+    // the server ignores the extension.
+    let mut data = Vec::new();
+    {
+        let mut reader = MemReader::new(Vec::new());
+        let mut tls = Tls::new(reader.by_ref(), data.by_ref(), OsRng::new().unwrap());
+        let random = Random::new(repeat(0u8).take(32).collect()).unwrap();
+        let suite = CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256;
+        let server_hello = Handshake::new_server_hello(random, Vec::new(), suite, Vec::new());
+        tls.writer.write_handshake(&server_hello.unwrap()).unwrap();
+    }
+    client_config.require_extended_master_secret = true;
+    let rng = OsRng::new().unwrap();
+    let client = TlsClient::new(MemReader::new(data), Vec::new(), rng, client_config);
+    assert_eq!(client.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+}
+
+// send a ClientHello with `extensions` to the server and return the handshake messages of
+// its first flight. the server fails when it runs out of input.
+fn server_flight(server_config: ServerConfig, extensions: Vec<Extension>) -> Vec<Handshake> {
+    let mut hello = Vec::new();
+    {
+        let mut reader = MemReader::new(Vec::new());
+        let mut tls = Tls::new(reader.by_ref(), hello.by_ref(), OsRng::new().unwrap());
+        let random = Random::new(repeat(0u8).take(32).collect()).unwrap();
+        let suites = vec!(CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256);
+        let client_hello = Handshake::new_client_hello(random, Vec::new(), suites, extensions);
+        tls.writer.write_handshake(&client_hello.unwrap()).unwrap();
+    }
+
+    let mut reply = Vec::new();
+    {
+        let rng = OsRng::new().unwrap();
+        let server = TlsServer::new(MemReader::new(hello), reply.by_ref(), rng, server_config);
+        assert_eq!(server.err().unwrap().kind, TlsErrorKind::IoFailure);
+    }
+
+    let mut tls = Tls::new(MemReader::new(reply), Vec::new(), OsRng::new().unwrap());
+    let mut messages = Vec::new();
+    loop {
+        match tls.reader.read_handshake() {
+            Ok(Handshake::server_hello_done(..)) => return messages,
+            Ok(message) => messages.push(message),
+            Err(err) => panic!("{:?}", err),
+        }
+    }
+}
+
+#[test]
+fn test_unknown_extension() {
+    // RFC 7685 padding, which we don't know.
+    let curves = Extension::new_elliptic_curve_list(vec!(NamedCurve::secp256r1)).unwrap();
+    let padding = ExtensionData::new(repeat(0u8).take(16).collect()).unwrap();
+    let extensions = vec!(curves, Extension::UnknownExtension(21, padding));
+    let messages = server_flight(server_config(), extensions);
+    match messages[0] {
+        Handshake::server_hello(..) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_certificate_request_algorithms() {
+    let mut server_config = server_config();
+    server_config.client_trust_store = Some(client_config().trust_store);
+    let curves = Extension::new_elliptic_curve_list(vec!(NamedCurve::secp256r1)).unwrap();
+    let messages = server_flight(server_config, vec!(curves));
+    let request = messages.iter().filter_map(|message| match *message {
+        Handshake::certificate_request(ref request) => Some(request),
+        _ => None,
+    }).next().unwrap();
+
+    // the server accepts every CertificateVerify the client can make.
+    for algorithm in supported_signature_algorithms().iter() {
+        assert!(request.supported_signature_algorithms.contains(algorithm));
+    }
+}
+
+// like `echo`, but the server requests renegotiation before echoing.
+// return whether the client renegotiated.
+fn echo_renegotiation(client_config: ClientConfig) -> bool {
+    let (client_tx, server_rx) = channel();
+    let (server_tx, client_rx) = channel();
+
+    let server = Thread::scoped(move || {
+        let reader = ChanReader::new(server_rx);
+        let writer = ChanWriter::new(server_tx);
+        let rng = OsRng::new().unwrap();
+        let mut server = TlsServer::new(reader, writer, rng, server_config()).unwrap();
+
+        let msg = server.read_exact(6).unwrap();
+        let renegotiated = server.renegotiate().unwrap();
+        server.write(&msg[]).unwrap();
+        renegotiated
+    });
+
+    let reader = ChanReader::new(client_rx);
+    let writer = ChanWriter::new(client_tx);
+    let rng = OsRng::new().unwrap();
+    let mut client = TlsClient::new(reader, writer, rng, client_config).unwrap();
+
+    client.write(b"suruga").unwrap();
+    let msg = client.read_exact(6).unwrap();
+    assert_eq!(msg, b"suruga".to_vec());
+
+    server.join().ok().expect("server failed")
+}
+
+#[test]
+fn test_renegotiation() {
+    let mut client_config = client_config();
+    client_config.allow_renegotiation = true;
+    assert!(echo_renegotiation(client_config));
+
+    // refused by default
+    assert!(!echo_renegotiation(client_config()));
+}
+
+// run the handshake only and return the ALPN protocols selected by each side.
+fn connect(client_config: ClientConfig, server_config: ServerConfig)
+        -> (TlsResult<Option<Vec<u8>>>, TlsResult<Option<Vec<u8>>>) {
+    let (client_tx, server_rx) = channel();
+    let (server_tx, client_rx) = channel();
+
+    let server = Thread::scoped(move || {
+        let reader = ChanReader::new(server_rx);
+        let writer = ChanWriter::new(server_tx);
+        let rng = OsRng::new().unwrap();
+        let server = TlsServer::new(reader, writer, rng, server_config);
+        server.map(|server| server.alpn_protocol().map(|protocol| protocol.to_vec()))
+    });
+
+    let reader = ChanReader::new(client_rx);
+    let writer = ChanWriter::new(client_tx);
+    let rng = OsRng::new().unwrap();
+    let client = TlsClient::new(reader, writer, rng, client_config);
+    let client = client.map(|client| client.alpn_protocol().map(|protocol| protocol.to_vec()));
+
+    (client, server.join().ok().expect("server panicked"))
+}
+
+#[test]
+fn test_alpn() {
+    let mut offer = client_config();
+    offer.alpn_protocols = vec!(b"h2".to_vec(), b"http/1.1".to_vec());
+    let mut accept = server_config();
+    accept.alpn_protocols = vec!(b"spdy/3".to_vec(), b"http/1.1".to_vec());
+
+    let (client, server) = connect(offer.clone(), accept.clone());
+    assert_eq!(client.unwrap(), Some(b"http/1.1".to_vec()));
+    assert_eq!(server.unwrap(), Some(b"http/1.1".to_vec()));
+
+    // the client does not use ALPN
+    let (client, server) = connect(client_config(), accept.clone());
+    assert_eq!(client.unwrap(), None);
+    assert_eq!(server.unwrap(), None);
+
+    accept.alpn_protocols = vec!(b"spdy/3".to_vec());
+    let (client, server) = connect(offer, accept);
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::NoApplicationProtocol);
+}
+
+#[test]
+fn test_no_shared_cipher_suite() {
+    let mut server_config = server_config();
+    server_config.cipher_suites = Vec::new();
+    let (client, server) = connect(client_config(), server_config);
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+
+    let mut client_config = client_config();
+    client_config.cipher_suites = Vec::new();
+    assert!(TlsClient::new(MemReader::new(Vec::new()), Vec::new(), OsRng::new().unwrap(),
+                           client_config).is_err());
+}
+
+#[test]
+fn test_chacha20_poly1305() {
+    let suites = [CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256_IETF,
+                  CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256];
+    for &suite in suites.iter() {
+        let mut config = client_config();
+        config.cipher_suites = vec!(suite);
+        echo(config, server_config());
+    }
+
+    let suites = [CipherSuite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256_IETF,
+                  CipherSuite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256];
+    for &suite in suites.iter() {
+        let mut config = client_config();
+        config.cipher_suites = vec!(suite);
+        echo(config, ec_server_config());
+    }
+}
+
+#[test]
+fn test_aes_gcm() {
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256);
+    echo(config, server_config());
+
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384);
+    echo(config, server_config());
+
+    // an ECDSA server only picks ECDSA suites.
+    echo(client_config(), ec_server_config());
+
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256);
+    echo(config, ec_server_config());
+
+    let mut server_config = ec_server_config();
+    server_config.cipher_suites = vec!(CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256);
+    let (client, server) = connect(client_config(), server_config);
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+}
+
+#[test]
+fn test_elliptic_curves() {
+    let mut config = client_config();
+    config.elliptic_curves = vec!(NamedCurve::x25519);
+    echo(config, server_config());
+
+    let mut config = client_config();
+    config.elliptic_curves = vec!(NamedCurve::secp256r1);
+    echo(config, ec_server_config());
+
+    let mut config = client_config();
+    config.elliptic_curves = vec!(NamedCurve::secp384r1);
+    echo(config, server_config());
+
+    let mut config = client_config();
+    config.elliptic_curves = vec!(NamedCurve::x25519);
+    let mut server_config = server_config();
+    server_config.elliptic_curves = vec!(NamedCurve::secp256r1);
+    let (client, server) = connect(config, server_config);
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+}
+
+#[test]
+fn test_ecdhe_x25519() {
+    let mut rng = OsRng::new().unwrap();
+    let private_key = PrivateKey::from_der(LEAF_EC_KEY).unwrap();
+    let public_key = Certificate::parse(LEAF_EC).unwrap().public_key;
+    let cli_random = [1u8; 32];
+    let serv_random = [2u8; 32];
+
+    let algorithms = supported_signature_algorithms();
+    let algorithm = private_key.choose_signature_algorithm(&algorithms[]).unwrap();
+
+    let kex = EllipticDiffieHellman;
+    let (data, secret) = kex.server_key_exchange(&cli_random, &serv_random,
+                                                 Some((&private_key, &algorithm)),
+                                                 Some(NamedCurve::x25519), &[],
+                                                 &mut rng).unwrap();
+
+    let (client_data, client_secret) = kex.compute_keys(&data[], &cli_random, &serv_random,
+                                                        Some(&public_key), &[NamedCurve::x25519],
+                                                        &algorithms[], None, &mut rng).unwrap();
+    let server_secret = kex.compute_server_keys(&client_data[], Some(NamedCurve::x25519),
+                                                &secret[], None).unwrap();
+    assert_eq!(client_secret, server_secret);
+
+    // the server chose a curve we did not offer
+    let err = kex.compute_keys(&data[], &cli_random, &serv_random, Some(&public_key),
+                               &[NamedCurve::secp256r1], &algorithms[], None,
+                               &mut rng).err().unwrap();
+    assert_eq!(err.kind, TlsErrorKind::IllegalParameter);
+
+    // a small-order public key gives the all-zero secret
+    let mut zero = vec!(32u8);
+    zero.push_all(&[0u8; 32]);
+    let err = kex.compute_server_keys(&zero[], Some(NamedCurve::x25519), &secret[], None);
+    let err = err.err().unwrap();
+    assert_eq!(err.kind, TlsErrorKind::IllegalParameter);
+}
+
+#[test]
+fn test_dhe() {
+    let suites = [CipherSuite::TLS_DHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+                  CipherSuite::TLS_DHE_RSA_WITH_AES_128_GCM_SHA256,
+                  CipherSuite::TLS_DHE_RSA_WITH_AES_256_GCM_SHA384];
+    for &suite in suites.iter() {
+        let mut config = client_config();
+        config.cipher_suites = vec!(suite);
+        echo(config, server_config());
+    }
+
+    // a legacy client without ffdhe groups
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_DHE_RSA_WITH_AES_128_GCM_SHA256);
+    config.elliptic_curves = vec!(NamedCurve::secp256r1);
+    echo(config, server_config());
+
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_DHE_RSA_WITH_AES_128_GCM_SHA256);
+    config.elliptic_curves = vec!(NamedCurve::ffdhe3072);
+    echo(config, server_config());
+
+    // the client offers only groups the server does not have
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_DHE_RSA_WITH_AES_128_GCM_SHA256);
+    config.elliptic_curves = vec!(NamedCurve::ffdhe4096);
+    let (client, server) = connect(config, server_config());
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+}
+
+#[test]
+fn test_dhe_key_exchange() {
+    let mut rng = OsRng::new().unwrap();
+    let private_key = PrivateKey::from_der(LEAF_RSA_KEY).unwrap();
+    let public_key = Certificate::parse(LEAF_RSA).unwrap().public_key;
+    let cli_random = [1u8; 32];
+    let serv_random = [2u8; 32];
+    let algorithms = supported_signature_algorithms();
+    let algorithm = private_key.choose_signature_algorithm(&algorithms[]).unwrap();
+    let server_key = Some((&private_key, &algorithm));
+
+    let kex = DiffieHellman;
+    for &group in [NamedCurve::ffdhe2048, NamedCurve::ffdhe3072].iter() {
+        let (data, secret) = kex.server_key_exchange(&cli_random, &serv_random, server_key,
+                                                     Some(group), &[], &mut rng).unwrap();
+        let (client_data, client_secret) = kex.compute_keys(&data[], &cli_random, &serv_random,
+                                                            Some(&public_key), &[group],
+                                                            &algorithms[], None,
+                                                            &mut rng).unwrap();
+        let server_secret = kex.compute_server_keys(&client_data[], Some(group),
+                                                    &secret[], None).unwrap();
+        assert_eq!(client_secret, server_secret);
+        // leading zeros are stripped
+        assert!(client_secret[0] != 0);
+
+        // the signature covers the randoms
+        assert!(kex.compute_keys(&data[], &serv_random, &cli_random, Some(&public_key),
+                                 &[group], &algorithms[], None, &mut rng).is_err());
+
+        // the server signed with an algorithm we did not offer
+        let others: Vec<SignatureAndHashAlgorithm> =
+            algorithms.iter().filter(|a| **a != algorithm).map(|a| a.clone()).collect();
+        let err = kex.compute_keys(&data[], &cli_random, &serv_random, Some(&public_key),
+                                   &[group], &others[], None, &mut rng).err().unwrap();
+        assert_eq!(err.kind, TlsErrorKind::IllegalParameter);
+    }
+
+    // dh_Yc = 1
+    let (_, secret) = kex.server_key_exchange(&cli_random, &serv_random, server_key,
+                                              Some(NamedCurve::ffdhe2048), &[],
+                                              &mut rng).unwrap();
+    let err = kex.compute_server_keys(&[0, 1, 1], Some(NamedCurve::ffdhe2048), &secret[], None);
+    assert_eq!(err.err().unwrap().kind, TlsErrorKind::IllegalParameter);
+}
+
+fn psk_configs() -> (ClientConfig, ServerConfig) {
+    let mut client_config = client_config();
+    client_config.set_psk_callback(|hint: &[u8]| {
+        assert_eq!(hint, b"suruga hint");
+        Some((b"device-1".to_vec(), b"0123456789abcdef".to_vec()))
+    });
+    let mut server_config = server_config();
+    server_config.psk_identity_hint = b"suruga hint".to_vec();
+    server_config.set_psk_callback(|identity: &[u8]| {
+        if identity == b"device-1" {
+            Some(b"0123456789abcdef".to_vec())
+        } else {
+            None
+        }
+    });
+    (client_config, server_config)
+}
+
+#[test]
+fn test_psk() {
+    let suites = [CipherSuite::TLS_ECDHE_PSK_WITH_CHACHA20_POLY1305_SHA256,
+                  CipherSuite::TLS_PSK_WITH_AES_128_GCM_SHA256];
+    for &suite in suites.iter() {
+        let (mut config, server_config) = psk_configs();
+        config.cipher_suites = vec!(suite);
+        echo(config, server_config);
+
+        // no hint: plain PSK omits ServerKeyExchange
+        let (mut config, mut server_config) = psk_configs();
+        config.cipher_suites = vec!(suite);
+        config.set_psk_callback(|hint: &[u8]| {
+            assert_eq!(hint, b"");
+            Some((b"device-1".to_vec(), b"0123456789abcdef".to_vec()))
+        });
+        server_config.psk_identity_hint = Vec::new();
+        echo(config, server_config);
+
+        // wrong key
+        let (mut config, server_config) = psk_configs();
+        config.cipher_suites = vec!(suite);
+        config.set_psk_callback(|_: &[u8]| {
+            Some((b"device-1".to_vec(), b"fedcba9876543210".to_vec()))
+        });
+        let (client, server) = connect(config, server_config);
+        assert!(client.is_err());
+        assert!(server.is_err());
+
+        // unknown identity
+        let (mut config, server_config) = psk_configs();
+        config.cipher_suites = vec!(suite);
+        config.set_psk_callback(|_: &[u8]| {
+            Some((b"device-2".to_vec(), b"0123456789abcdef".to_vec()))
+        });
+        let (client, server) = connect(config, server_config);
+        assert!(client.is_err());
+        assert_eq!(server.err().unwrap().kind, TlsErrorKind::DecryptError);
+    }
+
+    // PSK suites are not offered without the callback
+    let mut config = client_config();
+    config.cipher_suites = vec!(CipherSuite::TLS_PSK_WITH_AES_128_GCM_SHA256);
+    assert!(TlsClient::new(MemReader::new(Vec::new()), Vec::new(), OsRng::new().unwrap(),
+                           config).is_err());
+
+    // nor accepted
+    let (mut config, _) = psk_configs();
+    config.cipher_suites = vec!(CipherSuite::TLS_PSK_WITH_AES_128_GCM_SHA256);
+    let (client, server) = connect(config, server_config());
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+}
+
+#[test]
+fn test_signature_algorithms() {
+    // the server signs with an algorithm the client offered.
+    let mut config = client_config();
+    config.signature_algorithms = vec!(SignatureAndHashAlgorithm {
+        hash: HashAlgorithm::sha384,
+        signature: SignatureAlgorithm::rsa,
+    });
+    echo(config, server_config());
+
+    // the server cannot sign RSA-PSS.
+    let mut config = client_config();
+    config.signature_algorithms = vec!(SignatureAndHashAlgorithm {
+        hash: HashAlgorithm::intrinsic,
+        signature: SignatureAlgorithm::rsa_pss_rsae_sha256,
+    });
+    let (client, server) = connect(config, server_config());
+    assert!(client.is_err());
+    assert_eq!(server.err().unwrap().kind, TlsErrorKind::HandshakeFailure);
+
+    // suites of the other key type are not affected
+    let mut config = client_config();
+    config.signature_algorithms = vec!(SignatureAndHashAlgorithm {
+        hash: HashAlgorithm::sha256,
+        signature: SignatureAlgorithm::ecdsa,
+    });
+    echo(config.clone(), ec_server_config());
+    let (client, server) = connect(config, server_config());
+    assert!(client.is_err());
+    assert!(server.is_err());
+
+    let mut config = client_config();
+    config.signature_algorithms = Vec::new();
+    let (client, server) = connect(config, server_config());
+    assert_eq!(client.err().unwrap().kind, TlsErrorKind::InternalError);
+    assert!(server.is_err());
+}
@@ -1,7 +1,11 @@
 use std::rand::OsRng;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 use tls_result::{TlsResult, TlsError, TlsErrorKind};
 use record::{RecordWriter, RecordReader};
+use handshake::HandshakeTranscript;
+use signature::HashAlgorithm;
 use alert::{self, Alert};
 
 pub static TLS_VERSION: (u8, u8) = (3, 3);
@@ -10,19 +14,33 @@ pub struct Tls<R: Reader, W: Writer> {
     pub writer: RecordWriter<W>,
     pub reader: RecordReader<R>,
     pub rng: OsRng,
+    // fed by both the reader and the writer.
+    transcript: Rc<RefCell<HandshakeTranscript>>,
 }
 
 impl<R: Reader, W: Writer> Tls<R, W> {
     pub fn new(reader: R, writer: W, rng: OsRng) -> Tls<R, W> {
-        let writer = RecordWriter::new(writer);
-        let reader = RecordReader::new(reader);
+        let transcript = Rc::new(RefCell::new(HandshakeTranscript::new()));
+        let writer = RecordWriter::new(writer, transcript.clone());
+        let reader = RecordReader::new(reader, transcript.clone());
         Tls {
             writer: writer,
             reader: reader,
             rng: rng,
+            transcript: transcript,
         }
     }
 
+    // the handshake messages so far, e.g. to be signed by CertificateVerify.
+    pub fn handshake_messages(&self) -> Vec<u8> {
+        self.transcript.borrow().messages().to_vec()
+    }
+
+    // hash of the handshake messages so far.
+    pub fn handshake_hash(&self, hash: HashAlgorithm) -> Vec<u8> {
+        self.transcript.borrow().hash(hash)
+    }
+
     pub fn close(&mut self) -> TlsResult<()> {
         let alert_data = alert::Alert {
             level: alert::AlertLevel::fatal,
@@ -32,6 +50,14 @@ impl<R: Reader, W: Writer> Tls<R, W> {
         Ok(())
     }
 
+    // RFC 5746: decline a renegotiation request. the connection stays open.
+    pub fn refuse_renegotiation(&mut self) -> TlsResult<()> {
+        let alert_data = try!(alert::Alert::new(alert::AlertLevel::warning,
+                                                alert::AlertDescription::no_renegotiation));
+        try!(self.writer.write_alert(&alert_data));
+        Ok(())
+    }
+
     // send fatal alert and return error
     // (it may be different to `err`, because writing alert can fail)
     pub fn send_tls_alert(&mut self, err: TlsError) -> TlsError {
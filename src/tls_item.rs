@@ -130,6 +130,9 @@ macro_rules! tls_enum {
 //         }
 //     }
 // } Struct;
+//
+// an optional `Unknown(Body)` after the enum keeps unknown types as `Unknown(type, body)`
+// instead of failing. `Body` must be able to read any of them.
 macro_rules! tls_enum_struct {
     (
         $repr_ty:ident,
@@ -139,6 +142,9 @@ macro_rules! tls_enum_struct {
                 $name:ident($body_ty:ident) = $num:tt // $num: integer literal
             ),+
         }
+        $(
+            $unknown:ident($unknown_ty:ident)
+        )*
     ) => (
         #[allow(non_camel_case_types)]
         $(#[$a])*
@@ -146,6 +152,9 @@ macro_rules! tls_enum_struct {
             $(
                 $name($body_ty),
             )+
+            $(
+                $unknown($repr_ty, $unknown_ty),
+            )*
         }
 
         impl TlsItem for $enum_name {
@@ -157,6 +166,12 @@ macro_rules! tls_enum_struct {
                             try!(body.tls_write(writer));
                         }
                     )+
+                    $(
+                        $enum_name::$unknown(num, ref body) => {
+                            stry_write_num!($repr_ty, writer, num);
+                            try!(body.tls_write(writer));
+                        }
+                    )*
                 }
                 Ok(())
             }
@@ -170,8 +185,7 @@ macro_rules! tls_enum_struct {
                             Ok($enum_name::$name(body))
                         }
                     )+
-                    _ => return tls_err!(::tls_result::TlsErrorKind::DecodeError,
-                                         "unexpected value: {}", num),
+                    _ => tls_enum_struct_unknown!(reader, num, $enum_name $(, $unknown)*),
                 }
             }
 
@@ -181,6 +195,9 @@ macro_rules! tls_enum_struct {
                     $(
                         $enum_name::$name(ref body) => body.tls_size(),
                     )+
+                    $(
+                        $enum_name::$unknown(_, ref body) => body.tls_size(),
+                    )*
                 };
                 prefix_size + body_size
             }
@@ -188,6 +205,17 @@ macro_rules! tls_enum_struct {
     )
 }
 
+// the fallback of `tls_enum_struct` for unknown types.
+macro_rules! tls_enum_struct_unknown {
+    ($reader:ident, $num:ident, $enum_name:ident) => (
+        tls_err!(::tls_result::TlsErrorKind::DecodeError, "unexpected value: {}", $num)
+    );
+    ($reader:ident, $num:ident, $enum_name:ident, $unknown:ident) => ({
+        let body = try!(TlsItem::tls_read($reader));
+        Ok($enum_name::$unknown($num, body))
+    });
+}
+
 // fixed-sized u8/opaque array
 macro_rules! tls_array {
     ($name:ident = [u8, ..$n:expr]) => (
@@ -12,6 +12,14 @@ pub enum TlsErrorKind {
     DecodeError,
     DecryptError,
     InternalError,
+    HandshakeFailure,
+    BadCertificate,
+    UnsupportedCertificate,
+    CertificateExpired,
+    UnknownCa,
+    InsufficientSecurity,
+    NoApplicationProtocol,
+    UnsupportedExtension,
 
     // we probably can't even send alert?
     IoFailure,
@@ -43,8 +51,14 @@ impl Error for TlsError {
             TlsErrorKind::DecodeError => "cannot decode message",
             TlsErrorKind::DecryptError => "failed to verify signature/message",
             TlsErrorKind::InternalError => "internal error",
-
-            // UnsupportedExtension,
+            TlsErrorKind::HandshakeFailure => "no acceptable set of security parameters",
+            TlsErrorKind::BadCertificate => "bad certificate",
+            TlsErrorKind::UnsupportedCertificate => "unsupported certificate",
+            TlsErrorKind::CertificateExpired => "certificate expired or not yet valid",
+            TlsErrorKind::UnknownCa => "unknown certificate authority",
+            TlsErrorKind::InsufficientSecurity => "peer's parameters are too weak",
+            TlsErrorKind::NoApplicationProtocol => "no shared application protocol",
+            TlsErrorKind::UnsupportedExtension => "unsupported extension",
 
             // we probably can't even send alert?
             TlsErrorKind::IoFailure => "i/o error",
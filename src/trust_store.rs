@@ -0,0 +1,290 @@
+// certificate path validation (RFC 5280, section 6)
+// this is a simplified version: certificate policies, name constraints and
+// revocation are not supported.
+
+use std::iter::repeat;
+
+use tls_result::TlsResult;
+use tls_result::TlsErrorKind::{DecodeError, DecryptError, BadCertificate, UnsupportedCertificate,
+                               CertificateExpired, UnknownCa};
+use certificate::{Certificate, OID_ANY_EXT_KEY_USAGE, KEY_USAGE_DIGITAL_SIGNATURE,
+                  KEY_USAGE_KEY_CERT_SIGN};
+use pem;
+
+// we don't follow paths longer than this (including the end-entity certificate).
+const MAX_PATH_LEN: usize = 10;
+
+// set of trust anchors.
+// anchors are trusted as-is: their validity and extensions are not checked.
+pub struct TrustStore {
+    anchors: Vec<Certificate>,
+}
+
+impl TrustStore {
+    pub fn new() -> TrustStore {
+        TrustStore {
+            anchors: Vec::new(),
+        }
+    }
+
+    pub fn add_der(&mut self, data: &[u8]) -> TlsResult<()> {
+        let cert = try!(Certificate::parse(data));
+        self.anchors.push(cert);
+        Ok(())
+    }
+
+    // add every "CERTIFICATE" block of `data` and return the number of added anchors.
+    // system bundles often contain keys we can't use (e.g. other curves or DSA), so such
+    // certificates are skipped. a malformed certificate fails the whole call.
+    pub fn add_pem(&mut self, data: &[u8]) -> TlsResult<usize> {
+        let certs = try!(pem::decode(data, "CERTIFICATE"));
+        if certs.len() == 0 {
+            return tls_err!(DecodeError, "no certificate found in PEM data");
+        }
+        let mut added = 0us;
+        for (i, cert) in certs.iter().enumerate() {
+            match self.add_der(&cert[]) {
+                Ok(()) => added += 1,
+                Err(ref err) if err.kind == UnsupportedCertificate => {}
+                Err(err) => {
+                    return tls_err!(DecodeError, "invalid certificate #{} in PEM data: {}",
+                                    i, err.desc);
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn len(&self) -> usize {
+        self.anchors.len()
+    }
+
+    // verify that `chain[0]`, the end-entity certificate, is issued by a trust anchor
+    // via intermediate certificates in `chain[1..]`.
+    // the intermediates may be in any order, and unused ones are ignored.
+    // `ext_key_usage` is the extended key usage OID required for `chain[0]`.
+    // `now` is seconds since 1970-01-01T00:00:00Z.
+    pub fn verify_chain(&self,
+                        chain: &[Certificate],
+                        ext_key_usage: &[u8],
+                        now: i64) -> TlsResult<()> {
+        let leaf = match chain.get(0) {
+            Some(leaf) => leaf,
+            None => return tls_err!(BadCertificate, "empty certificate chain"),
+        };
+        try!(check_certificate(leaf, now));
+        try!(check_end_entity(leaf, ext_key_usage));
+
+        let intermediates = &chain[1..];
+        let mut used: Vec<bool> = repeat(false).take(intermediates.len()).collect();
+        self.find_path(leaf, intermediates, &mut used, 0, now)
+    }
+
+    // `cert` is already checked. find its issuer, then the issuer's issuer, ...
+    // `depth` is the number of intermediate certificates below `cert`.
+    fn find_path(&self,
+                 cert: &Certificate,
+                 intermediates: &[Certificate],
+                 used: &mut Vec<bool>,
+                 depth: usize,
+                 now: i64) -> TlsResult<()> {
+        let mut last_err = None;
+
+        for anchor in self.anchors.iter() {
+            if anchor.subject != cert.issuer {
+                continue;
+            }
+            match verify_signature(cert, anchor) {
+                Ok(()) => return Ok(()),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        if depth + 2 <= MAX_PATH_LEN {
+            for (i, issuer) in intermediates.iter().enumerate() {
+                if used[i] || issuer.subject != cert.issuer {
+                    continue;
+                }
+
+                let result = match check_issuer(cert, issuer, depth, now) {
+                    Ok(()) => {
+                        used[i] = true;
+                        let result = self.find_path(issuer, intermediates, used, depth + 1, now);
+                        used[i] = false;
+                        result
+                    }
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Ok(()) => return Ok(()),
+                    Err(err) => last_err = Some(err),
+                }
+            }
+        }
+
+        match last_err {
+            Some(err) => Err(err),
+            None => tls_err!(UnknownCa, "no trusted issuer found"),
+        }
+    }
+}
+
+// checks common to every certificate except trust anchors
+fn check_certificate(cert: &Certificate, now: i64) -> TlsResult<()> {
+    if now < cert.validity.not_before || now > cert.validity.not_after {
+        return tls_err!(CertificateExpired, "certificate is expired or not yet valid");
+    }
+    if cert.extensions.unknown_critical.len() > 0 {
+        return tls_err!(UnsupportedCertificate, "unknown critical extension");
+    }
+    Ok(())
+}
+
+fn check_end_entity(cert: &Certificate, ext_key_usage: &[u8]) -> TlsResult<()> {
+    // the key is used to sign handshake messages
+    match cert.extensions.key_usage {
+        Some(usage) if usage & KEY_USAGE_DIGITAL_SIGNATURE == 0 => {
+            return tls_err!(BadCertificate, "key usage does not permit digitalSignature");
+        }
+        _ => {}
+    }
+
+    match cert.extensions.ext_key_usage {
+        Some(ref usages) => {
+            let permitted = usages.iter().any(|oid| {
+                &oid[] == ext_key_usage || &oid[] == OID_ANY_EXT_KEY_USAGE
+            });
+            if !permitted {
+                return tls_err!(BadCertificate, "extended key usage does not match");
+            }
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+// check that `issuer`, an intermediate certificate with `depth` intermediates below,
+// may issue `cert`.
+fn check_issuer(cert: &Certificate,
+                issuer: &Certificate,
+                depth: usize,
+                now: i64) -> TlsResult<()> {
+    try!(check_certificate(issuer, now));
+
+    match issuer.extensions.basic_constraints {
+        Some(ref bc) if bc.ca => {
+            match bc.path_len {
+                Some(path_len) if (path_len as usize) < depth => {
+                    return tls_err!(BadCertificate, "path length constraint violated");
+                }
+                _ => {}
+            }
+        }
+        _ => return tls_err!(BadCertificate, "issuer is not a CA"),
+    }
+
+    match issuer.extensions.key_usage {
+        Some(usage) if usage & KEY_USAGE_KEY_CERT_SIGN == 0 => {
+            return tls_err!(BadCertificate, "key usage does not permit keyCertSign");
+        }
+        _ => {}
+    }
+
+    verify_signature(cert, issuer)
+}
+
+fn verify_signature(cert: &Certificate, issuer: &Certificate) -> TlsResult<()> {
+    let algorithm = match cert.signature_algorithm {
+        Some(ref algorithm) => algorithm,
+        None => return tls_err!(UnsupportedCertificate, "unsupported signature algorithm"),
+    };
+
+    match issuer.public_key.verify(algorithm, &cert.tbs_certificate[], &cert.signature[]) {
+        Ok(()) => Ok(()),
+        Err(ref err) if err.kind == DecryptError => {
+            tls_err!(BadCertificate, "bad certificate signature")
+        }
+        Err(err) => tls_err!(UnsupportedCertificate, "{}", err.desc),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tls_result::TlsErrorKind;
+    use tls_result::TlsErrorKind::{DecodeError, BadCertificate, CertificateExpired, UnknownCa};
+    use certificate::{Certificate, OID_SERVER_AUTH};
+    use super::TrustStore;
+
+    static ROOT: &'static [u8] = include_bytes!("test_data/root.der");
+    static ROOT_PEM: &'static [u8] = include_bytes!("test_data/root.pem");
+    // a P-192 certificate followed by root.pem
+    static BUNDLE_PEM: &'static [u8] = include_bytes!("test_data/bundle.pem");
+    static INTER: &'static [u8] = include_bytes!("test_data/inter.der");
+    static LEAF_EC: &'static [u8] = include_bytes!("test_data/leaf_ec.der");
+    static LEAF_RSA: &'static [u8] = include_bytes!("test_data/leaf_rsa.der");
+
+    // 2017-07-14T02:40:00Z
+    const NOW: i64 = 1500000000;
+
+    fn chain(certs: &[&[u8]]) -> Vec<Certificate> {
+        certs.iter().map(|cert| Certificate::parse(*cert).unwrap()).collect()
+    }
+
+    fn store() -> TrustStore {
+        let mut store = TrustStore::new();
+        store.add_der(ROOT).unwrap();
+        store
+    }
+
+    fn verify_err(store: &TrustStore, chain: &[Certificate], now: i64) -> TlsErrorKind {
+        store.verify_chain(chain, OID_SERVER_AUTH, now).err().unwrap().kind
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let store = store();
+        store.verify_chain(&chain(&[LEAF_EC, INTER])[], OID_SERVER_AUTH, NOW).unwrap();
+        store.verify_chain(&chain(&[LEAF_RSA, INTER])[], OID_SERVER_AUTH, NOW).unwrap();
+        // unrelated certificates are ignored
+        store.verify_chain(&chain(&[LEAF_EC, LEAF_RSA, ROOT, INTER])[], OID_SERVER_AUTH, NOW)
+             .unwrap();
+
+        let mut pem_store = TrustStore::new();
+        assert_eq!(pem_store.add_pem(ROOT_PEM).unwrap(), 1);
+        assert_eq!(pem_store.len(), 1);
+        pem_store.verify_chain(&chain(&[LEAF_EC, INTER])[], OID_SERVER_AUTH, NOW).unwrap();
+    }
+
+    #[test]
+    fn test_add_pem() {
+        // the unsupported curve is skipped.
+        let mut store = TrustStore::new();
+        assert_eq!(store.add_pem(BUNDLE_PEM).unwrap(), 1);
+        assert_eq!(store.len(), 1);
+
+        let mut store = TrustStore::new();
+        let broken = b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n";
+        assert_eq!(store.add_pem(broken).err().unwrap().kind, DecodeError);
+        assert_eq!(store.add_pem(b"no certificate").err().unwrap().kind, DecodeError);
+    }
+
+    #[test]
+    fn test_verify_chain_failure() {
+        let store = store();
+
+        assert_eq!(verify_err(&TrustStore::new(), &chain(&[LEAF_EC, INTER])[], NOW), UnknownCa);
+        assert_eq!(verify_err(&store, &chain(&[LEAF_EC])[], NOW), UnknownCa);
+        assert_eq!(verify_err(&store, &[], NOW), BadCertificate);
+
+        // 2046-01-01T00:00:00Z
+        assert_eq!(verify_err(&store, &chain(&[LEAF_EC, INTER])[], 2398377600), CertificateExpired);
+
+        let mut broken = chain(&[LEAF_EC, INTER]);
+        broken[0].signature[10] ^= 1;
+        assert_eq!(verify_err(&store, &broken[], NOW), BadCertificate);
+
+        // the intermediate CA is not allowed to sign handshake messages
+        assert_eq!(verify_err(&store, &chain(&[INTER])[], NOW), BadCertificate);
+    }
+}